@@ -1,6 +1,9 @@
 use crate::dnspod::api_client::Client;
-use crate::err;
+use crate::{err, kerr};
+use crate::provider::AUTH_FAILURE;
 use crate::error::ItdResult;
+use crate::ipaddr::bind::Bind;
+use crate::provider::parse_bind;
 use crate::utils::decrypt_to_str;
 use serde::Deserialize;
 use tracing::info;
@@ -10,6 +13,8 @@ pub struct PodAction {
     //appid: i32,
     secret_id: String,
     secret_key: String,
+    /// 请求的出口绑定
+    bind: Bind,
     // 暂存domainlist缓存
     domain_list: Vec<DomainItem>,
 }
@@ -17,9 +22,20 @@ pub struct PodAction {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ResponseError {
-    //pub code: String,
+    #[serde(default)]
+    pub code: String,
     pub message: String,
 }
+
+impl ResponseError {
+    /// 密钥错误(AuthFailure.*)单独标记，便于通知
+    pub fn into_err<T>(self) -> ItdResult<T> {
+        if self.code.starts_with("AuthFailure") {
+            return kerr!(AUTH_FAILURE, self.message);
+        }
+        err!(self.message)
+    }
+}
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "PascalCase")]
 pub struct DomainItem {
@@ -37,7 +53,8 @@ pub struct DomainListResponse {
 #[serde(rename_all = "PascalCase")]
 pub struct RecordItem {
     pub record_id: i32,
-    //pub line: String,
+    #[serde(default)]
+    pub line: String,
     //pub line_id: String,
 
     pub name: String,
@@ -68,8 +85,8 @@ pub struct Response<T> {
 impl PodAction {
     /// 创建一个PodAction实例
     pub async fn new<'db>(db: &'db sqlx::Pool<sqlx::Sqlite>, appid: i32) -> ItdResult<Self> {
-        let result = sqlx::query_as::<_, (String, String)>(
-            "select secret_id,secret_key from user_apps where id = ?",
+        let result = sqlx::query_as::<_, (String, String, Option<String>)>(
+            "select secret_id,secret_key,bind from user_apps where id = ?",
         )
         .bind(appid)
         .fetch_optional(db)
@@ -77,16 +94,22 @@ impl PodAction {
         if result.is_none() {
             return err!("appid not found");
         }
-        let (secret_id, secret_key) = result.unwrap();
+        let (secret_id, secret_key, bind) = result.unwrap();
         let secret_id = decrypt_to_str(&secret_id)?;
         let secret_key = decrypt_to_str(&secret_key)?;
         Ok(PodAction {
            
             secret_id: secret_id,
             secret_key: secret_key,
+            bind: parse_bind(bind)?,
             domain_list: vec![],
         })
     }
+    fn client(&self) -> Client {
+        let mut client = Client::new(self.secret_id.clone(), self.secret_key.clone());
+        client.bind = self.bind.clone();
+        client
+    }
     /// 添加域名
     /// #Args
     /// * host   如果为空，则默认为@
@@ -104,6 +127,7 @@ impl PodAction {
         record_type: &str,
         ip: &str,
         ttl: i32,
+        line: Option<&str>,
     ) -> ItdResult<i32> {
         /*
         let mut find_result: Option<(String, String)> = None;
@@ -128,15 +152,19 @@ impl PodAction {
         let resords = self.find_records(domain).await?;
         for record in resords {
             //已经存在需要更新域名,注意其它的记录类型会被替换为A类型
-            if record.name == host && (record.r#type == "A" || record.r#type == "AAAA") {
-                self.modify_record(host, domain, record.record_id, record_type, ip, ttl)
+            // 多线路解析时同一主机名有多条记录，按线路区分
+            if record.name == host
+                && (record.r#type == "A" || record.r#type == "AAAA")
+                && record.line == line.unwrap_or("默认")
+            {
+                self.modify_record(host, domain, record.record_id, record_type, ip, ttl, None, line)
                     .await?;
                 return Ok(record.record_id);
             }
         }
         // 添加记录
         let result = self
-            .create_record(&host, domain, record_type, ip, ttl)
+            .create_record(&host, domain, record_type, ip, ttl, line)
             .await?;
         Ok(result)
     }
@@ -148,6 +176,7 @@ impl PodAction {
     /// * `record_type` - 记录类型
     /// * `ip` - ip地址
     /// * `ttl` - 有效期
+    /// * `line` - 解析线路，为空时使用默认线路
     pub async fn create_record(
         &self,
         hostname: &str,
@@ -155,19 +184,20 @@ impl PodAction {
         record_type: &str,
         ip: &str,
         ttl: i32,
+        line: Option<&str>,
     ) -> ItdResult<i32> {
-        let client = Client::new(self.secret_id.clone(), self.secret_key.clone());
+        let client = self.client();
 
         let body = format!(
             r#"{{"Domain":"{}","SubDomain":"{}","RecordType":"{}","RecordLine":"{}","Value":"{}","TTL":{},"Status":"ENABLE"}}"#,
-            domain, hostname, record_type, "默认", ip, ttl
+            domain, hostname, record_type, line.unwrap_or("默认"), ip, ttl
         );
         info!("create_record ===> {}", body);
         let res = client
             .do_request::<Response<AddRecordResponse>>("POST", "CreateRecord", "", &body)
             .await?;
         if let Some(error) = res.response.error {
-            return err!(error.message);
+            return error.into_err();
         }
         Ok(res.response.record_id.unwrap())
         // todo!()
@@ -179,7 +209,10 @@ impl PodAction {
     /// * `record_type` - 记录类型
     /// * `ip` - 记录值
     /// * `ttl` - 有效期
+    /// * `weight` - 权重，为空时不修改
+    /// * `line` - 解析线路，为空时使用默认线路
     ///
+    #[allow(clippy::too_many_arguments)]
     pub async fn modify_record(
         &self,
         host: &str,
@@ -188,19 +221,22 @@ impl PodAction {
         record_type: &str,
         ip: &str,
         ttl: i32,
+        weight: Option<i32>,
+        line: Option<&str>,
     ) -> ItdResult<i32> {
-        let client = Client::new(self.secret_id.clone(), self.secret_key.clone());
+        let client = self.client();
 
+        let weight = weight.map(|weight| format!(r#","Weight":{}"#, weight)).unwrap_or_default();
         let body = format!(
-            r#"{{"Domain":"{}","RecordId":{},"RecordType":"{}","RecordLine":"{}","Value":"{}","TTL":{},"Status":"ENABLE","SubDomain":"{}"}}"#,
-            domain, record_id, record_type, "默认", ip, ttl, host
+            r#"{{"Domain":"{}","RecordId":{},"RecordType":"{}","RecordLine":"{}","Value":"{}","TTL":{}{},"Status":"ENABLE","SubDomain":"{}"}}"#,
+            domain, record_id, record_type, line.unwrap_or("默认"), ip, ttl, weight, host
         );
         info!("modify_record ===> {}", body);
         let res = client
             .do_request::<Response<AddRecordResponse>>("POST", "ModifyRecord", "", &body)
             .await?;
         if let Some(error) = res.response.error {
-            return err!(error.message);
+            return error.into_err();
         }
         Ok(res.response.record_id.unwrap())
     }
@@ -210,7 +246,7 @@ impl PodAction {
     /// * `record_id` - 主机记录ID，通过 查询获取
     ///
     pub async fn delete_record(&self, domain: &str, record_id: i32) -> ItdResult<()> {
-        let client = Client::new(self.secret_id.clone(), self.secret_key.clone());
+        let client = self.client();
 
         let body = format!(r#"{{"Domain":"{}","RecordId":{}}}"#, domain, record_id);
         info!("delete_record ===> {}", body);
@@ -218,7 +254,7 @@ impl PodAction {
             .do_request::<Response<AddRecordResponse>>("POST", "DeleteRecord", "", &body)
             .await?;
         if let Some(error) = res.response.error {
-            return err!(error.message);
+            return error.into_err();
         }
         Ok(())
     }
@@ -227,7 +263,7 @@ impl PodAction {
     /// * domain: 域名 全域名形式带主机名 如 host.example.com
     pub async fn find_records(&self, domain: &str) -> ItdResult<Vec<RecordItem>> {
         // get domain host
-        let client = Client::new(self.secret_id.clone(), self.secret_key.clone());
+        let client = self.client();
         let body = format!(r#"{{"Domain":"{}"}}"#, domain);
         info!("find records ===> {}", body);
         let res = client
@@ -278,14 +314,14 @@ impl PodAction {
         if self.domain_list.len() > 0 {
             return Ok(self.domain_list.clone());
         }
-        let client = Client::new(self.secret_id.clone(), self.secret_key.clone());
+        let client = self.client();
         let res = client
             .do_request::<Response<DomainListResponse>>("GET", "DescribeDomainList", "", "")
             .await?;
         //println!("===>{:?}", res);
         //let response = res.get("Response").unwrap();
         if let Some(error) = res.response.error {
-            return err!(error.message);
+            return error.into_err();
         }
         let domain_list = res.response.domain_list;
         //self.domain_list = domain_list.clone();
@@ -5,6 +5,8 @@ use hex;
 //use time::OffsetDateTime;
 //use time::macros::format_description;
 use crate::error::{ItdError, ItdResult};
+use crate::ipaddr::bind::Bind;
+use crate::provider::http_client;
 use serde::de::DeserializeOwned;
 use hmac::{Hmac, Mac};
 
@@ -14,6 +16,8 @@ pub struct Client {
     pub secret_key: String,
     pub today: String,
     pub timestamp: String,
+    /// 请求的出口绑定
+    pub bind: Bind,
 }
 impl Client {
     pub fn new(secret_id: String, secret_key: String) -> Client {
@@ -27,6 +31,7 @@ impl Client {
             secret_key: secret_key,
             today,
             timestamp,
+            bind: Bind::default(),
         }
     }
     /// 发送请求
@@ -38,7 +43,7 @@ impl Client {
     /// # Returns
     /// 请求结果
     pub async fn do_request<U: DeserializeOwned>(&self, method:&str, action:&str, query:&str, body:&str) -> ItdResult<U> {
-        let client = reqwest::Client::new();
+        let client = http_client(&self.bind, std::time::Duration::from_secs(30))?;
         let full_url = 
         if !query.is_empty() {
             "https://dnspod.tencentcloudapi.com/".to_string() + "?" + query
@@ -16,6 +16,9 @@ impl ItdError {
     pub fn new(kind: String, message: String) -> ItdError {
         ItdError(kind, message)
     }
+    pub fn kind(&self) -> &str {
+        &self.0
+    }
 }
 impl fmt::Display for ItdError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -73,6 +76,26 @@ impl From<std::net::AddrParseError> for ItdError {
         ItdError::new("AddrParse".to_string(), err.to_string())
     }
 }
+impl From<getrandom::Error> for ItdError {
+    fn from(err: getrandom::Error) -> Self {
+        ItdError::new("Random".to_string(), err.to_string())
+    }
+}
+impl From<lettre::transport::smtp::Error> for ItdError {
+    fn from(err: lettre::transport::smtp::Error) -> Self {
+        ItdError::new("Smtp".to_string(), err.to_string())
+    }
+}
+impl From<lettre::error::Error> for ItdError {
+    fn from(err: lettre::error::Error) -> Self {
+        ItdError::new("Email".to_string(), err.to_string())
+    }
+}
+impl From<lettre::address::AddressError> for ItdError {
+    fn from(err: lettre::address::AddressError) -> Self {
+        ItdError::new("EmailAddress".to_string(), err.to_string())
+    }
+}
 impl From<validator::ValidationErrors> for ItdError {
     fn from(err: validator::ValidationErrors) -> Self {
         ItdError::new("RequestValidation".to_string(), err.to_string())
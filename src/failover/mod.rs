@@ -0,0 +1,331 @@
+//! 基于健康探测的故障切换
+//! 后台定时探测主值，连续失败达到阈值后把记录切换到备用值，主值恢复后切回，
+//! 每次切换都记录在 failover_switch 中
+use crate::error::ItdResult;
+use crate::ipaddr::guard::publish_denied;
+use crate::ipaddr::source::SourceStates;
+use crate::model::failover::{FailoverGroup, FailoverModel, ACTIVE_BACKUP, ACTIVE_PRIMARY, PROBE_HTTP};
+use crate::model::ip_state::DEFAULT_SOURCE;
+use crate::model::records::Records;
+use crate::queue::limiter::RateLimiter;
+use crate::queue::sync_record;
+use crate::{err, IpState};
+use chrono::Local;
+use sqlx::SqlitePool;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::RwLock;
+use tokio::time::timeout;
+use tracing::{error, info, warn};
+
+/// 探测主值是否可用
+pub async fn probe(group: &FailoverGroup, value: &str) -> ItdResult<()> {
+    let ip: IpAddr = value.parse()?;
+    let addr = SocketAddr::new(ip, group.probe_port as u16);
+    let wait = Duration::from_secs(group.timeout as u64);
+    if group.probe_type == PROBE_HTTP {
+        let client = reqwest::Client::builder().no_proxy().timeout(wait).build()?;
+        let path = group.probe_path.clone().unwrap_or("/".to_string());
+        let resp = client.get(format!("http://{}{}", addr, path)).send().await?;
+        if resp.status().is_server_error() {
+            return err!(format!("http status code: {}", resp.status()));
+        }
+        return Ok(());
+    }
+    match timeout(wait, TcpStream::connect(addr)).await {
+        Ok(result) => {
+            result?;
+            Ok(())
+        }
+        Err(_) => err!(format!("连接 {} 超时", addr)),
+    }
+}
+
+/// 取固定值或IP来源当前的地址
+async fn resolve_value(
+    value: &Option<String>,
+    source: &Option<String>,
+    ip_type: &str,
+    ip_state: &Arc<RwLock<IpState>>,
+    source_states: &SourceStates,
+) -> Option<String> {
+    if value.is_some() {
+        return value.clone();
+    }
+    let state = match source.as_deref() {
+        None => return None,
+        Some(DEFAULT_SOURCE) => ip_state.clone(),
+        Some(name) => source_states.read().await.get(name)?.clone(),
+    };
+    let state = state.read().await;
+    match ip_type {
+        "AAAA" => state.ipv6.clone(),
+        _ => state.ipv4.clone(),
+    }
+}
+
+/// 探测一个切换组，需要时切换记录的值
+pub async fn run_group(
+    db: &SqlitePool,
+    group: &FailoverGroup,
+    ip_state: &Arc<RwLock<IpState>>,
+    source_states: &SourceStates,
+    limiter: &Arc<RateLimiter>,
+) -> ItdResult<()> {
+    let failover_model = FailoverModel::new(db);
+    let record = match Records::new(db).get_record(group.domain_id).await? {
+        Some(record) => record,
+        None => return err!("Record not found"),
+    };
+    if let Some(reason) = record.skip_reason() {
+        return Records::new(db).set_skipped(record.id as i64, &reason).await;
+    }
+    let primary = resolve_value(&group.primary_value, &group.primary_source, &record.ip_type, ip_state, source_states).await;
+    let backup = resolve_value(&group.backup_value, &group.backup_source, &record.ip_type, ip_state, source_states).await;
+    // 主值来源还没检测到IP时状态未知，不计入失败，等检测完成后再探测
+    let primary = match primary {
+        Some(primary) => primary,
+        None => {
+            info!("Failover group {} skipped: primary source has no IP yet", group.id);
+            return Ok(());
+        }
+    };
+    let result = probe(group, &primary).await;
+    let (mut fail_count, mut success_count) = (group.fail_count, group.success_count);
+    let reason = match &result {
+        Ok(_) => {
+            fail_count = 0;
+            success_count += 1;
+            format!("primary recovered after {} successful probes", success_count)
+        }
+        Err(e) => {
+            fail_count += 1;
+            success_count = 0;
+            format!("primary failed {} probes: {}", fail_count, e)
+        }
+    };
+    let active = if group.active == ACTIVE_PRIMARY && fail_count >= group.fail_threshold {
+        ACTIVE_BACKUP
+    } else if group.active == ACTIVE_BACKUP && success_count >= group.recover_threshold {
+        ACTIVE_PRIMARY
+    } else {
+        group.active.as_str()
+    };
+    let desired = if active == ACTIVE_PRIMARY { Some(&primary) } else { backup.as_ref() };
+    let mut published = true;
+    if let Some(desired) = desired.filter(|desired| **desired != record.ip) {
+        // 私有、CGNAT等保留地址默认不发布，记录显式放行时除外
+        if let Some(reason) = publish_denied(desired, record.allow_reserved) {
+            warn!("Skip record {}.{}: {}", record.host, record.domain, reason);
+            Records::new(db).set_skipped(record.id as i64, &reason).await?;
+            published = false;
+        } else {
+            info!("Failover {}.{}: {} -> {} ({})", record.host, record.domain, record.ip, desired, active);
+            // 和同步队列一样发布，镜像目标和发布验证随之更新
+            match sync_record(db, limiter, &record, desired).await {
+                Ok(_) => {
+                    let reason = if active == group.active { "value changed" } else { reason.as_str() };
+                    failover_model.add_switch(group.id, &record.ip, desired, active, reason).await?;
+                }
+                Err(e) => {
+                    published = false;
+                    error!("Failover {}.{} publish failed: {}", record.host, record.domain, e);
+                }
+            }
+        }
+    }
+    // 发布失败时保持原状态，下次探测重试
+    let active = if published { active } else { group.active.as_str() };
+    failover_model.set_probe_state(group.id, active, fail_count, success_count).await?;
+    Ok(())
+}
+
+/// 探测所有到期的切换组
+pub async fn task(
+    db: &SqlitePool,
+    ip_state: &Arc<RwLock<IpState>>,
+    source_states: &SourceStates,
+    limiter: &Arc<RateLimiter>,
+) -> ItdResult<()> {
+    let now = Local::now().naive_local();
+    for group in FailoverModel::new(db).list().await? {
+        let due = match group.last_probe_at {
+            Some(last_probe_at) => (now - last_probe_at).num_seconds() >= group.interval,
+            None => true,
+        };
+        if !due {
+            continue;
+        }
+        if let Err(e) = run_group(db, &group, ip_state, source_states, limiter).await {
+            error!("Failover group {} failed: {}", group.id, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::failover::{FailoverForm, PROBE_TCP};
+    use crate::model::targets::{Targets, SYNC_SYNCED};
+    use crate::verify::{set_verify_config, VerifyConfig};
+    use axum::{
+        routing::{get, put},
+        Router,
+    };
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test]
+    async fn it_failover_works() -> ItdResult<()> {
+        let healthy = Arc::new(AtomicBool::new(true));
+        let health = healthy.clone();
+        let app = Router::new()
+            .route("/update/:id", put(|| async { "ok" }))
+            .route(
+                "/health",
+                get(move || async move {
+                    if health.load(Ordering::SeqCst) {
+                        (http::StatusCode::OK, "ok")
+                    } else {
+                        (http::StatusCode::SERVICE_UNAVAILABLE, "down")
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        let appid = crate::tests::insert_custom_app(&db, &format!("http://{}/update/{{record_id}}", addr)).await?;
+        sqlx::query(
+            r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id)
+               VALUES (1,?,'www','example.com','A','127.0.0.1',1)"#,
+        )
+        .bind(appid)
+        .execute(&db)
+        .await?;
+        // 镜像目标随切换一起更新
+        sqlx::query(r#"INSERT INTO record_target (domain_id,appid,record_id) VALUES (1,?,'2')"#)
+            .bind(appid)
+            .execute(&db)
+            .await?;
+        set_verify_config(&db, &VerifyConfig { enabled: false, ..Default::default() }).await?;
+        let failover_model = FailoverModel::new(&db);
+        let form = FailoverForm {
+            primary_value: Some("127.0.0.1".to_string()),
+            primary_source: None,
+            backup_value: Some("127.0.0.2".to_string()),
+            backup_source: None,
+            probe_type: PROBE_HTTP.to_string(),
+            probe_port: addr.port() as i64,
+            probe_path: Some("/health".to_string()),
+            interval: None,
+            timeout: Some(1),
+            fail_threshold: Some(2),
+            recover_threshold: Some(1),
+        };
+        let id = failover_model.add(1, form).await?;
+        let ip_state = Arc::new(RwLock::new(IpState::default()));
+        let source_states = SourceStates::default();
+        let run = || async {
+            let group = failover_model.get(id).await?.unwrap();
+            run_group(&db, &group, &ip_state, &source_states, &Arc::default()).await?;
+            let record = Records::new(&db).get_record(1).await?.unwrap();
+            ItdResult::Ok(record.ip)
+        };
+        assert_eq!(run().await?, "127.0.0.1");
+        healthy.store(false, Ordering::SeqCst);
+        // 未达到失败阈值
+        assert_eq!(run().await?, "127.0.0.1");
+        // 备用值是保留地址，记录没有放行时不切换
+        assert_eq!(run().await?, "127.0.0.1");
+        let record = Records::new(&db).get_record(1).await?.unwrap();
+        assert!(record.skip_reason.is_some());
+        assert_eq!(failover_model.get(id).await?.unwrap().active, ACTIVE_PRIMARY);
+        sqlx::query(r#"UPDATE user_domain SET allow_reserved = 1 WHERE id = 1"#).execute(&db).await?;
+        assert_eq!(run().await?, "127.0.0.2");
+        let targets = Targets::new(&db).list(1).await?;
+        assert_eq!(targets[0].sync_status, SYNC_SYNCED);
+        let record = Records::new(&db).get_record(1).await?.unwrap();
+        assert!(record.last_synced_at.is_some());
+        healthy.store(true, Ordering::SeqCst);
+        assert_eq!(run().await?, "127.0.0.1");
+        let switches = failover_model.switches(id).await?;
+        assert_eq!(switches.len(), 2);
+        assert_eq!(switches[0].active, ACTIVE_PRIMARY);
+        assert_eq!(switches[1].active, ACTIVE_BACKUP);
+        assert_eq!(switches[1].to_value, "127.0.0.2");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_undetected_primary_does_not_fail_over() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        sqlx::query(r#"INSERT INTO user_domain (id,appid,host,domain,ip,record_id) VALUES (1,1,'www','example.com','',1)"#)
+            .execute(&db)
+            .await?;
+        sqlx::query(r#"INSERT INTO user_apps (id,uid,title,secret_id,secret_key) VALUES (1,1,'app','id','key')"#)
+            .execute(&db)
+            .await?;
+        let form = FailoverForm {
+            primary_value: None,
+            primary_source: Some("wan2".to_string()),
+            backup_value: Some("127.0.0.2".to_string()),
+            backup_source: None,
+            probe_type: PROBE_TCP.to_string(),
+            probe_port: 1,
+            probe_path: None,
+            interval: None,
+            timeout: Some(1),
+            fail_threshold: Some(1),
+            recover_threshold: None,
+        };
+        let failover_model = FailoverModel::new(&db);
+        let id = failover_model.add(1, form).await?;
+        let ip_state = Arc::new(RwLock::new(IpState::default()));
+        let source_states = SourceStates::default();
+        for _ in 0..2 {
+            let group = failover_model.get(id).await?.unwrap();
+            run_group(&db, &group, &ip_state, &source_states, &Arc::default()).await?;
+        }
+        let group = failover_model.get(id).await?.unwrap();
+        assert_eq!(group.active, ACTIVE_PRIMARY);
+        assert_eq!(group.fail_count, 0);
+        assert!(failover_model.switches(id).await?.is_empty());
+        assert_eq!(Records::new(&db).get_record(1).await?.unwrap().ip, "");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_tcp_probe_works() -> ItdResult<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let db = crate::tests::memory_db().await;
+        sqlx::query(r#"INSERT INTO user_domain (id,appid,host,domain,ip,record_id) VALUES (1,1,'www','example.com','',1)"#)
+            .execute(&db)
+            .await?;
+        let form = FailoverForm {
+            primary_value: Some("127.0.0.1".to_string()),
+            primary_source: None,
+            backup_value: Some("127.0.0.2".to_string()),
+            backup_source: None,
+            probe_type: PROBE_TCP.to_string(),
+            probe_port: port as i64,
+            probe_path: None,
+            interval: None,
+            timeout: Some(1),
+            fail_threshold: None,
+            recover_threshold: None,
+        };
+        let failover_model = FailoverModel::new(&db);
+        let id = failover_model.add(1, form).await?;
+        let group = failover_model.get(id).await?.unwrap();
+        assert!(probe(&group, "127.0.0.1").await.is_ok());
+        drop(listener);
+        assert!(probe(&group, "127.0.0.1").await.is_err());
+        Ok(())
+    }
+}
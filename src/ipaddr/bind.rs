@@ -0,0 +1,158 @@
+//! 出口绑定
+//! 多WAN环境下检测到的公网IP取决于请求从哪条线路发出，
+//! 绑定本地地址或网卡(SO_BINDTODEVICE，仅Linux)后可分别跟踪每条线路
+use crate::error::ItdResult;
+use crate::ipaddr::IpType;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Bind {
+    /// 本地出口地址，需与检测的IP类型一致
+    #[serde(default)]
+    pub local_address: Option<IpAddr>,
+    /// 出口网卡，如 eth1 pppoe-wan2
+    #[serde(default)]
+    pub interface: Option<String>,
+}
+
+impl Bind {
+    pub fn is_empty(&self) -> bool {
+        self.local_address.is_none() && self.interface.is_none()
+    }
+    /// 创建绑定出口的 HTTP 客户端
+    pub fn client_builder(&self) -> ItdResult<reqwest::ClientBuilder> {
+        let mut builder = reqwest::Client::builder().no_proxy();
+        if let Some(local_address) = self.local_address {
+            builder = builder.local_address(local_address);
+        }
+        if let Some(interface) = &self.interface {
+            builder = bind_interface(builder, interface)?;
+        }
+        Ok(builder)
+    }
+    fn std_udp_socket(&self, ip_type: &IpType) -> ItdResult<std::net::UdpSocket> {
+        let local_addr: SocketAddr = match (self.local_address, ip_type) {
+            (Some(ip), _) => (ip, 0).into(),
+            (None, IpType::V4) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            (None, IpType::V6) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = std::net::UdpSocket::bind(local_addr)?;
+        if let Some(interface) = &self.interface {
+            bind_device(&socket, interface)?;
+        }
+        Ok(socket)
+    }
+    /// 创建绑定出口的UDP套接字
+    pub async fn udp_socket(&self, ip_type: &IpType) -> ItdResult<UdpSocket> {
+        let socket = self.std_udp_socket(ip_type)?;
+        socket.set_nonblocking(true)?;
+        Ok(UdpSocket::from_std(socket)?)
+    }
+    /// 从该出口能否到达目标地址，UDP connect 只查询路由表，不发送数据
+    pub fn reachable(&self, target: SocketAddr) -> bool {
+        let ip_type = match target {
+            SocketAddr::V4(_) => IpType::V4,
+            SocketAddr::V6(_) => IpType::V6,
+        };
+        match self.std_udp_socket(&ip_type) {
+            Ok(socket) => socket.connect(target).is_ok(),
+            Err(_) => false,
+        }
+    }
+    /// 是否有该类型的公网路由，仅IPv4或仅IPv6的网络中另一类型不可达
+    pub fn has_route(&self, ip_type: &IpType) -> bool {
+        let target: SocketAddr = match ip_type {
+            IpType::V4 => (Ipv4Addr::new(1, 1, 1, 1), 53).into(),
+            IpType::V6 => (Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111), 53).into(),
+        };
+        self.reachable(target)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn bind_interface(builder: reqwest::ClientBuilder, interface: &str) -> ItdResult<reqwest::ClientBuilder> {
+    Ok(builder.interface(interface))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_interface(_builder: reqwest::ClientBuilder, _interface: &str) -> ItdResult<reqwest::ClientBuilder> {
+    crate::err!("绑定网卡仅支持Linux")
+}
+
+#[cfg(target_os = "linux")]
+fn bind_device(socket: &std::net::UdpSocket, interface: &str) -> ItdResult<()> {
+    use std::os::fd::AsRawFd;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            interface.as_ptr() as *const libc::c_void,
+            interface.len() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_device(_socket: &std::net::UdpSocket, _interface: &str) -> ItdResult<()> {
+    crate::err!("绑定网卡仅支持Linux")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::ConnectInfo, routing::get, Router};
+
+    /// 回显请求方地址的HTTP服务
+    async fn echo_server() -> ItdResult<SocketAddr> {
+        let app = Router::new().route(
+            "/",
+            get(|ConnectInfo(addr): ConnectInfo<SocketAddr>| async move { addr.ip().to_string() }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .unwrap();
+        });
+        Ok(addr)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn it_bind_local_address_works() -> ItdResult<()> {
+        let addr = echo_server().await?;
+        // Linux 上整个 127.0.0.0/8 都在回环网卡上
+        let bind = Bind {
+            local_address: Some("127.0.0.2".parse()?),
+            interface: None,
+        };
+        let client = bind.client_builder()?.build()?;
+        let text = client.get(format!("http://{}/", addr)).send().await?.text().await?;
+        assert_eq!(text, "127.0.0.2");
+        let socket = bind.udp_socket(&IpType::V4).await?;
+        assert_eq!(socket.local_addr()?.ip(), "127.0.0.2".parse::<IpAddr>()?);
+        assert!(bind.reachable("127.0.0.1:53".parse()?));
+        // IPv4 出口不能到达IPv6地址
+        assert!(!bind.reachable("[::1]:53".parse()?));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_bind_default_works() -> ItdResult<()> {
+        let addr = echo_server().await?;
+        let bind = Bind::default();
+        assert!(bind.is_empty());
+        let client = bind.client_builder()?.build()?;
+        let text = client.get(format!("http://{}/", addr)).send().await?.text().await?;
+        assert_eq!(text, "127.0.0.1");
+        Ok(())
+    }
+}
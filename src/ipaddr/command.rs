@@ -0,0 +1,219 @@
+//! 执行外部命令获取IP
+//! 命令通过 sh -c (Windows 下为 cmd /C) 执行，环境变量 ITD_IP_TYPE 为 4 或 6，
+//! 从标准输出中取第一个对应类型的地址，退出码非0视为检测失败
+//! 命令只能在本地文件 ip_commands.json (可通过环境变量 ITD_IP_COMMANDS 指定路径) 中定义，如:
+//! {"wan2":{"command":"ip -4 addr show ppp1","timeout":5}}
+//! IP来源配置中只引用命令名称，不能通过接口写入命令内容
+use crate::error::ItdResult;
+use crate::ipaddr::{IpAddrExt, IpType};
+use crate::{err, BoxFuture};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command as Process;
+use tokio::time::timeout;
+
+pub const COMMANDS_FILE: &str = "ip_commands.json";
+pub const COMMANDS_ENV: &str = "ITD_IP_COMMANDS";
+
+/// 本地文件中定义的命令
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandConfig {
+    pub command: String,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+/// 读取本地命令文件，文件不存在时没有可用命令
+pub fn local_commands() -> ItdResult<HashMap<String, CommandConfig>> {
+    let path = std::env::var(COMMANDS_ENV).unwrap_or_else(|_| COMMANDS_FILE.to_string());
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(serde_json::from_str(&text)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 按名称查找本地命令
+pub fn local_command(name: &str) -> ItdResult<CommandConfig> {
+    match local_commands()?.remove(name) {
+        Some(config) => Ok(config),
+        None => err!(format!("命令未在本地文件中定义: {}", name)),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub command: String,
+    /// 匹配IP的正则表达式，有分组时取第一个分组，为空时按空白等分隔符拆分查找
+    pub pattern: Option<String>,
+    pub frequency: i64,
+    /// 命令执行超时(秒)
+    pub timeout: u64,
+}
+
+impl Command {
+    pub fn new(command: &str, pattern: Option<String>) -> Command {
+        Command {
+            command: command.to_string(),
+            pattern,
+            frequency: 300,
+            timeout: 10,
+        }
+    }
+    /// 执行命令并返回标准输出
+    pub async fn run(&self, ip_type: &IpType) -> ItdResult<String> {
+        let mut process = if cfg!(windows) {
+            let mut process = Process::new("cmd");
+            process.arg("/C");
+            process
+        } else {
+            let mut process = Process::new("sh");
+            process.arg("-c");
+            process
+        };
+        let ip_type = match ip_type {
+            IpType::V4 => "4",
+            IpType::V6 => "6",
+        };
+        process
+            .arg(&self.command)
+            .env("ITD_IP_TYPE", ip_type)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let output = match timeout(Duration::from_secs(self.timeout), process.output()).await {
+            Ok(output) => output?,
+            Err(_) => return err!(format!("命令执行超时: {}", self.command)),
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return err!(format!("命令执行失败({}): {}", output.status, stderr.trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+    /// 从输出中取第一个指定类型的地址
+    pub fn extract(&self, text: &str, ip_type: &IpType) -> ItdResult<Option<IpAddr>> {
+        let candidates: Vec<String> = match &self.pattern {
+            Some(pattern) => {
+                let re = match Regex::new(pattern) {
+                    Ok(re) => re,
+                    Err(_) => return err!(format!("正则表达式格式错误: {}", pattern)),
+                };
+                re.captures_iter(text)
+                    .filter_map(|caps| caps.get(1).or_else(|| caps.get(0)))
+                    .map(|m| m.as_str().trim().to_string())
+                    .collect()
+            }
+            None => text
+                .split(|c: char| !(c.is_ascii_hexdigit() || c == '.' || c == ':'))
+                .map(|word| word.trim_matches(|c| c == '.' || c == ':').to_string())
+                .collect(),
+        };
+        let ip = candidates
+            .iter()
+            .filter_map(|word| word.parse::<IpAddr>().ok())
+            .find(|ip| match ip_type {
+                IpType::V4 => ip.is_ipv4(),
+                IpType::V6 => ip.is_ipv6(),
+            });
+        Ok(ip)
+    }
+}
+
+impl IpAddrExt for Command {
+    fn frequency(&self) -> i64 {
+        self.frequency
+    }
+    fn lookup(&self, ip_type: IpType) -> BoxFuture<'_, Option<IpAddr>> {
+        Box::pin(async move {
+            let stdout = self.run(&ip_type).await?;
+            self.extract(&stdout, &ip_type)
+        })
+    }
+}
+
+/// 引用本地命令的IP来源，每次检测时重新读取本地文件
+#[derive(Debug, Clone)]
+pub struct LocalCommand {
+    pub name: String,
+    pub frequency: i64,
+}
+
+impl LocalCommand {
+    pub fn new(name: &str) -> LocalCommand {
+        LocalCommand {
+            name: name.to_string(),
+            frequency: 300,
+        }
+    }
+    pub fn command(&self) -> ItdResult<Command> {
+        let config = local_command(&self.name)?;
+        let mut command = Command::new(&config.command, config.pattern);
+        if let Some(timeout) = config.timeout {
+            command.timeout = timeout;
+        }
+        command.frequency = self.frequency;
+        Ok(command)
+    }
+}
+
+impl IpAddrExt for LocalCommand {
+    fn frequency(&self) -> i64 {
+        self.frequency
+    }
+    fn lookup(&self, ip_type: IpType) -> BoxFuture<'_, Option<IpAddr>> {
+        Box::pin(async move { self.command()?.lookup(ip_type).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_extract_works() -> ItdResult<()> {
+        let command = Command::new("", None);
+        let text = "wan0: inet 203.0.113.5/24, inet6 2001:db8::5/64.\n";
+        assert_eq!(command.extract(text, &IpType::V4)?, Some("203.0.113.5".parse()?));
+        assert_eq!(command.extract(text, &IpType::V6)?, Some("2001:db8::5".parse()?));
+        let command = Command::new("", Some(r"public=(\S+)".to_string()));
+        let text = "private=10.0.0.2 public=198.51.100.7";
+        assert_eq!(command.extract(text, &IpType::V4)?, Some("198.51.100.7".parse()?));
+        assert_eq!(command.extract(text, &IpType::V6)?, None);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_command_works() -> ItdResult<()> {
+        let command = Command::new(
+            r#"if [ "$ITD_IP_TYPE" = 4 ]; then echo 203.0.113.8; else echo 2001:db8::8; fi"#,
+            None,
+        );
+        assert_eq!(command.lookup(IpType::V4).await?, Some("203.0.113.8".parse()?));
+        assert_eq!(command.lookup(IpType::V6).await?, Some("2001:db8::8".parse()?));
+        let command = Command::new("echo 203.0.113.8; exit 3", None);
+        assert!(command.lookup(IpType::V4).await.is_err());
+        let mut command = Command::new("sleep 5", None);
+        command.timeout = 1;
+        assert!(command.lookup(IpType::V4).await.is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_local_command_works() -> ItdResult<()> {
+        crate::tests::local_commands();
+        assert_eq!(LocalCommand::new("echo9").lookup(IpType::V4).await?, Some("198.51.100.9".parse()?));
+        assert!(LocalCommand::new("echo 198.51.100.9").lookup(IpType::V4).await.is_err());
+        assert!(local_command("echo 198.51.100.9").is_err());
+        Ok(())
+    }
+}
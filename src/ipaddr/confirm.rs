@@ -0,0 +1,147 @@
+//! 新地址的确认和变更频率限制
+//! 重新拨号时运营商可能短暂分配临时地址，新地址需要连续检测到 N 次或持续 T 秒后才发布，
+//! 期间变回原地址的视为抖动并丢弃
+use crate::error::ItdResult;
+use crate::model::setting::SettingModel;
+use crate::utils::timestamp;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tracing::info;
+
+pub const CONFIRM_KEY: &str = "change_confirm";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfirmConfig {
+    /// 连续检测到的次数，为1时立即发布
+    pub confirmations: u32,
+    /// 持续时间(秒)，大于0时新地址持续这么久也视为确认
+    pub persist: i64,
+    /// 单条记录每小时最多变更次数，0 为不限制
+    pub max_changes_per_hour: u32,
+}
+
+impl Default for ConfirmConfig {
+    fn default() -> Self {
+        ConfirmConfig {
+            confirmations: 1,
+            persist: 0,
+            max_changes_per_hour: 0,
+        }
+    }
+}
+
+pub async fn get_confirm_config(db: &SqlitePool) -> ItdResult<ConfirmConfig> {
+    let setting = SettingModel::new(db);
+    let config = match setting.find(CONFIRM_KEY).await? {
+        Some(value) => serde_json::from_str(&value)?,
+        None => ConfirmConfig::default(),
+    };
+    Ok(config)
+}
+
+pub async fn set_confirm_config(db: &SqlitePool, config: &ConfirmConfig) -> ItdResult<()> {
+    let setting = SettingModel::new(db);
+    let value = serde_json::to_string(config)?;
+    setting.set(CONFIRM_KEY, &value, "地址变更确认配置").await?;
+    Ok(())
+}
+
+#[derive(Debug, Default, sqlx::FromRow)]
+struct Candidate {
+    confirmed: Option<String>,
+    candidate: Option<String>,
+    seen_count: i64,
+    first_seen_at: i64,
+}
+
+/// 记录一次检测结果，返回地址是否刚被确认需要发布
+pub async fn observe(db: &SqlitePool, config: &ConfirmConfig, source: &str, family: &str, address: &str) -> ItdResult<bool> {
+    let mut row: Candidate = sqlx::query_as(
+        r#"SELECT confirmed,candidate,seen_count,first_seen_at FROM ip_confirm WHERE source=? AND family=?"#,
+    )
+    .bind(source)
+    .bind(family)
+    .fetch_optional(db)
+    .await?
+    .unwrap_or_default();
+    let now = timestamp();
+    let confirmed = if row.confirmed.as_deref() == Some(address) {
+        if let Some(candidate) = row.candidate.take() {
+            info!("Flap suppressed: {} {} {} reverted to {}", source, family, candidate, address);
+        }
+        row.seen_count = 0;
+        false
+    } else {
+        if row.candidate.as_deref() == Some(address) {
+            row.seen_count += 1;
+        } else {
+            if let Some(candidate) = &row.candidate {
+                info!("Flap suppressed: {} {} {} replaced by {}", source, family, candidate, address);
+            }
+            row.candidate = Some(address.to_string());
+            row.seen_count = 1;
+            row.first_seen_at = now;
+        }
+        let persisted = config.persist > 0 && now - row.first_seen_at >= config.persist;
+        if row.seen_count >= config.confirmations.max(1) as i64 || persisted {
+            row.confirmed = row.candidate.take();
+            row.seen_count = 0;
+            true
+        } else {
+            info!(
+                "Address {} of {} seen {}/{} times, waiting for confirmation",
+                address, source, row.seen_count, config.confirmations
+            );
+            false
+        }
+    };
+    sqlx::query(
+        r#"INSERT INTO ip_confirm (source,family,confirmed,candidate,seen_count,first_seen_at) VALUES (?,?,?,?,?,?)
+           ON CONFLICT(source,family) DO UPDATE SET confirmed=excluded.confirmed, candidate=excluded.candidate,
+           seen_count=excluded.seen_count, first_seen_at=excluded.first_seen_at"#,
+    )
+    .bind(source)
+    .bind(family)
+    .bind(&row.confirmed)
+    .bind(&row.candidate)
+    .bind(row.seen_count)
+    .bind(row.first_seen_at)
+    .execute(db)
+    .await?;
+    Ok(confirmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_observe_works() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        let config = ConfirmConfig::default();
+        assert!(observe(&db, &config, "default", "ipv4", "198.51.100.1").await?);
+        assert!(!observe(&db, &config, "default", "ipv4", "198.51.100.1").await?);
+
+        let config = ConfirmConfig { confirmations: 3, ..Default::default() };
+        // 临时地址出现一次后变回，不发布
+        assert!(!observe(&db, &config, "default", "ipv4", "203.0.113.9").await?);
+        assert!(!observe(&db, &config, "default", "ipv4", "198.51.100.1").await?);
+        assert!(!observe(&db, &config, "default", "ipv4", "198.51.100.2").await?);
+        assert!(!observe(&db, &config, "default", "ipv4", "198.51.100.2").await?);
+        assert!(observe(&db, &config, "default", "ipv4", "198.51.100.2").await?);
+        assert!(!observe(&db, &config, "default", "ipv4", "198.51.100.2").await?);
+        // 各来源和地址族互不影响
+        assert!(!observe(&db, &config, "default", "ipv6", "2001:db8::1").await?);
+        assert!(!observe(&db, &config, "wan2", "ipv4", "198.51.100.2").await?);
+
+        // 持续时间达到后确认
+        let config = ConfirmConfig { confirmations: 100, persist: 60, ..Default::default() };
+        assert!(!observe(&db, &config, "wan2", "ipv4", "198.51.100.3").await?);
+        sqlx::query(r#"UPDATE ip_confirm SET first_seen_at = first_seen_at - 60 WHERE source='wan2'"#)
+            .execute(&db)
+            .await?;
+        assert!(observe(&db, &config, "wan2", "ipv4", "198.51.100.3").await?);
+        Ok(())
+    }
+}
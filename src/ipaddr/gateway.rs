@@ -0,0 +1,425 @@
+//! 向路由器查询WAN口地址
+//! 支持 UPnP IGD(SSDP发现 + GetExternalIPAddress)、NAT-PMP(RFC 6886)、PCP(RFC 6887)，
+//! 按配置顺序依次尝试，仅支持IPv4
+use crate::error::{ItdError, ItdResult};
+use crate::ipaddr::{IpAddrExt, IpType, REQUEST_AGENET};
+use crate::{err, BoxFuture};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::{timeout, Instant};
+use tracing::info;
+
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+const IGD_DEVICE: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+const WAN_SERVICES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+const NATPMP_PORT: u16 = 5351;
+const PCP_MAP_LIFETIME: u32 = 30;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GatewayProtocol {
+    Upnp,
+    NatPmp,
+    Pcp,
+}
+
+pub fn default_protocols() -> Vec<GatewayProtocol> {
+    vec![GatewayProtocol::Upnp, GatewayProtocol::NatPmp, GatewayProtocol::Pcp]
+}
+
+#[derive(Debug, Clone)]
+pub struct Gateway {
+    /// 按顺序尝试的协议
+    pub protocols: Vec<GatewayProtocol>,
+    /// 路由器地址，为空时读取系统默认网关
+    pub gateway: Option<IpAddr>,
+    /// UPnP 设备描述地址，配置后跳过 SSDP 发现
+    pub location: Option<String>,
+    /// NAT-PMP/PCP 端口
+    pub port: u16,
+    pub frequency: i64,
+    /// 单个协议的等待超时(秒)
+    pub timeout: u64,
+}
+
+impl Gateway {
+    pub fn new(protocols: Vec<GatewayProtocol>) -> Gateway {
+        Gateway {
+            protocols,
+            gateway: None,
+            location: None,
+            port: NATPMP_PORT,
+            frequency: 60,
+            timeout: 3,
+        }
+    }
+    fn wait(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+    fn gateway_addr(&self) -> ItdResult<SocketAddr> {
+        let gateway = match self.gateway {
+            Some(gateway) => gateway,
+            None => IpAddr::V4(default_gateway()?),
+        };
+        Ok(SocketAddr::new(gateway, self.port))
+    }
+    /// 通过 SSDP 发现 IGD 设备描述地址
+    pub async fn discover(&self) -> ItdResult<String> {
+        if let Some(location) = &self.location {
+            return Ok(location.clone());
+        }
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        let request = format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+            SSDP_ADDR, IGD_DEVICE
+        );
+        socket.send_to(request.as_bytes(), SSDP_ADDR).await?;
+        let deadline = Instant::now() + self.wait();
+        let mut buf = [0u8; 2048];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let (len, _) = match timeout(remaining, socket.recv_from(&mut buf)).await {
+                Ok(result) => result?,
+                Err(_) => return err!("SSDP 未发现 IGD 设备"),
+            };
+            let response = String::from_utf8_lossy(&buf[..len]);
+            if let Some(location) = parse_ssdp_location(&response) {
+                return Ok(location);
+            }
+        }
+    }
+    /// UPnP GetExternalIPAddress
+    pub async fn upnp(&self) -> ItdResult<IpAddr> {
+        let location = self.discover().await?;
+        let client = reqwest::Client::builder()
+            .no_proxy()
+            .timeout(self.wait())
+            .build()?;
+        let description = client
+            .get(&location)
+            .header("User-Agent", REQUEST_AGENET)
+            .send()
+            .await?
+            .text()
+            .await?;
+        let (service_type, control_url) = match parse_control_url(&description) {
+            Some(found) => found,
+            None => return err!("IGD 设备描述中没有 WAN 连接服务"),
+        };
+        let control_url = join_url(&location, &description, &control_url)?;
+        let body = format!(
+            r#"<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:GetExternalIPAddress xmlns:u="{}"></u:GetExternalIPAddress></s:Body></s:Envelope>"#,
+            service_type
+        );
+        let resp = client
+            .post(&control_url)
+            .header("Content-Type", "text/xml; charset=\"utf-8\"")
+            .header("SOAPAction", format!("\"{}#GetExternalIPAddress\"", service_type))
+            .header("User-Agent", REQUEST_AGENET)
+            .body(body)
+            .send()
+            .await?;
+        let status_code = resp.status();
+        let res_text = resp.text().await?;
+        if !status_code.is_success() {
+            return err!(format!("http status code: {}", status_code));
+        }
+        match xml_text(&res_text, "NewExternalIPAddress") {
+            Some(ip) => Ok(ip.trim().parse::<IpAddr>()?),
+            None => err!("响应中没有 NewExternalIPAddress"),
+        }
+    }
+    /// NAT-PMP 外部地址请求
+    pub async fn nat_pmp(&self) -> ItdResult<IpAddr> {
+        let gateway = self.gateway_addr()?;
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        socket.connect(gateway).await?;
+        socket.send(&[0, 0]).await?;
+        let mut buf = [0u8; 16];
+        let len = match timeout(self.wait(), socket.recv(&mut buf)).await {
+            Ok(result) => result?,
+            Err(_) => return err!("NAT-PMP 响应超时"),
+        };
+        parse_natpmp_response(&buf[..len])
+    }
+    /// PCP MAP 请求，从分配结果中获取外部地址
+    pub async fn pcp(&self) -> ItdResult<IpAddr> {
+        let gateway = self.gateway_addr()?;
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        socket.connect(gateway).await?;
+        let local = socket.local_addr()?;
+        let mut nonce = [0u8; 12];
+        getrandom::getrandom(&mut nonce)?;
+        let request = pcp_map_request(local, &nonce, PCP_MAP_LIFETIME);
+        socket.send(&request).await?;
+        let mut buf = [0u8; 1100];
+        let len = match timeout(self.wait(), socket.recv(&mut buf)).await {
+            Ok(result) => result?,
+            Err(_) => return err!("PCP 响应超时"),
+        };
+        let result = parse_pcp_response(&buf[..len], &nonce);
+        // 只需要外部地址，读取后立即删除映射
+        if result.is_ok() {
+            let request = pcp_map_request(local, &nonce, 0);
+            if let Err(e) = socket.send(&request).await {
+                info!("Delete PCP mapping failed: {}", e);
+            } else if timeout(self.wait(), socket.recv(&mut buf)).await.is_err() {
+                info!("Delete PCP mapping timeout");
+            }
+        }
+        result
+    }
+}
+
+/// 从 SSDP 响应中读取 LOCATION 头
+pub fn parse_ssdp_location(response: &str) -> Option<String> {
+    response.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("location") {
+            Some(value.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// 读取XML中第一个指定标签的文本
+pub fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = xml[start..].find(&format!("</{}>", tag))? + start;
+    Some(xml[start..end].trim().to_string())
+}
+
+/// 从设备描述中找到 WAN 连接服务，返回 (serviceType, controlURL)
+pub fn parse_control_url(description: &str) -> Option<(String, String)> {
+    let services: Vec<(String, String)> = description
+        .split("<service>")
+        .skip(1)
+        .filter_map(|block| {
+            let block = block.split("</service>").next()?;
+            Some((xml_text(block, "serviceType")?, xml_text(block, "controlURL")?))
+        })
+        .collect();
+    WAN_SERVICES.iter().find_map(|wanted| {
+        services
+            .iter()
+            .find(|(service_type, _)| service_type == wanted)
+            .cloned()
+    })
+}
+
+/// 拼接控制地址，优先使用描述中的 URLBase
+fn join_url(location: &str, description: &str, control_url: &str) -> ItdResult<String> {
+    let base = xml_text(description, "URLBase").unwrap_or(location.to_string());
+    let url = reqwest::Url::parse(&base)
+        .and_then(|base| base.join(control_url))
+        .map_err(|e| ItdError::new("UrlParse".to_string(), e.to_string()))?;
+    Ok(url.to_string())
+}
+
+/// 解析 NAT-PMP 外部地址响应
+pub fn parse_natpmp_response(data: &[u8]) -> ItdResult<IpAddr> {
+    if data.len() < 12 || data[0] != 0 || data[1] != 128 {
+        return err!("NAT-PMP 响应格式错误");
+    }
+    let result_code = u16::from_be_bytes([data[2], data[3]]);
+    if result_code != 0 {
+        return err!(format!("NAT-PMP 返回错误码: {}", result_code));
+    }
+    Ok(IpAddr::V4(Ipv4Addr::new(data[8], data[9], data[10], data[11])))
+}
+
+/// 构造 PCP MAP 请求，映射本地UDP端口，lifetime 为 0 时删除映射
+pub fn pcp_map_request(local: SocketAddr, nonce: &[u8; 12], lifetime: u32) -> Vec<u8> {
+    // IPv4 映射的建议外部地址须为 ::ffff:0.0.0.0 (RFC 6887 11.1)
+    let (client_ip, suggested) = match local.ip() {
+        IpAddr::V4(ip) => (ip.to_ipv6_mapped(), Ipv4Addr::UNSPECIFIED.to_ipv6_mapped()),
+        IpAddr::V6(ip) => (ip, Ipv6Addr::UNSPECIFIED),
+    };
+    let mut request = vec![2u8, 1, 0, 0];
+    request.extend_from_slice(&lifetime.to_be_bytes());
+    request.extend_from_slice(&client_ip.octets());
+    request.extend_from_slice(nonce);
+    // protocol UDP + reserved
+    request.extend_from_slice(&[17, 0, 0, 0]);
+    request.extend_from_slice(&local.port().to_be_bytes());
+    request.extend_from_slice(&0u16.to_be_bytes());
+    request.extend_from_slice(&suggested.octets());
+    request
+}
+
+/// 解析 PCP MAP 响应中的外部地址
+pub fn parse_pcp_response(data: &[u8], nonce: &[u8; 12]) -> ItdResult<IpAddr> {
+    if data.len() < 60 || data[0] != 2 || data[1] != 0x81 {
+        return err!("PCP 响应格式错误");
+    }
+    if data[3] != 0 {
+        return err!(format!("PCP 返回错误码: {}", data[3]));
+    }
+    if &data[24..36] != nonce {
+        return err!("PCP 响应的 nonce 不匹配");
+    }
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&data[44..60]);
+    let ip = Ipv6Addr::from(octets);
+    match ip.to_ipv4_mapped() {
+        Some(ip) => Ok(IpAddr::V4(ip)),
+        None => Ok(IpAddr::V6(ip)),
+    }
+}
+
+/// 从 /proc/net/route 读取默认网关
+pub fn default_gateway() -> ItdResult<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route")?;
+    parse_default_gateway(&routes)
+}
+
+pub fn parse_default_gateway(routes: &str) -> ItdResult<Ipv4Addr> {
+    for line in routes.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields[1] != "00000000" {
+            continue;
+        }
+        if let Ok(gateway) = u32::from_str_radix(fields[2], 16) {
+            return Ok(Ipv4Addr::from(gateway.swap_bytes()));
+        }
+    }
+    err!("未找到默认网关，请配置路由器地址")
+}
+
+impl IpAddrExt for Gateway {
+    fn frequency(&self) -> i64 {
+        self.frequency
+    }
+    fn lookup(&self, ip_type: IpType) -> BoxFuture<'_, Option<IpAddr>> {
+        Box::pin(async move {
+            if ip_type != IpType::V4 {
+                return Ok(None);
+            }
+            for protocol in self.protocols.iter() {
+                let result = match protocol {
+                    GatewayProtocol::Upnp => self.upnp().await,
+                    GatewayProtocol::NatPmp => self.nat_pmp().await,
+                    GatewayProtocol::Pcp => self.pcp().await,
+                };
+                match result {
+                    Ok(ip) if ip.is_ipv4() => return Ok(Some(ip)),
+                    Ok(ip) => info!("Gateway {:?} returned non ipv4 address {}", protocol, ip),
+                    Err(e) => info!("Gateway {:?} failed: {}", protocol, e),
+                }
+            }
+            err!("无法从路由器获取WAN口地址")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        routing::{get, post},
+        Router,
+    };
+
+    const DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+<device><deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+<serviceList>
+<service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType><controlURL>/l3f</controlURL></service>
+<service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType><controlURL>/ctl/IPConn</controlURL></service>
+</serviceList></device></root>"#;
+
+    #[test]
+    fn it_parse_upnp_works() {
+        let response = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\nLocation: http://192.168.1.1:5000/rootDesc.xml\r\n\r\n";
+        assert_eq!(
+            parse_ssdp_location(response),
+            Some("http://192.168.1.1:5000/rootDesc.xml".to_string())
+        );
+        let (service_type, control_url) = parse_control_url(DESCRIPTION).unwrap();
+        assert_eq!(service_type, "urn:schemas-upnp-org:service:WANIPConnection:1");
+        assert_eq!(control_url, "/ctl/IPConn");
+        assert_eq!(
+            join_url("http://192.168.1.1:5000/rootDesc.xml", DESCRIPTION, &control_url).unwrap(),
+            "http://192.168.1.1:5000/ctl/IPConn"
+        );
+    }
+
+    #[test]
+    fn it_parse_default_gateway_works() -> ItdResult<()> {
+        let routes = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+                      eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+                      eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\n";
+        assert_eq!(parse_default_gateway(routes)?, Ipv4Addr::new(192, 168, 1, 1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_upnp_works() -> ItdResult<()> {
+        let app = Router::new()
+            .route("/rootDesc.xml", get(|| async { DESCRIPTION }))
+            .route(
+                "/ctl/IPConn",
+                post(|| async {
+                    r#"<?xml version="1.0"?><s:Envelope><s:Body><u:GetExternalIPAddressResponse><NewExternalIPAddress>203.0.113.9</NewExternalIPAddress></u:GetExternalIPAddressResponse></s:Body></s:Envelope>"#
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let location = format!("http://{}/rootDesc.xml", listener.local_addr()?);
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let mut gateway = Gateway::new(vec![GatewayProtocol::Upnp]);
+        gateway.location = Some(location);
+        assert_eq!(gateway.lookup(IpType::V4).await?, Some("203.0.113.9".parse()?));
+        assert_eq!(gateway.lookup(IpType::V6).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_nat_pmp_and_pcp_works() -> ItdResult<()> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let addr = socket.local_addr()?;
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1100];
+            while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+                if len > 2 {
+                    seen.lock().unwrap().push(buf[..len].to_vec());
+                }
+                let response = if len == 2 {
+                    vec![0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 10]
+                } else {
+                    let mut response = vec![2u8, 0x81, 0, 0];
+                    response.extend_from_slice(&buf[4..8]);
+                    response.extend_from_slice(&[0u8; 16]);
+                    response.extend_from_slice(&buf[24..44]);
+                    response.extend_from_slice(&Ipv4Addr::new(203, 0, 113, 11).to_ipv6_mapped().octets());
+                    response
+                };
+                let _ = socket.send_to(&response, from).await;
+            }
+        });
+        let mut gateway = Gateway::new(vec![GatewayProtocol::NatPmp]);
+        gateway.gateway = Some(addr.ip());
+        gateway.port = addr.port();
+        assert_eq!(gateway.lookup(IpType::V4).await?, Some("203.0.113.10".parse()?));
+        gateway.protocols = vec![GatewayProtocol::Pcp];
+        assert_eq!(gateway.lookup(IpType::V4).await?, Some("203.0.113.11".parse()?));
+        // 先创建映射，读取地址后以 lifetime 0 删除
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(&requests[0][4..8], &PCP_MAP_LIFETIME.to_be_bytes());
+        assert_eq!(&requests[1][4..8], &0u32.to_be_bytes());
+        assert_eq!(&requests[0][44..60], &Ipv4Addr::UNSPECIFIED.to_ipv6_mapped().octets());
+        assert_eq!(&requests[0][24..36], &requests[1][24..36]);
+        Ok(())
+    }
+}
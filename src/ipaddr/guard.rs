@@ -0,0 +1,172 @@
+//! 发布保护
+//! 检测到的地址属于私有、CGNAT、ULA、文档或其它保留网段时默认不发布到公网DNS，
+//! 记录可以通过 allow_reserved 显式放行
+use crate::ipaddr::gateway::{default_protocols, Gateway};
+use crate::ipaddr::{IpAddrExt, IpType};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use tracing::warn;
+
+fn in_v4(ip: Ipv4Addr, net: [u8; 4], len: u32) -> bool {
+    let mask = if len == 0 { 0 } else { u32::MAX << (32 - len) };
+    u32::from(ip) & mask == u32::from(Ipv4Addr::from(net)) & mask
+}
+
+fn in_v6(ip: Ipv6Addr, net: [u16; 8], len: u32) -> bool {
+    let mask = if len == 0 { 0 } else { u128::MAX << (128 - len) };
+    u128::from(ip) & mask == u128::from(Ipv6Addr::from(net)) & mask
+}
+
+/// 是否为运营商级NAT地址(RFC 6598)
+pub fn is_cgnat(ip: Ipv4Addr) -> bool {
+    in_v4(ip, [100, 64, 0, 0], 10)
+}
+
+fn v4_class(ip: Ipv4Addr) -> Option<&'static str> {
+    const RANGES: [([u8; 4], u32, &str); 15] = [
+        ([0, 0, 0, 0], 8, "本网络地址"),
+        ([10, 0, 0, 0], 8, "私有地址(RFC 1918)"),
+        ([100, 64, 0, 0], 10, "运营商级NAT地址(RFC 6598)"),
+        ([127, 0, 0, 0], 8, "环回地址"),
+        ([169, 254, 0, 0], 16, "链路本地地址"),
+        ([172, 16, 0, 0], 12, "私有地址(RFC 1918)"),
+        ([192, 0, 0, 0], 24, "IETF 协议分配地址"),
+        ([192, 0, 2, 0], 24, "文档地址"),
+        ([192, 168, 0, 0], 16, "私有地址(RFC 1918)"),
+        ([198, 18, 0, 0], 15, "基准测试地址"),
+        ([198, 51, 100, 0], 24, "文档地址"),
+        ([203, 0, 113, 0], 24, "文档地址"),
+        ([224, 0, 0, 0], 4, "组播地址"),
+        ([240, 0, 0, 0], 4, "保留地址"),
+        ([255, 255, 255, 255], 32, "广播地址"),
+    ];
+    RANGES
+        .iter()
+        .find(|(net, len, _)| in_v4(ip, *net, *len))
+        .map(|(_, _, class)| *class)
+}
+
+fn v6_class(ip: Ipv6Addr) -> Option<&'static str> {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return v4_class(v4).or(Some("IPv4 映射地址"));
+    }
+    const RANGES: [([u16; 8], u32, &str); 8] = [
+        ([0, 0, 0, 0, 0, 0, 0, 0], 128, "未指定地址"),
+        ([0, 0, 0, 0, 0, 0, 0, 1], 128, "环回地址"),
+        ([0x100, 0, 0, 0, 0, 0, 0, 0], 64, "丢弃地址"),
+        ([0x2001, 0xdb8, 0, 0, 0, 0, 0, 0], 32, "文档地址"),
+        ([0x3fff, 0, 0, 0, 0, 0, 0, 0], 20, "文档地址"),
+        ([0xfc00, 0, 0, 0, 0, 0, 0, 0], 7, "唯一本地地址(ULA)"),
+        ([0xfe80, 0, 0, 0, 0, 0, 0, 0], 10, "链路本地地址"),
+        ([0xff00, 0, 0, 0, 0, 0, 0, 0], 8, "组播地址"),
+    ];
+    if let Some((_, _, class)) = RANGES.iter().find(|(net, len, _)| in_v6(ip, *net, *len)) {
+        return Some(class);
+    }
+    // 目前只有 2000::/3 分配为全球单播地址
+    if !in_v6(ip, [0x2000, 0, 0, 0, 0, 0, 0, 0], 3) {
+        return Some("非全球单播地址");
+    }
+    None
+}
+
+/// 地址属于不能发布的保留网段时返回网段说明
+pub fn reserved_class(ip: &IpAddr) -> Option<&'static str> {
+    match ip {
+        IpAddr::V4(ip) => v4_class(*ip),
+        IpAddr::V6(ip) => v6_class(*ip),
+    }
+}
+
+/// 检查记录值是否允许发布，返回拒绝的原因
+pub fn publish_denied(value: &str, allow_reserved: bool) -> Option<String> {
+    if allow_reserved {
+        return None;
+    }
+    let ip: IpAddr = value.parse().ok()?;
+    reserved_class(&ip).map(|class| format!("{} 是{}，默认不发布", value, class))
+}
+
+/// 比较WAN地址和回显的公网地址，判断是否处于运营商级NAT后
+/// wan 为路由器WAN口地址和本机网卡地址
+pub fn cgnat_warning(wan: &[Ipv4Addr], public: Ipv4Addr) -> Option<String> {
+    if wan.contains(&public) || v4_class(public).is_some() {
+        return None;
+    }
+    let cgnat = wan.iter().find(|ip| is_cgnat(**ip))?;
+    Some(format!(
+        "WAN 地址 {} 属于运营商级NAT网段，与公网地址 {} 不同，外部可能无法访问",
+        cgnat, public
+    ))
+}
+
+/// 获取本机网卡和路由器WAN口的IPv4地址，检查是否处于运营商级NAT后
+pub async fn check_cgnat(public: Ipv4Addr) -> Option<String> {
+    let mut wan: Vec<Ipv4Addr> = if_addrs::get_if_addrs()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|iface| match iface.ip() {
+            IpAddr::V4(ip) if !ip.is_loopback() => Some(ip),
+            _ => None,
+        })
+        .collect();
+    let mut gateway = Gateway::new(default_protocols());
+    gateway.timeout = 2;
+    let lookup = tokio::time::timeout(Duration::from_secs(8), gateway.lookup(IpType::V4)).await;
+    if let Ok(Ok(Some(IpAddr::V4(ip)))) = lookup {
+        wan.push(ip);
+    }
+    let warning = cgnat_warning(&wan, public);
+    if let Some(warning) = &warning {
+        warn!("{}", warning);
+    }
+    warning
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reserved_class_works() {
+        let reserved = [
+            "10.1.2.3",
+            "100.64.0.1",
+            "100.127.255.254",
+            "169.254.1.1",
+            "172.31.0.1",
+            "192.168.1.1",
+            "198.51.100.7",
+            "203.0.113.1",
+            "240.0.0.1",
+            "fd00::1",
+            "fe80::1",
+            "2001:db8::1",
+            "::ffff:192.168.1.1",
+            "::1",
+        ];
+        for ip in reserved {
+            assert!(reserved_class(&ip.parse().unwrap()).is_some(), "{}", ip);
+        }
+        let public = ["1.1.1.1", "100.128.0.1", "172.32.0.1", "223.5.5.5", "2400:3200::1", "2606:4700::1111"];
+        for ip in public {
+            assert!(reserved_class(&ip.parse().unwrap()).is_none(), "{}", ip);
+        }
+        assert!(publish_denied("10.0.0.1", false).is_some());
+        assert!(publish_denied("10.0.0.1", true).is_none());
+        assert!(publish_denied("example.com", false).is_none());
+    }
+
+    #[test]
+    fn it_cgnat_warning_works() {
+        let public: Ipv4Addr = "1.2.3.4".parse().unwrap();
+        let wan: Vec<Ipv4Addr> = vec!["192.168.1.10".parse().unwrap(), "100.72.1.2".parse().unwrap()];
+        assert!(cgnat_warning(&wan, public).is_some());
+        // WAN 口直接拿到公网地址
+        let wan: Vec<Ipv4Addr> = vec!["100.72.1.2".parse().unwrap(), public];
+        assert!(cgnat_warning(&wan, public).is_none());
+        // 普通家用NAT
+        let wan: Vec<Ipv4Addr> = vec!["192.168.1.10".parse().unwrap()];
+        assert!(cgnat_warning(&wan, public).is_none());
+    }
+}
@@ -0,0 +1,411 @@
+//! 通过多个HTTP回显服务获取公网IP
+//! 支持按顺序回退(fallback)和并发请求多数确认(quorum)两种模式，
+//! 每个回显服务的请求结果都会记录到 ip_echo_health 表中
+use crate::error::ItdResult;
+use crate::ipaddr::bind::Bind;
+use crate::ipaddr::{IpAddrExt, IpType, REQUEST_AGENET};
+use crate::model::echo_health::EchoHealthModel;
+use crate::{err, BoxFuture};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tracing::{error, info};
+
+/// 回显服务响应内容的解析方式
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum EchoExtractor {
+    /// 响应内容即为IP
+    #[default]
+    Plain,
+    /// JSON 字段，多级字段用 . 分隔，如 data.ip
+    Json { field: String },
+    /// 正则表达式，有分组时取第一个分组
+    Regex { pattern: String },
+}
+
+impl EchoExtractor {
+    pub fn extract(&self, text: &str) -> ItdResult<IpAddr> {
+        let ip = match self {
+            EchoExtractor::Plain => text.trim().to_string(),
+            EchoExtractor::Json { field } => {
+                let mut value: serde_json::Value = serde_json::from_str(text)?;
+                for key in field.split('.') {
+                    value = value[key].take();
+                }
+                match value {
+                    serde_json::Value::String(ip) => ip,
+                    _ => return err!(format!("响应中没有字段: {}", field)),
+                }
+            }
+            EchoExtractor::Regex { pattern } => {
+                let re = match Regex::new(pattern) {
+                    Ok(re) => re,
+                    Err(_) => return err!(format!("正则表达式格式错误: {}", pattern)),
+                };
+                let caps = match re.captures(text) {
+                    Some(caps) => caps,
+                    None => return err!(format!("响应未匹配: {}", pattern)),
+                };
+                caps.get(1)
+                    .or_else(|| caps.get(0))
+                    .map(|m| m.as_str().to_string())
+                    .unwrap_or_default()
+            }
+        };
+        let ip = ip.trim().parse::<IpAddr>()?;
+        Ok(ip)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EchoEndpoint {
+    pub url: String,
+    #[serde(default)]
+    pub extractor: EchoExtractor,
+}
+
+impl EchoEndpoint {
+    pub fn new(url: &str, extractor: EchoExtractor) -> EchoEndpoint {
+        EchoEndpoint {
+            url: url.to_string(),
+            extractor,
+        }
+    }
+    /// 请求回显服务并解析IP
+    pub async fn request(&self, client: &reqwest::Client) -> ItdResult<IpAddr> {
+        let resp = client
+            .get(&self.url)
+            .header("User-Agent", REQUEST_AGENET)
+            .send()
+            .await?;
+        let status_code = resp.status();
+        let res_text = resp.text().await?;
+        if !status_code.is_success() {
+            return err!(format!("http status code: {}", status_code));
+        }
+        self.extractor.extract(&res_text)
+    }
+}
+
+/// 多个回显服务的组合方式
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum EchoMode {
+    /// 按顺序请求，返回第一个成功的结果
+    #[default]
+    Fallback,
+    /// 并发请求所有服务，至少 required 个结果一致时才采用
+    Quorum { required: usize },
+}
+
+pub fn default_ipv4_endpoints() -> Vec<EchoEndpoint> {
+    vec![
+        EchoEndpoint::new(
+            "https://ipv4.lookup.test-ipv6.com/ip/?asn=1&testdomain=test-ipv6.com&testname=test_asn4",
+            EchoExtractor::Json { field: "ip".to_string() },
+        ),
+        EchoEndpoint::new("https://api.ipify.org", EchoExtractor::Plain),
+        EchoEndpoint::new("https://ipv4.icanhazip.com", EchoExtractor::Plain),
+    ]
+}
+
+pub fn default_ipv6_endpoints() -> Vec<EchoEndpoint> {
+    vec![
+        EchoEndpoint::new(
+            "https://ipv6.lookup.test-ipv6.com/ip/?asn=1&testdomain=test-ipv6.com&testname=test_asn6",
+            EchoExtractor::Json { field: "ip".to_string() },
+        ),
+        EchoEndpoint::new("https://api6.ipify.org", EchoExtractor::Plain),
+        EchoEndpoint::new("https://ipv6.icanhazip.com", EchoExtractor::Plain),
+    ]
+}
+
+pub struct HttpEcho {
+    pub ipv4: Vec<EchoEndpoint>,
+    pub ipv6: Vec<EchoEndpoint>,
+    pub mode: EchoMode,
+    pub frequency: i64,
+    /// 出口绑定
+    pub bind: Bind,
+    /// 用于记录健康统计，为空时不记录
+    pub db: Option<SqlitePool>,
+}
+
+impl HttpEcho {
+    pub fn new(ipv4: Vec<EchoEndpoint>, ipv6: Vec<EchoEndpoint>, mode: EchoMode) -> HttpEcho {
+        HttpEcho {
+            ipv4,
+            ipv6,
+            mode,
+            frequency: 300,
+            bind: Bind::default(),
+            db: None,
+        }
+    }
+    fn client(&self) -> ItdResult<reqwest::Client> {
+        let client = self
+            .bind
+            .client_builder()?
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(client)
+    }
+    /// 记录回显服务的健康统计，失败时仅记录日志
+    async fn record_health(&self, url: &str, result: &Result<String, String>, latency_ms: i64) {
+        if let Some(db) = &self.db {
+            if let Err(e) = EchoHealthModel::new(db).record(url, result, latency_ms).await {
+                error!("Record echo health of {} failed: {}", url, e);
+            }
+        }
+    }
+    /// 按顺序请求，返回第一个类型正确的结果
+    async fn fallback(&self, endpoints: &[EchoEndpoint], ip_type: &IpType) -> ItdResult<IpAddr> {
+        let client = self.client()?;
+        for endpoint in endpoints {
+            let start = Instant::now();
+            let result = match endpoint.request(&client).await {
+                Ok(ip) if matches_type(&ip, ip_type) => Ok(ip),
+                Ok(ip) => err!(format!("地址类型不符: {}", ip)),
+                Err(e) => Err(e),
+            };
+            let latency_ms = start.elapsed().as_millis() as i64;
+            match result {
+                Ok(ip) => {
+                    self.record_health(&endpoint.url, &Ok(ip.to_string()), latency_ms).await;
+                    return Ok(ip);
+                }
+                Err(e) => {
+                    info!("Echo service {} failed: {}", endpoint.url, e);
+                    self.record_health(&endpoint.url, &Err(e.to_string()), latency_ms).await;
+                }
+            }
+        }
+        err!("所有IP回显服务均请求失败")
+    }
+    /// 并发请求所有服务，多数一致时采用
+    async fn quorum(
+        &self,
+        endpoints: &[EchoEndpoint],
+        ip_type: &IpType,
+        required: usize,
+    ) -> ItdResult<IpAddr> {
+        let client = self.client()?;
+        let mut set = JoinSet::new();
+        for endpoint in endpoints.iter().cloned() {
+            let client = client.clone();
+            set.spawn(async move {
+                let start = Instant::now();
+                let result = endpoint.request(&client).await;
+                let latency_ms = start.elapsed().as_millis() as i64;
+                (endpoint.url, result.map_err(|e| e.to_string()), latency_ms)
+            });
+        }
+        let mut answers: Vec<(String, Result<IpAddr, String>, i64)> = vec![];
+        while let Some(joined) = set.join_next().await {
+            if let Ok((url, result, latency_ms)) = joined {
+                let result = match result {
+                    Ok(ip) if !matches_type(&ip, ip_type) => Err(format!("地址类型不符: {}", ip)),
+                    other => other,
+                };
+                answers.push((url, result, latency_ms));
+            }
+        }
+        let chosen = pick_quorum(answers.iter().map(|answer| &answer.1), required);
+        for (url, result, latency_ms) in answers.iter() {
+            // 与多数结果不一致的回答计为失败
+            let result = match (result, &chosen) {
+                (Ok(ip), Some(chosen)) if ip != chosen => Err(format!("结果不一致: {}", ip)),
+                (Ok(ip), _) => Ok(ip.to_string()),
+                (Err(e), _) => Err(e.clone()),
+            };
+            self.record_health(url, &result, *latency_ms).await;
+        }
+        match chosen {
+            Some(ip) => Ok(ip),
+            None => err!(format!("IP回显服务未达到 {}/{} 一致", required, endpoints.len())),
+        }
+    }
+}
+
+fn matches_type(ip: &IpAddr, ip_type: &IpType) -> bool {
+    match ip_type {
+        IpType::V4 => ip.is_ipv4(),
+        IpType::V6 => ip.is_ipv6(),
+    }
+}
+
+/// 统计一致的结果，出现次数唯一最多且不少于 required 时返回
+pub fn pick_quorum<'a, I>(answers: I, required: usize) -> Option<IpAddr>
+where
+    I: Iterator<Item = &'a Result<IpAddr, String>>,
+{
+    let mut counts: HashMap<IpAddr, usize> = HashMap::new();
+    for ip in answers.flatten() {
+        *counts.entry(*ip).or_insert(0) += 1;
+    }
+    let (ip, count) = counts.iter().max_by_key(|(_, count)| **count)?;
+    // 票数相同时无法确定结果
+    if counts.values().filter(|other| *other == count).count() > 1 {
+        return None;
+    }
+    if *count >= required.max(1) {
+        Some(*ip)
+    } else {
+        None
+    }
+}
+
+/// 校验多数确认需要的结果数，不能超过回显服务数量
+pub fn check_quorum(mode: &EchoMode, ipv4: &[EchoEndpoint], ipv6: &[EchoEndpoint]) -> ItdResult<()> {
+    if let EchoMode::Quorum { required } = mode {
+        if *required == 0 {
+            return err!("多数确认至少需要1个结果");
+        }
+        for (family, endpoints) in [("IPv4", ipv4), ("IPv6", ipv6)] {
+            if !endpoints.is_empty() && *required > endpoints.len() {
+                return err!(format!("{} 回显服务只有 {} 个，少于多数确认需要的 {} 个", family, endpoints.len(), required));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl IpAddrExt for HttpEcho {
+    fn frequency(&self) -> i64 {
+        self.frequency
+    }
+    fn lookup(&self, ip_type: IpType) -> BoxFuture<'_, Option<IpAddr>> {
+        Box::pin(async move {
+            let endpoints = match ip_type {
+                IpType::V4 => &self.ipv4,
+                IpType::V6 => &self.ipv6,
+            };
+            if endpoints.is_empty() {
+                return Ok(None);
+            }
+            let result = match self.mode {
+                EchoMode::Fallback => self.fallback(endpoints, &ip_type).await,
+                EchoMode::Quorum { required } => {
+                    self.quorum(endpoints, &ip_type, required).await
+                }
+            };
+            match result {
+                Ok(ip) => Ok(Some(ip)),
+                // 仅IPv4或仅IPv6的网络中另一类型不可用，不算检测失败
+                Err(e) if !self.bind.has_route(&ip_type) => {
+                    info!("No {:?} route, skip echo services: {}", ip_type, e);
+                    Ok(None)
+                }
+                Err(e) => Err(e),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+
+    async fn echo_server() -> ItdResult<String> {
+        let app = Router::new()
+            .route("/plain", get(|| async { "203.0.113.7\n" }))
+            .route("/json", get(|| async { r#"{"data":{"ip":"203.0.113.7"}}"# }))
+            .route("/html", get(|| async { "<p>Your IP: 203.0.113.7</p>" }))
+            .route("/wrong", get(|| async { "198.51.100.1" }))
+            .route(
+                "/down",
+                get(|| async { (http::StatusCode::SERVICE_UNAVAILABLE, "down") }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let base = format!("http://{}", listener.local_addr()?);
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        Ok(base)
+    }
+
+    #[test]
+    fn it_extractor_works() -> ItdResult<()> {
+        let ip: IpAddr = "203.0.113.7".parse()?;
+        assert_eq!(EchoExtractor::Plain.extract("203.0.113.7\n")?, ip);
+        let json = EchoExtractor::Json { field: "data.ip".to_string() };
+        assert_eq!(json.extract(r#"{"data":{"ip":"203.0.113.7"}}"#)?, ip);
+        assert!(json.extract(r#"{"ip":"203.0.113.7"}"#).is_err());
+        let re = EchoExtractor::Regex { pattern: r"IP: ([0-9.]+)".to_string() };
+        assert_eq!(re.extract("<p>Your IP: 203.0.113.7</p>")?, ip);
+        Ok(())
+    }
+
+    #[test]
+    fn it_pick_quorum_works() {
+        let a: IpAddr = "203.0.113.7".parse().unwrap();
+        let b: IpAddr = "198.51.100.1".parse().unwrap();
+        let answers = [Ok(a), Ok(b), Ok(a), Err("timeout".to_string())];
+        assert_eq!(pick_quorum(answers.iter(), 2), Some(a));
+        assert_eq!(pick_quorum(answers.iter(), 3), None);
+        // 票数相同时不采用任何结果
+        let answers = [Ok(a), Ok(b), Ok(a), Ok(b)];
+        assert_eq!(pick_quorum(answers.iter(), 2), None);
+        assert_eq!(pick_quorum([Ok(b)].iter(), 1), Some(b));
+    }
+
+    #[test]
+    fn it_check_quorum_works() {
+        let endpoints = default_ipv4_endpoints();
+        assert!(check_quorum(&EchoMode::Quorum { required: 2 }, &endpoints, &[]).is_ok());
+        assert!(check_quorum(&EchoMode::Quorum { required: 4 }, &endpoints, &[]).is_err());
+        assert!(check_quorum(&EchoMode::Quorum { required: 0 }, &endpoints, &[]).is_err());
+        assert!(check_quorum(&EchoMode::Fallback, &[], &[]).is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_fallback_works() -> ItdResult<()> {
+        let base = echo_server().await?;
+        let db = crate::tests::memory_db().await;
+        let mut echo = HttpEcho::new(
+            vec![
+                EchoEndpoint::new(&format!("{}/down", base), EchoExtractor::Plain),
+                EchoEndpoint::new(&format!("{}/plain", base), EchoExtractor::Plain),
+            ],
+            vec![],
+            EchoMode::Fallback,
+        );
+        echo.db = Some(db.clone());
+        assert_eq!(echo.lookup(IpType::V4).await?, Some("203.0.113.7".parse()?));
+        assert_eq!(echo.lookup(IpType::V6).await?, None);
+        let health = EchoHealthModel::new(&db).list().await?;
+        assert_eq!(health.len(), 2);
+        assert_eq!(health[0].failure_count, 1);
+        assert_eq!(health[1].success_count, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_quorum_works() -> ItdResult<()> {
+        let base = echo_server().await?;
+        let db = crate::tests::memory_db().await;
+        let endpoints = vec![
+            EchoEndpoint::new(&format!("{}/plain", base), EchoExtractor::Plain),
+            EchoEndpoint::new(
+                &format!("{}/json", base),
+                EchoExtractor::Json { field: "data.ip".to_string() },
+            ),
+            EchoEndpoint::new(&format!("{}/wrong", base), EchoExtractor::Plain),
+        ];
+        let mut echo = HttpEcho::new(endpoints.clone(), vec![], EchoMode::Quorum { required: 2 });
+        echo.db = Some(db.clone());
+        assert_eq!(echo.lookup(IpType::V4).await?, Some("203.0.113.7".parse()?));
+        let health = EchoHealthModel::new(&db).list().await?;
+        let wrong = health.iter().find(|h| h.url.ends_with("/wrong")).unwrap();
+        assert_eq!(wrong.failure_count, 1);
+        let echo = HttpEcho::new(endpoints, vec![], EchoMode::Quorum { required: 3 });
+        assert!(echo.lookup(IpType::V4).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! 从本机网卡读取IP地址
+//! 对于IPv6，WAN口上的全局地址即为公网地址，无需请求外部服务
+use crate::error::ItdResult;
+#[cfg(target_os = "linux")]
+use crate::ipaddr::ipv6_policy::read_if_inet6;
+use crate::ipaddr::ipv6_policy::{Ipv6Candidate, Ipv6Policy};
+use crate::ipaddr::{IpAddrExt, IpType};
+use crate::{err, BoxFuture};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+/// 地址范围过滤
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// 排除回环、链路本地、组播等地址
+    #[default]
+    Global,
+    /// 仅链路本地地址
+    Link,
+    /// 除回环地址外的所有地址
+    Any,
+}
+
+impl Scope {
+    pub fn matches(&self, ip: &IpAddr) -> bool {
+        if ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() {
+            return false;
+        }
+        let link_local = match ip {
+            IpAddr::V4(ip) => ip.is_link_local(),
+            IpAddr::V6(ip) => ip.is_unicast_link_local(),
+        };
+        match self {
+            Scope::Global => !link_local,
+            Scope::Link => link_local,
+            Scope::Any => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Interface {
+    /// 网卡名称，如 eth0 pppoe-wan
+    pub name: String,
+    pub scope: Scope,
+    /// IPv6 地址选择策略
+    pub policy: Ipv6Policy,
+    pub frequency: i64,
+    /// 最近一次IPv6选择的地址和原因
+    selected: Arc<Mutex<Option<String>>>,
+}
+
+impl Interface {
+    pub fn new(name: &str, scope: Scope) -> Interface {
+        Interface {
+            name: name.to_string(),
+            scope,
+            policy: Ipv6Policy::default(),
+            frequency: 0,
+            selected: Arc::new(Mutex::new(None)),
+        }
+    }
+    /// 读取网卡上的所有地址
+    pub fn addresses(&self) -> ItdResult<Vec<IpAddr>> {
+        let addrs = if_addrs::get_if_addrs()?;
+        let addrs: Vec<IpAddr> = addrs
+            .into_iter()
+            .filter(|addr| addr.name == self.name)
+            .map(|addr| addr.ip())
+            .collect();
+        if addrs.is_empty() {
+            return err!(format!("网卡 {} 不存在或没有地址", self.name));
+        }
+        Ok(addrs)
+    }
+    /// 按类型和地址范围选取第一个地址
+    pub fn pick(&self, addrs: &[IpAddr], ip_type: &IpType) -> Option<IpAddr> {
+        addrs
+            .iter()
+            .filter(|ip| match ip_type {
+                IpType::V4 => ip.is_ipv4(),
+                IpType::V6 => ip.is_ipv6(),
+            })
+            .find(|ip| self.scope.matches(ip))
+            .cloned()
+    }
+    /// 读取IPv6候选地址，Linux 下带有临时/废弃等标志
+    pub fn ipv6_candidates(&self, addrs: &[IpAddr]) -> Vec<Ipv6Candidate> {
+        #[cfg(target_os = "linux")]
+        if let Ok(candidates) = read_if_inet6(&self.name) {
+            if !candidates.is_empty() {
+                return candidates;
+            }
+        }
+        addrs
+            .iter()
+            .filter_map(|ip| match ip {
+                IpAddr::V6(ip) => Some(Ipv6Candidate::new(*ip)),
+                IpAddr::V4(_) => None,
+            })
+            .collect()
+    }
+    /// 按地址范围和选择策略选取IPv6地址
+    pub fn pick_ipv6(&self, candidates: &[Ipv6Candidate]) -> ItdResult<Option<IpAddr>> {
+        let candidates: Vec<Ipv6Candidate> = candidates
+            .iter()
+            .filter(|candidate| self.scope.matches(&IpAddr::V6(candidate.ip)))
+            .cloned()
+            .collect();
+        let selection = self.policy.select(&candidates)?;
+        let mut selected = self.selected.lock().unwrap();
+        match selection {
+            Some(selection) => {
+                info!("Interface {} selected {}: {}", self.name, selection.ip, selection.reason);
+                *selected = Some(format!("{}: {}", selection.ip, selection.reason));
+                Ok(Some(IpAddr::V6(selection.ip)))
+            }
+            None => {
+                *selected = None;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl IpAddrExt for Interface {
+    fn frequency(&self) -> i64 {
+        self.frequency
+    }
+    fn lookup(&self, ip_type: IpType) -> BoxFuture<'_, Option<IpAddr>> {
+        Box::pin(async move {
+            let addrs = self.addresses()?;
+            match ip_type {
+                IpType::V4 => Ok(self.pick(&addrs, &ip_type)),
+                IpType::V6 => self.pick_ipv6(&self.ipv6_candidates(&addrs)),
+            }
+        })
+    }
+    fn reason(&self) -> Option<String> {
+        self.selected.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_scope_filter_works() {
+        let addrs: Vec<IpAddr> = ["127.0.0.1", "169.254.1.1", "192.168.1.2", "fe80::1", "240e:1::1"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        let interface = Interface::new("eth0", Scope::Global);
+        assert_eq!(interface.pick(&addrs, &IpType::V4), Some("192.168.1.2".parse().unwrap()));
+        assert_eq!(interface.pick(&addrs, &IpType::V6), Some("240e:1::1".parse().unwrap()));
+        let interface = Interface::new("eth0", Scope::Link);
+        assert_eq!(interface.pick(&addrs, &IpType::V4), Some("169.254.1.1".parse().unwrap()));
+        assert_eq!(interface.pick(&addrs, &IpType::V6), Some("fe80::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn it_pick_ipv6_works() -> ItdResult<()> {
+        let mut temporary = Ipv6Candidate::new("240e:1::99".parse()?);
+        temporary.temporary = true;
+        let candidates = vec![
+            Ipv6Candidate::new("fe80::1".parse()?),
+            temporary,
+            Ipv6Candidate::new("240e:1::10".parse()?),
+        ];
+        let interface = Interface::new("eth0", Scope::Global);
+        assert_eq!(interface.pick_ipv6(&candidates)?, Some("240e:1::10".parse()?));
+        assert!(interface.reason().unwrap().starts_with("240e:1::10: stable address"));
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn it_loopback_lookup_works() -> ItdResult<()> {
+        let interface = Interface::new("lo", Scope::Any);
+        assert!(interface.addresses()?.iter().any(|ip| ip.is_loopback()));
+        // 回环地址总是被过滤
+        assert_eq!(interface.lookup(IpType::V4).await?, None);
+        let interface = Interface::new("no-such-if0", Scope::Any);
+        assert!(interface.lookup(IpType::V4).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+//! IPv6 地址选择策略
+//! 网卡上通常同时存在稳定地址、临时隐私地址、ULA、链路本地地址，
+//! 按策略排除并排序，避免AAAA记录在隐私地址之间来回切换
+use crate::err;
+use crate::error::ItdResult;
+use serde::{Deserialize, Serialize};
+use std::net::Ipv6Addr;
+
+/// /proc/net/if_inet6 中的地址标志
+const IFA_F_TEMPORARY: u32 = 0x01;
+const IFA_F_DADFAILED: u32 = 0x08;
+const IFA_F_DEPRECATED: u32 = 0x20;
+const IFA_F_TENTATIVE: u32 = 0x40;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ipv6Candidate {
+    pub ip: Ipv6Addr,
+    /// 临时隐私地址(RFC 4941)
+    pub temporary: bool,
+    /// 已过首选生存期
+    pub deprecated: bool,
+    /// 重复地址检测未完成或失败
+    pub tentative: bool,
+}
+
+impl Ipv6Candidate {
+    pub fn new(ip: Ipv6Addr) -> Ipv6Candidate {
+        Ipv6Candidate {
+            ip,
+            temporary: false,
+            deprecated: false,
+            tentative: false,
+        }
+    }
+}
+
+/// 读取网卡上带标志的IPv6地址，仅Linux可用
+pub fn read_if_inet6(name: &str) -> ItdResult<Vec<Ipv6Candidate>> {
+    let content = std::fs::read_to_string("/proc/net/if_inet6")?;
+    Ok(parse_if_inet6(&content, name))
+}
+
+pub fn parse_if_inet6(content: &str, name: &str) -> Vec<Ipv6Candidate> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 || fields[5] != name {
+                return None;
+            }
+            let ip = u128::from_str_radix(fields[0], 16).ok()?;
+            let flags = u32::from_str_radix(fields[4], 16).ok()?;
+            Some(Ipv6Candidate {
+                ip: Ipv6Addr::from(ip),
+                temporary: flags & IFA_F_TEMPORARY != 0,
+                deprecated: flags & IFA_F_DEPRECATED != 0,
+                tentative: flags & (IFA_F_TENTATIVE | IFA_F_DADFAILED) != 0,
+            })
+        })
+        .collect()
+}
+
+/// 解析 addr/len 格式的地址模式
+fn parse_pattern(pattern: &str) -> ItdResult<(u128, u32)> {
+    let (addr, len) = match pattern.split_once('/') {
+        Some((addr, len)) => (addr, len.parse::<u32>().unwrap_or(129)),
+        None => (pattern, 128),
+    };
+    let addr = match addr.parse::<Ipv6Addr>() {
+        Ok(addr) => addr,
+        Err(_) => return err!(format!("IPv6地址模式格式错误: {}", pattern)),
+    };
+    if len > 128 {
+        return err!(format!("IPv6地址模式长度错误: {}", pattern));
+    }
+    Ok((u128::from(addr), len))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ipv6Policy {
+    /// 优先使用稳定地址，没有时才使用临时地址
+    pub prefer_stable: bool,
+    pub allow_temporary: bool,
+    pub allow_deprecated: bool,
+    /// 是否允许 fc00::/7 唯一本地地址
+    pub allow_ula: bool,
+    pub allow_link_local: bool,
+    /// 前缀匹配，如 2408:8200::/32
+    pub prefix: Option<String>,
+    /// 后缀匹配，长度为末尾的位数，如 ::1234:5678/32
+    pub suffix: Option<String>,
+}
+
+impl Default for Ipv6Policy {
+    fn default() -> Self {
+        Ipv6Policy {
+            prefer_stable: true,
+            allow_temporary: true,
+            allow_deprecated: false,
+            allow_ula: false,
+            allow_link_local: false,
+            prefix: None,
+            suffix: None,
+        }
+    }
+}
+
+/// 选择结果及原因
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub ip: Ipv6Addr,
+    pub reason: String,
+}
+
+impl Ipv6Policy {
+    /// 返回候选地址被排除的原因，None 表示可用
+    pub fn exclude_reason(&self, candidate: &Ipv6Candidate) -> ItdResult<Option<&'static str>> {
+        let ip = candidate.ip;
+        if ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() {
+            return Ok(Some("loopback/multicast"));
+        }
+        if candidate.tentative {
+            return Ok(Some("tentative"));
+        }
+        if !self.allow_link_local && ip.is_unicast_link_local() {
+            return Ok(Some("link-local"));
+        }
+        if !self.allow_ula && ip.is_unique_local() {
+            return Ok(Some("ula"));
+        }
+        if !self.allow_deprecated && candidate.deprecated {
+            return Ok(Some("deprecated"));
+        }
+        if !self.allow_temporary && candidate.temporary {
+            return Ok(Some("temporary"));
+        }
+        let value = u128::from(ip);
+        if let Some(prefix) = &self.prefix {
+            let (addr, len) = parse_pattern(prefix)?;
+            let mask = u128::MAX.checked_shl(128 - len).unwrap_or(0);
+            if value & mask != addr & mask {
+                return Ok(Some("prefix mismatch"));
+            }
+        }
+        if let Some(suffix) = &self.suffix {
+            let (addr, len) = parse_pattern(suffix)?;
+            let mask = u128::MAX.checked_shr(128 - len).unwrap_or(0);
+            if value & mask != addr & mask {
+                return Ok(Some("suffix mismatch"));
+            }
+        }
+        Ok(None)
+    }
+    /// 按策略选择地址，保持候选的原始顺序，稳定地址优先
+    pub fn select(&self, candidates: &[Ipv6Candidate]) -> ItdResult<Option<Selection>> {
+        let mut excluded = vec![];
+        let mut allowed = vec![];
+        for candidate in candidates {
+            match self.exclude_reason(candidate)? {
+                Some(reason) => excluded.push(format!("{}: {}", candidate.ip, reason)),
+                None => allowed.push(candidate),
+            }
+        }
+        let chosen = if self.prefer_stable {
+            allowed
+                .iter()
+                .find(|candidate| !candidate.temporary)
+                .or_else(|| allowed.first())
+        } else {
+            allowed.first()
+        };
+        let chosen = match chosen {
+            Some(chosen) => chosen,
+            None => return Ok(None),
+        };
+        let kind = if chosen.temporary { "temporary" } else { "stable" };
+        let mut reason = format!("{} address, {} of {} candidates allowed", kind, allowed.len(), candidates.len());
+        if !excluded.is_empty() {
+            reason = format!("{}; excluded {}", reason, excluded.join(", "));
+        }
+        Ok(Some(Selection {
+            ip: chosen.ip,
+            reason,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IF_INET6: &str = "\
+fe800000000000000000000000000001 02 40 20 80     eth0
+240e0001000000000000000000000099 02 40 00 01     eth0
+240e0001000000000000000000000010 02 40 00 00     eth0
+240e0001000000000000000000000020 02 40 00 20     eth0
+fd000000000000000000000000000001 02 40 00 00     eth0
+00000000000000000000000000000001 01 80 10 80       lo
+";
+
+    #[test]
+    fn it_parse_if_inet6_works() {
+        let candidates = parse_if_inet6(IF_INET6, "eth0");
+        assert_eq!(candidates.len(), 5);
+        assert!(candidates[1].temporary);
+        assert!(candidates[3].deprecated);
+        assert!(!candidates[2].temporary && !candidates[2].deprecated);
+    }
+
+    #[test]
+    fn it_select_works() -> ItdResult<()> {
+        let candidates = parse_if_inet6(IF_INET6, "eth0");
+        let selection = Ipv6Policy::default().select(&candidates)?.unwrap();
+        assert_eq!(selection.ip, "240e:1::10".parse::<Ipv6Addr>()?);
+        assert!(selection.reason.starts_with("stable address, 2 of 5"));
+        assert!(selection.reason.contains("fd00::1: ula"));
+
+        let policy = Ipv6Policy {
+            prefer_stable: false,
+            ..Default::default()
+        };
+        assert_eq!(policy.select(&candidates)?.unwrap().ip, "240e:1::99".parse::<Ipv6Addr>()?);
+
+        let policy = Ipv6Policy {
+            suffix: Some("::20/16".to_string()),
+            allow_deprecated: true,
+            ..Default::default()
+        };
+        assert_eq!(policy.select(&candidates)?.unwrap().ip, "240e:1::20".parse::<Ipv6Addr>()?);
+
+        let policy = Ipv6Policy {
+            prefix: Some("2408::/16".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(policy.select(&candidates)?, None);
+        Ok(())
+    }
+}
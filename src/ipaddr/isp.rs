@@ -0,0 +1,124 @@
+//! 出口网络的ASN和运营商信息
+//! test-ipv6.com 的检测结果带有 asn 和 asn_name，保存到IP状态和历史中，
+//! 记录线路为 auto 时按检测到的运营商选择 电信/联通/移动 线路
+use crate::error::ItdResult;
+use crate::model::ip_state::{IpStateModel, DEFAULT_SOURCE};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::SqlitePool;
+
+/// 记录线路按检测到的运营商自动选择
+pub const LINE_AUTO: &str = "auto";
+pub const LINE_DEFAULT: &str = "默认";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AsnInfo {
+    pub asn: Option<i64>,
+    /// ASN 注册名称，如 CHINANET-BACKBONE
+    pub isp: Option<String>,
+}
+
+impl AsnInfo {
+    /// 从 test-ipv6.com 的响应中读取，asn 可能是字符串或数字
+    pub fn from_response(res: &Value) -> AsnInfo {
+        let asn = match &res["asn"] {
+            Value::Number(asn) => asn.as_i64(),
+            Value::String(asn) => asn.trim().trim_start_matches("AS").parse().ok(),
+            _ => None,
+        };
+        let isp = res["asn_name"]
+            .as_str()
+            .map(|isp| isp.trim().to_string())
+            .filter(|isp| !isp.is_empty());
+        AsnInfo { asn, isp }
+    }
+}
+
+const TELECOM_ASN: [i64; 8] = [4134, 4809, 4812, 4811, 4813, 23724, 58466, 134774];
+const UNICOM_ASN: [i64; 7] = [4837, 4808, 9929, 17621, 17622, 17623, 17816];
+const MOBILE_ASN: [i64; 9] = [9808, 24400, 24444, 24445, 56040, 56041, 56042, 56046, 58453];
+
+/// 按ASN或运营商名称匹配线路，无法识别时返回 None
+pub fn isp_line(asn: Option<i64>, isp: Option<&str>) -> Option<&'static str> {
+    if let Some(asn) = asn {
+        if TELECOM_ASN.contains(&asn) {
+            return Some("电信");
+        }
+        if UNICOM_ASN.contains(&asn) {
+            return Some("联通");
+        }
+        if MOBILE_ASN.contains(&asn) {
+            return Some("移动");
+        }
+    }
+    let isp = isp?.to_uppercase();
+    if isp.contains("CHINANET") || isp.contains("TELECOM") {
+        Some("电信")
+    } else if isp.contains("UNICOM") || isp.contains("CNCGROUP") {
+        Some("联通")
+    } else if isp.contains("CMNET") || isp.contains("MOBILE") {
+        Some("移动")
+    } else {
+        None
+    }
+}
+
+/// 记录发布时使用的线路
+/// 为 auto 时读取记录IP来源最近检测到的运营商，无法识别时使用默认线路
+pub async fn resolve_line(
+    db: &SqlitePool,
+    line: Option<&str>,
+    source: Option<&str>,
+    ip_type: &str,
+) -> ItdResult<Option<String>> {
+    if line != Some(LINE_AUTO) {
+        return Ok(line.map(str::to_string));
+    }
+    let states = IpStateModel::new(db).load_all().await?;
+    let detected = states.get(source.unwrap_or(DEFAULT_SOURCE)).and_then(|state| match ip_type {
+        "AAAA" => isp_line(state.ipv6_asn, state.ipv6_isp.as_deref()),
+        _ => isp_line(state.ipv4_asn, state.ipv4_isp.as_deref()),
+    });
+    Ok(Some(detected.unwrap_or(LINE_DEFAULT).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IpState;
+
+    #[test]
+    fn it_asn_info_works() {
+        let res: Value = serde_json::from_str(
+            r#"{"ip":"203.0.113.1","type":"ipv4","asn":"4134","asn_name":"CHINANET-BACKBONE No.31,Jin-rong Street, CN"}"#,
+        )
+        .unwrap();
+        let info = AsnInfo::from_response(&res);
+        assert_eq!(info.asn, Some(4134));
+        assert_eq!(isp_line(info.asn, info.isp.as_deref()), Some("电信"));
+        let res: Value = serde_json::from_str(r#"{"ip":"203.0.113.1","asn":4837}"#).unwrap();
+        assert_eq!(AsnInfo::from_response(&res), AsnInfo { asn: Some(4837), isp: None });
+        assert_eq!(isp_line(None, Some("China Mobile Communications Corporation")), Some("移动"));
+        assert_eq!(isp_line(Some(13335), Some("CLOUDFLARENET")), None);
+        let res: Value = serde_json::from_str(r#"{"ip":"203.0.113.1"}"#).unwrap();
+        assert_eq!(AsnInfo::from_response(&res), AsnInfo::default());
+    }
+
+    #[tokio::test]
+    async fn it_resolve_line_works() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        let state = IpState {
+            ipv4: Some("203.0.113.1".to_string()),
+            ipv4_asn: Some(4837),
+            ..Default::default()
+        };
+        IpStateModel::new(&db).save("wan2", &state).await?;
+        assert_eq!(resolve_line(&db, None, None, "A").await?, None);
+        assert_eq!(resolve_line(&db, Some("移动"), None, "A").await?.as_deref(), Some("移动"));
+        assert_eq!(resolve_line(&db, Some(LINE_AUTO), Some("wan2"), "A").await?.as_deref(), Some("联通"));
+        // 来源未检测到运营商时使用默认线路
+        assert_eq!(resolve_line(&db, Some(LINE_AUTO), None, "A").await?.as_deref(), Some(LINE_DEFAULT));
+        assert_eq!(resolve_line(&db, Some(LINE_AUTO), Some("wan2"), "AAAA").await?.as_deref(), Some(LINE_DEFAULT));
+        Ok(())
+    }
+}
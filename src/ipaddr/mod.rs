@@ -1,19 +1,158 @@
-use crate::{BoxFuture, IpState};
-use std::sync::Arc;
-use tokio::sync::RwLock;
-
-pub mod ipv6_net;
-pub mod watch;
-
-const REQUEST_AGENET: &str = "Ip-Tell-DNS/v0.1";
-#[derive(Debug, Clone,PartialEq)]
-pub enum IpType {
-    V4,
-    V6,
-}
-/// (ipv4_changed, ipv6_changed)
-pub type IpStateChanged = (bool, bool);
-pub trait IpAddrExt {
-    fn get_ip(&self,ip_types:Vec<IpType>, ip_state: Arc<RwLock<IpState>>) -> BoxFuture<IpStateChanged>;
-    //fn get_record_type(&self, ip: String) -> IpAddr;
-}
+use crate::ipaddr::isp::AsnInfo;
+use crate::utils::timestamp;
+use crate::{BoxFuture, IpState};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::error;
+
+pub mod bind;
+pub mod command;
+pub mod confirm;
+pub mod gateway;
+pub mod guard;
+pub mod http_echo;
+pub mod interface;
+pub mod isp;
+pub mod ipv6_net;
+pub mod ipv6_policy;
+pub mod netlink;
+pub mod prefix;
+pub mod source;
+pub mod stun;
+pub mod watch;
+
+const REQUEST_AGENET: &str = "Ip-Tell-DNS/v0.1";
+#[derive(Debug, Clone,PartialEq)]
+pub enum IpType {
+    V4,
+    V6,
+}
+/// (ipv4_changed, ipv6_changed)
+pub type IpStateChanged = (bool, bool);
+/// 一次 get_ip 的结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Detection {
+    pub changed: IpStateChanged,
+    /// 实际查询到地址的类型 (ipv4, ipv6)，在检测频率内被跳过的类型为 false
+    pub looked_up: (bool, bool),
+}
+pub trait IpAddrExt: Send + Sync {
+    /// 检测频率，距离上次IP变化不足该秒数时跳过检测
+    fn frequency(&self) -> i64;
+    /// 获取指定类型的IP，不支持该类型时返回 None
+    fn lookup(&self, ip_type: IpType) -> BoxFuture<'_, Option<IpAddr>>;
+    /// 最近一次IPv6地址的选择原因
+    fn reason(&self) -> Option<String> {
+        None
+    }
+    /// 最近一次检测到的出口ASN和运营商
+    fn asn(&self, _ip_type: &IpType) -> Option<AsnInfo> {
+        None
+    }
+    /// 按检测频率获取IP并写入 IpState，force 为 true 时忽略检测频率
+    fn get_ip(&self, ip_types: Vec<IpType>, ip_state: Arc<RwLock<IpState>>, force: bool) -> BoxFuture<'_, Detection> {
+        Box::pin(async move {
+            let mut ip_state_changed = (false, false);
+            let mut looked_up = (false, false);
+            let mut failure = None;
+            let current_timestamp = timestamp();
+            for ip_type in ip_types {
+                let last_updated_at = match ip_type {
+                    IpType::V4 => ip_state.read().await.ipv4_updated_at,
+                    IpType::V6 => ip_state.read().await.ipv6_updated_at,
+                };
+                if !force && current_timestamp - last_updated_at < self.frequency() {
+                    continue;
+                }
+                // 一种类型检测失败时继续检测另一种，避免丢失已检测到的变化
+                let ip = match self.lookup(ip_type.clone()).await {
+                    Ok(Some(ip)) => ip,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        failure = Some(e);
+                        continue;
+                    }
+                };
+                match ip_type {
+                    IpType::V4 => looked_up.0 = ip.is_ipv4(),
+                    IpType::V6 => looked_up.1 = ip.is_ipv6(),
+                }
+                let ip_str = Some(ip.to_string());
+                let mut data = ip_state.write().await;
+                if ip_type == IpType::V6 {
+                    data.ipv6_reason = self.reason();
+                }
+                let asn = self.asn(&ip_type).unwrap_or_default();
+                match ip_type {
+                    IpType::V4 => (data.ipv4_asn, data.ipv4_isp) = (asn.asn, asn.isp),
+                    IpType::V6 => (data.ipv6_asn, data.ipv6_isp) = (asn.asn, asn.isp),
+                }
+                match ip_type {
+                    IpType::V4 => {
+                        if ip.is_ipv4() && data.ipv4 != ip_str {
+                            data.ipv4 = ip_str;
+                            data.ipv4_updated_at = current_timestamp;
+                            ip_state_changed.0 = true;
+                        }
+                    }
+                    IpType::V6 => {
+                        if ip.is_ipv6() && data.ipv6 != ip_str {
+                            data.ipv6 = ip_str;
+                            data.ipv6_updated_at = current_timestamp;
+                            ip_state_changed.1 = true;
+                        }
+                    }
+                }
+            }
+            let detection = Detection { changed: ip_state_changed, looked_up };
+            match failure {
+                Some(e) if looked_up == (false, false) => Err(e),
+                Some(e) => {
+                    error!("Get ip failed: {}", e);
+                    Ok(detection)
+                }
+                None => Ok(detection),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::err;
+    use crate::error::ItdResult;
+
+    /// IPv4 固定返回一个地址，IPv6 检测失败
+    struct V4Only;
+
+    impl IpAddrExt for V4Only {
+        fn frequency(&self) -> i64 {
+            0
+        }
+        fn lookup(&self, ip_type: IpType) -> BoxFuture<'_, Option<IpAddr>> {
+            Box::pin(async move {
+                match ip_type {
+                    IpType::V4 => Ok(Some("203.0.113.7".parse()?)),
+                    IpType::V6 => err!("Network is unreachable"),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn it_get_ip_keeps_other_family_works() -> ItdResult<()> {
+        let state = Arc::new(RwLock::new(IpState::default()));
+        let detection = V4Only.get_ip(vec![IpType::V4, IpType::V6], state.clone(), false).await?;
+        assert_eq!(detection.changed, (true, false));
+        assert_eq!(detection.looked_up, (true, false));
+        assert_eq!(state.read().await.ipv4.as_deref(), Some("203.0.113.7"));
+        // 没有变化时仍返回查询到的类型
+        let detection = V4Only.get_ip(vec![IpType::V4, IpType::V6], state.clone(), false).await?;
+        assert_eq!(detection, Detection { changed: (false, false), looked_up: (true, false) });
+        // 所有类型都失败时返回检测失败
+        assert!(V4Only.get_ip(vec![IpType::V6], state.clone(), false).await.is_err());
+        Ok(())
+    }
+}
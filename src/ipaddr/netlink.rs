@@ -0,0 +1,296 @@
+//! 通过 netlink 监听网卡地址变化(仅Linux)
+//! 订阅 RTNLGRP_IPV4_IFADDR / RTNLGRP_IPV6_IFADDR，地址增删后经过防抖立即触发一次检测，
+//! 定时轮询仍然保留作为兜底
+#[cfg(not(target_os = "linux"))]
+use crate::err;
+use crate::error::ItdResult;
+use crate::model::setting::SettingModel;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::{timeout, Instant};
+use tracing::{error, info, warn};
+
+pub const NETLINK_KEY: &str = "netlink_watch";
+
+const RTM_NEWADDR: u16 = 20;
+const RTM_DELADDR: u16 = 21;
+const NLMSG_HDRLEN: usize = 16;
+const RT_SCOPE_UNIVERSE: u8 = 0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetlinkConfig {
+    pub enabled: bool,
+    /// 防抖时间(毫秒)，期间的连续事件合并为一次检测
+    pub debounce: u64,
+    /// 从第一个事件起最多等待的时间(毫秒)，持续不断的事件也不会一直推迟检测
+    pub max_delay: u64,
+}
+
+impl Default for NetlinkConfig {
+    fn default() -> Self {
+        NetlinkConfig {
+            enabled: false,
+            debounce: 2000,
+            max_delay: 10000,
+        }
+    }
+}
+
+pub async fn get_netlink_config(db: &sqlx::Pool<sqlx::Sqlite>) -> ItdResult<NetlinkConfig> {
+    let setting = SettingModel::new(db);
+    let config = match setting.find(NETLINK_KEY).await? {
+        Some(value) => serde_json::from_str(&value)?,
+        None => NetlinkConfig::default(),
+    };
+    Ok(config)
+}
+
+pub async fn set_netlink_config(db: &sqlx::Pool<sqlx::Sqlite>, config: &NetlinkConfig) -> ItdResult<()> {
+    let setting = SettingModel::new(db);
+    let value = serde_json::to_string(config)?;
+    setting.set(NETLINK_KEY, &value, "netlink 地址变化监听").await?;
+    Ok(())
+}
+
+/// 地址变化事件
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddrEvent {
+    pub added: bool,
+    /// AF_INET / AF_INET6
+    pub family: u8,
+    pub scope: u8,
+    pub index: u32,
+}
+
+/// 解析一次 recv 得到的 netlink 消息，返回其中的地址增删事件
+pub fn parse_messages(data: &[u8]) -> Vec<AddrEvent> {
+    let mut events = vec![];
+    let mut offset = 0;
+    while offset + NLMSG_HDRLEN <= data.len() {
+        let len = u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        let msg_type = u16::from_ne_bytes(data[offset + 4..offset + 6].try_into().unwrap());
+        if len < NLMSG_HDRLEN || offset + len > data.len() {
+            break;
+        }
+        // ifaddrmsg: family, prefixlen, flags, scope, index
+        let body = &data[offset + NLMSG_HDRLEN..offset + len];
+        if (msg_type == RTM_NEWADDR || msg_type == RTM_DELADDR) && body.len() >= 8 {
+            events.push(AddrEvent {
+                added: msg_type == RTM_NEWADDR,
+                family: body[0],
+                scope: body[3],
+                index: u32::from_ne_bytes(body[4..8].try_into().unwrap()),
+            });
+        }
+        // 消息按4字节对齐
+        offset += len.div_ceil(4) * 4;
+    }
+    events
+}
+
+#[cfg(target_os = "linux")]
+fn open_socket() -> ItdResult<i32> {
+    const RTMGRP_IPV4_IFADDR: u32 = 0x10;
+    const RTMGRP_IPV6_IFADDR: u32 = 0x100;
+    unsafe {
+        let fd = libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        );
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut addr: libc::sockaddr_nl = std::mem::zeroed();
+        addr.nl_family = libc::AF_NETLINK as u16;
+        addr.nl_groups = RTMGRP_IPV4_IFADDR | RTMGRP_IPV6_IFADDR;
+        let ret = libc::bind(
+            fd,
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as u32,
+        );
+        if ret < 0 {
+            let e = std::io::Error::last_os_error();
+            libc::close(fd);
+            return Err(e.into());
+        }
+        Ok(fd)
+    }
+}
+
+/// recv 失败后的处理
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq)]
+enum RecvFailure {
+    /// 被信号中断，直接重试
+    Retry,
+    /// 接收缓冲区溢出，事件已丢失
+    Overflow,
+    /// 其它错误，停止监听
+    Fatal,
+}
+
+#[cfg(target_os = "linux")]
+fn recv_failure(e: &std::io::Error) -> RecvFailure {
+    match e.raw_os_error() {
+        Some(libc::EINTR) => RecvFailure::Retry,
+        Some(libc::ENOBUFS) => RecvFailure::Overflow,
+        _ => RecvFailure::Fatal,
+    }
+}
+
+/// 在独立线程中阻塞读取 netlink 消息
+#[cfg(target_os = "linux")]
+fn spawn_reader(tx: mpsc::UnboundedSender<AddrEvent>) -> ItdResult<()> {
+    let fd = open_socket()?;
+    std::thread::spawn(move || {
+        let mut buf = vec![0u8; 8192];
+        loop {
+            let len = unsafe { libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+            if len < 0 {
+                let e = std::io::Error::last_os_error();
+                match recv_failure(&e) {
+                    RecvFailure::Retry => continue,
+                    RecvFailure::Overflow => {
+                        // 不知道丢失了哪些事件，按全局地址变化处理，触发一次完整检测
+                        warn!("netlink recv buffer overflow, trigger detection");
+                        let event = AddrEvent {
+                            added: true,
+                            family: 0,
+                            scope: RT_SCOPE_UNIVERSE,
+                            index: 0,
+                        };
+                        if tx.send(event).is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    RecvFailure::Fatal => {
+                        error!("netlink recv failed: {}", e);
+                        break;
+                    }
+                }
+            }
+            for event in parse_messages(&buf[..len as usize]) {
+                if tx.send(event).is_err() {
+                    unsafe { libc::close(fd) };
+                    return;
+                }
+            }
+        }
+        unsafe { libc::close(fd) };
+    });
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn spawn_reader(_tx: mpsc::UnboundedSender<AddrEvent>) -> ItdResult<()> {
+    err!("netlink 仅支持Linux")
+}
+
+/// 合并防抖时间内的事件，全局地址变化时唤醒检测任务
+/// 新事件会重新计时，但从第一个事件起最多等待 max_delay
+pub async fn debounce(mut rx: mpsc::UnboundedReceiver<AddrEvent>, wait: Duration, max_delay: Duration, notify: Arc<Notify>) {
+    while let Some(event) = rx.recv().await {
+        let deadline = Instant::now() + max_delay;
+        let mut relevant = event.scope == RT_SCOPE_UNIVERSE;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            match timeout(wait.min(remaining), rx.recv()).await {
+                Ok(Some(event)) => relevant |= event.scope == RT_SCOPE_UNIVERSE,
+                _ => break,
+            }
+        }
+        if relevant {
+            info!("Address changed, trigger detection");
+            notify.notify_one();
+        }
+    }
+}
+
+/// 按配置启动监听，未启用时直接返回
+pub async fn start(db: &sqlx::Pool<sqlx::Sqlite>, notify: Arc<Notify>) -> ItdResult<bool> {
+    let config = get_netlink_config(db).await?;
+    if !config.enabled {
+        return Ok(false);
+    }
+    let (tx, rx) = mpsc::unbounded_channel();
+    spawn_reader(tx)?;
+    let (wait, max_delay) = (Duration::from_millis(config.debounce), Duration::from_millis(config.max_delay));
+    tokio::spawn(debounce(rx, wait, max_delay, notify));
+    info!("Netlink address watcher started");
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(msg_type: u16, family: u8, scope: u8, index: u32) -> Vec<u8> {
+        let mut msg = vec![];
+        msg.extend_from_slice(&24u32.to_ne_bytes());
+        msg.extend_from_slice(&msg_type.to_ne_bytes());
+        msg.extend_from_slice(&[0u8; 10]);
+        msg.extend_from_slice(&[family, 64, 0, scope]);
+        msg.extend_from_slice(&index.to_ne_bytes());
+        msg
+    }
+
+    #[test]
+    fn it_parse_messages_works() {
+        let mut data = message(RTM_NEWADDR, 10, 0, 2);
+        data.extend(message(RTM_DELADDR, 2, 253, 1));
+        data.extend(message(16, 0, 0, 0));
+        let events = parse_messages(&data);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], AddrEvent { added: true, family: 10, scope: 0, index: 2 });
+        assert!(!events[1].added);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn it_recv_failure_works() {
+        let failure = |errno| recv_failure(&std::io::Error::from_raw_os_error(errno));
+        assert_eq!(failure(libc::EINTR), RecvFailure::Retry);
+        assert_eq!(failure(libc::ENOBUFS), RecvFailure::Overflow);
+        assert_eq!(failure(libc::EBADF), RecvFailure::Fatal);
+    }
+
+    #[tokio::test]
+    async fn it_debounce_works() {
+        let notify = Arc::new(Notify::new());
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(debounce(rx, Duration::from_millis(50), Duration::from_secs(5), notify.clone()));
+        // 仅链路本地地址变化不触发
+        tx.send(AddrEvent { added: true, family: 10, scope: 253, index: 2 }).unwrap();
+        assert!(timeout(Duration::from_millis(200), notify.notified()).await.is_err());
+        for _ in 0..3 {
+            tx.send(AddrEvent { added: true, family: 10, scope: 0, index: 2 }).unwrap();
+        }
+        assert!(timeout(Duration::from_millis(500), notify.notified()).await.is_ok());
+        // 连续事件只触发一次
+        assert!(timeout(Duration::from_millis(200), notify.notified()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_debounce_max_delay_works() {
+        let notify = Arc::new(Notify::new());
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(debounce(rx, Duration::from_millis(100), Duration::from_millis(300), notify.clone()));
+        // 事件间隔小于防抖时间，到达最长等待时间后仍然触发
+        let sender = tokio::spawn(async move {
+            for _ in 0..50 {
+                tx.send(AddrEvent { added: true, family: 10, scope: 0, index: 2 }).unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+        assert!(timeout(Duration::from_millis(600), notify.notified()).await.is_ok());
+        sender.abort();
+    }
+}
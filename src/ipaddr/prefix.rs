@@ -0,0 +1,103 @@
+//! IPv6 前缀 + 接口标识
+//! 运营商下发的前缀变化时，局域网内所有主机地址都会变化，
+//! 记录中保存固定的接口标识，与当前检测到的前缀拼接得到主机地址
+use crate::err;
+use crate::error::ItdResult;
+use std::net::Ipv6Addr;
+
+pub const DEFAULT_PREFIX_LEN: i32 = 64;
+
+/// 解析接口标识，支持IPv6后缀(如 ::1234:5678)或MAC地址(转换为EUI-64)
+pub fn parse_interface_id(value: &str) -> ItdResult<Ipv6Addr> {
+    let value = value.trim();
+    if let Some(mac) = parse_mac(value) {
+        return Ok(eui64(&mac));
+    }
+    match value.parse::<Ipv6Addr>() {
+        Ok(ip) => Ok(ip),
+        Err(_) => err!(format!("接口标识格式错误: {}", value)),
+    }
+}
+
+/// 解析 aa:bb:cc:dd:ee:ff 或 aa-bb-cc-dd-ee-ff 格式的MAC地址
+fn parse_mac(value: &str) -> Option<[u8; 6]> {
+    let parts: Vec<&str> = value.split([':', '-']).collect();
+    if parts.len() != 6 || parts.iter().any(|part| part.len() != 2) {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    for (i, part) in parts.iter().enumerate() {
+        mac[i] = u8::from_str_radix(part, 16).ok()?;
+    }
+    Some(mac)
+}
+
+/// MAC 转换为 EUI-64 接口标识：中间插入 fffe，翻转 U/L 位
+pub fn eui64(mac: &[u8; 6]) -> Ipv6Addr {
+    let mut octets = [0u8; 16];
+    octets[8] = mac[0] ^ 0x02;
+    octets[9] = mac[1];
+    octets[10] = mac[2];
+    octets[11] = 0xff;
+    octets[12] = 0xfe;
+    octets[13] = mac[3];
+    octets[14] = mac[4];
+    octets[15] = mac[5];
+    Ipv6Addr::from(octets)
+}
+
+/// 取 prefix 的前 prefix_len 位与接口标识的剩余位拼接
+pub fn combine(prefix: Ipv6Addr, prefix_len: i32, interface_id: Ipv6Addr) -> ItdResult<Ipv6Addr> {
+    if !(0..=128).contains(&prefix_len) {
+        return err!(format!("前缀长度错误: {}", prefix_len));
+    }
+    let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+    let ip = (u128::from(prefix) & mask) | (u128::from(interface_id) & !mask);
+    Ok(Ipv6Addr::from(ip))
+}
+
+/// 根据记录的接口标识计算要发布的地址，未配置接口标识时原样返回
+pub fn record_address(detected: &str, interface_id: Option<&str>, prefix_len: Option<i32>) -> ItdResult<String> {
+    let interface_id = match interface_id {
+        Some(interface_id) if !interface_id.trim().is_empty() => parse_interface_id(interface_id)?,
+        _ => return Ok(detected.to_string()),
+    };
+    let prefix = match detected.parse::<Ipv6Addr>() {
+        Ok(prefix) => prefix,
+        Err(_) => return err!(format!("不是IPv6地址，无法拼接接口标识: {}", detected)),
+    };
+    let ip = combine(prefix, prefix_len.unwrap_or(DEFAULT_PREFIX_LEN), interface_id)?;
+    Ok(ip.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_interface_id_works() -> ItdResult<()> {
+        assert_eq!(parse_interface_id("::1234:5678")?, "::1234:5678".parse::<Ipv6Addr>()?);
+        assert_eq!(
+            parse_interface_id("52:54:00:12:34:56")?,
+            "::5054:ff:fe12:3456".parse::<Ipv6Addr>()?
+        );
+        assert!(parse_interface_id("not-an-id").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_record_address_works() -> ItdResult<()> {
+        let detected = "2001:db8:aaaa:bbbb:1:2:3:4";
+        assert_eq!(
+            record_address(detected, Some("::1234:5678"), None)?,
+            "2001:db8:aaaa:bbbb::1234:5678"
+        );
+        assert_eq!(
+            record_address(detected, Some("0:0:0:ff::1234:5678"), Some(56))?,
+            "2001:db8:aaaa:bbff::1234:5678"
+        );
+        assert_eq!(record_address(detected, None, None)?, detected);
+        assert!(record_address("203.0.113.1", Some("::1"), None).is_err());
+        Ok(())
+    }
+}
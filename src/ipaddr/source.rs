@@ -0,0 +1,309 @@
+//! IP 来源配置
+//! 配置以JSON形式保存在 setting 表的 ip_source 键中，如:
+//! {"kind":"interface","name":"eth0","scope":"global"}
+//! 未配置时默认使用 test-ipv6.com
+//! 解析记录也可以通过 user_domain.source 引用 ip_source 表中的命名来源
+use crate::err;
+use crate::error::ItdResult;
+use crate::ipaddr::bind::Bind;
+use crate::ipaddr::command::{local_command, LocalCommand};
+use crate::ipaddr::gateway::{default_protocols, Gateway, GatewayProtocol};
+use crate::ipaddr::http_echo::{
+    check_quorum, default_ipv4_endpoints, default_ipv6_endpoints, EchoEndpoint, EchoMode, HttpEcho,
+};
+use crate::ipaddr::interface::{Interface, Scope};
+use crate::ipaddr::ipv6_net::Ipv6Net;
+use crate::ipaddr::ipv6_policy::Ipv6Policy;
+use crate::ipaddr::stun::{default_stun_servers, Stun};
+use crate::ipaddr::IpAddrExt;
+use crate::model::ip_source::IpSourceModel;
+use crate::model::setting::SettingModel;
+use crate::IpState;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub const IP_SOURCE_KEY: &str = "ip_source";
+
+/// 命名IP来源 -> 该来源的检测状态
+pub type SourceStates = Arc<RwLock<HashMap<String, Arc<RwLock<IpState>>>>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SourceConfig {
+    /// 请求 test-ipv6.com 获取公网地址
+    TestIpv6 {
+        #[serde(default)]
+        frequency: Option<i64>,
+        #[serde(default, skip_serializing_if = "Bind::is_empty")]
+        bind: Bind,
+    },
+    /// 读取本机网卡地址
+    Interface {
+        name: String,
+        #[serde(default)]
+        scope: Scope,
+        /// IPv6 地址选择策略，scope 为 link 时需同时设置 allow_link_local
+        #[serde(default)]
+        policy: Ipv6Policy,
+        #[serde(default)]
+        frequency: Option<i64>,
+    },
+    /// 请求多个HTTP回显服务
+    HttpEcho {
+        #[serde(default = "default_ipv4_endpoints")]
+        ipv4: Vec<EchoEndpoint>,
+        #[serde(default = "default_ipv6_endpoints")]
+        ipv6: Vec<EchoEndpoint>,
+        #[serde(default)]
+        mode: EchoMode,
+        #[serde(default)]
+        frequency: Option<i64>,
+        #[serde(default, skip_serializing_if = "Bind::is_empty")]
+        bind: Bind,
+    },
+    /// 通过 STUN 服务器获取NAT映射地址
+    Stun {
+        #[serde(default = "default_stun_servers")]
+        servers: Vec<String>,
+        #[serde(default)]
+        frequency: Option<i64>,
+        #[serde(default, skip_serializing_if = "Bind::is_empty")]
+        bind: Bind,
+    },
+    /// 通过 UPnP IGD / NAT-PMP / PCP 向路由器查询WAN口地址
+    Gateway {
+        #[serde(default = "default_protocols")]
+        protocols: Vec<GatewayProtocol>,
+        #[serde(default)]
+        gateway: Option<IpAddr>,
+        #[serde(default)]
+        location: Option<String>,
+        #[serde(default)]
+        frequency: Option<i64>,
+    },
+    /// 执行本地文件中定义的外部命令，从标准输出中解析IP
+    Command {
+        /// ip_commands.json 中的命令名称
+        name: String,
+        #[serde(default)]
+        frequency: Option<i64>,
+    },
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        SourceConfig::TestIpv6 {
+            frequency: None,
+            bind: Bind::default(),
+        }
+    }
+}
+
+impl SourceConfig {
+    /// 保存前校验配置
+    pub fn check(&self) -> ItdResult<()> {
+        match self {
+            SourceConfig::HttpEcho { ipv4, ipv6, mode, .. } => check_quorum(mode, ipv4, ipv6)?,
+            SourceConfig::Command { name, .. } => {
+                local_command(name)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    /// 根据配置创建IP来源
+    pub fn build(&self, db: &SqlitePool) -> Box<dyn IpAddrExt> {
+        match self {
+            SourceConfig::TestIpv6 { frequency, bind } => {
+                let mut source = Ipv6Net::new();
+                source.bind = bind.clone();
+                if let Some(frequency) = frequency {
+                    source.frequency = *frequency;
+                }
+                Box::new(source)
+            }
+            SourceConfig::Interface {
+                name,
+                scope,
+                policy,
+                frequency,
+            } => {
+                let mut source = Interface::new(name, scope.clone());
+                source.policy = policy.clone();
+                if let Some(frequency) = frequency {
+                    source.frequency = *frequency;
+                }
+                Box::new(source)
+            }
+            SourceConfig::HttpEcho {
+                ipv4,
+                ipv6,
+                mode,
+                frequency,
+                bind,
+            } => {
+                let mut source = HttpEcho::new(ipv4.clone(), ipv6.clone(), mode.clone());
+                source.bind = bind.clone();
+                if let Some(frequency) = frequency {
+                    source.frequency = *frequency;
+                }
+                source.db = Some(db.clone());
+                Box::new(source)
+            }
+            SourceConfig::Stun {
+                servers,
+                frequency,
+                bind,
+            } => {
+                let mut source = Stun::new(servers.clone());
+                source.bind = bind.clone();
+                if let Some(frequency) = frequency {
+                    source.frequency = *frequency;
+                }
+                Box::new(source)
+            }
+            SourceConfig::Gateway {
+                protocols,
+                gateway,
+                location,
+                frequency,
+            } => {
+                let mut source = Gateway::new(protocols.clone());
+                source.gateway = *gateway;
+                source.location = location.clone();
+                if let Some(frequency) = frequency {
+                    source.frequency = *frequency;
+                }
+                Box::new(source)
+            }
+            SourceConfig::Command { name, frequency } => {
+                let mut source = LocalCommand::new(name);
+                if let Some(frequency) = frequency {
+                    source.frequency = *frequency;
+                }
+                Box::new(source)
+            }
+        }
+    }
+}
+
+/// 读取当前的IP来源配置
+pub async fn get_source_config(db: &sqlx::Pool<sqlx::Sqlite>) -> ItdResult<SourceConfig> {
+    let setting = SettingModel::new(db);
+    let config = match setting.find(IP_SOURCE_KEY).await? {
+        Some(value) => serde_json::from_str(&value)?,
+        None => SourceConfig::default(),
+    };
+    Ok(config)
+}
+
+/// 保存IP来源配置
+pub async fn set_source_config(
+    db: &sqlx::Pool<sqlx::Sqlite>,
+    config: &SourceConfig,
+) -> ItdResult<()> {
+    config.check()?;
+    let setting = SettingModel::new(db);
+    let value = serde_json::to_string(config)?;
+    setting.set(IP_SOURCE_KEY, &value, "IP来源配置").await?;
+    Ok(())
+}
+
+/// 按配置加载IP来源
+pub async fn load_source(db: &sqlx::Pool<sqlx::Sqlite>) -> ItdResult<Box<dyn IpAddrExt>> {
+    let config = get_source_config(db).await?;
+    Ok(config.build(db))
+}
+
+/// 按名称加载 ip_source 表中的IP来源
+pub async fn load_named_source(db: &sqlx::Pool<sqlx::Sqlite>, name: &str) -> ItdResult<Box<dyn IpAddrExt>> {
+    let source = match IpSourceModel::new(db).find_by_name(name).await? {
+        Some(source) => source,
+        None => return err!(format!("IP来源不存在: {}", name)),
+    };
+    Ok(source.source_config()?.build(db))
+}
+
+/// 获取命名来源的检测状态，不存在时创建
+pub async fn source_state(source_states: &SourceStates, name: &str) -> Arc<RwLock<IpState>> {
+    source_states
+        .write()
+        .await
+        .entry(name.to_string())
+        .or_default()
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_source_config_parse_works() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        let config: SourceConfig = serde_json::from_str(r#"{"kind":"interface","name":"eth0"}"#)?;
+        match config {
+            SourceConfig::Interface { name, scope, .. } => {
+                assert_eq!(name, "eth0");
+                assert_eq!(scope, Scope::Global);
+            }
+            _ => panic!("unexpected config {:?}", config),
+        }
+        let config: SourceConfig = serde_json::from_str(r#"{"kind":"test_ipv6","frequency":60}"#)?;
+        assert_eq!(config.build(&db).frequency(), 60);
+        let config: SourceConfig =
+            serde_json::from_str(r#"{"kind":"stun","bind":{"interface":"eth1","local_address":"192.0.2.10"}}"#)?;
+        match config {
+            SourceConfig::Stun { bind, .. } => {
+                assert_eq!(bind.interface, Some("eth1".to_string()));
+                assert_eq!(bind.local_address, Some("192.0.2.10".parse()?));
+            }
+            _ => panic!("unexpected config {:?}", config),
+        }
+        let config: SourceConfig =
+            serde_json::from_str(r#"{"kind":"http_echo","mode":{"type":"quorum","required":2}}"#)?;
+        match config {
+            SourceConfig::HttpEcho { ipv4, mode, .. } => {
+                assert_eq!(ipv4.len(), default_ipv4_endpoints().len());
+                assert!(matches!(mode, EchoMode::Quorum { required: 2 }));
+            }
+            _ => panic!("unexpected config {:?}", config),
+        }
+        let config: SourceConfig =
+            serde_json::from_str(r#"{"kind":"gateway","protocols":["nat_pmp"],"gateway":"192.168.1.1"}"#)?;
+        match config {
+            SourceConfig::Gateway { protocols, gateway, .. } => {
+                assert_eq!(protocols, vec![GatewayProtocol::NatPmp]);
+                assert_eq!(gateway, Some("192.168.1.1".parse()?));
+            }
+            _ => panic!("unexpected config {:?}", config),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_source_config_setting_works() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        assert!(matches!(get_source_config(&db).await?, SourceConfig::TestIpv6 { .. }));
+        let config = SourceConfig::Interface {
+            name: "eth0".to_string(),
+            scope: Scope::Global,
+            policy: Ipv6Policy::default(),
+            frequency: None,
+        };
+        set_source_config(&db, &config).await?;
+        assert!(matches!(get_source_config(&db).await?, SourceConfig::Interface { .. }));
+        // 命令内容不能通过配置写入，只能引用本地文件中的命令
+        crate::tests::local_commands();
+        assert!(serde_json::from_str::<SourceConfig>(r#"{"kind":"command","command":"id"}"#).is_err());
+        let config: SourceConfig = serde_json::from_str(r#"{"kind":"command","name":"id"}"#)?;
+        assert!(set_source_config(&db, &config).await.is_err());
+        let config: SourceConfig = serde_json::from_str(r#"{"kind":"command","name":"echo1"}"#)?;
+        set_source_config(&db, &config).await?;
+        Ok(())
+    }
+}
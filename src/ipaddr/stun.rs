@@ -0,0 +1,286 @@
+//! 通过 STUN Binding 请求(RFC 5389)获取NAT映射后的公网地址
+//! 适用于HTTP回显服务被屏蔽或经过代理的环境
+use crate::error::ItdResult;
+use crate::ipaddr::bind::Bind;
+use crate::ipaddr::{IpAddrExt, IpType};
+use crate::{err, BoxFuture};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use tokio::net::lookup_host;
+use tokio::time::timeout;
+use tracing::info;
+
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_RESPONSE: u16 = 0x0101;
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+pub fn default_stun_servers() -> Vec<String> {
+    vec![
+        "stun.l.google.com:19302".to_string(),
+        "stun.cloudflare.com:3478".to_string(),
+        "stun.miwifi.com:3478".to_string(),
+    ]
+}
+
+#[derive(Debug, Clone)]
+pub struct Stun {
+    /// STUN 服务器列表，按顺序尝试，格式为 host:port
+    pub servers: Vec<String>,
+    pub frequency: i64,
+    /// 单个服务器的等待超时(秒)
+    pub timeout: u64,
+    /// 出口绑定
+    pub bind: Bind,
+}
+
+impl Stun {
+    pub fn new(servers: Vec<String>) -> Stun {
+        Stun {
+            servers,
+            frequency: 300,
+            timeout: 3,
+            bind: Bind::default(),
+        }
+    }
+    /// 向单个服务器发送 Binding 请求，返回映射地址
+    /// 服务器没有该类型的地址或本机无法到达时返回 None
+    pub async fn binding(&self, server: &str, ip_type: &IpType) -> ItdResult<Option<IpAddr>> {
+        let server_addr = lookup_host(server)
+            .await?
+            .find(|addr| match ip_type {
+                IpType::V4 => addr.is_ipv4(),
+                IpType::V6 => addr.is_ipv6(),
+            });
+        let server_addr = match server_addr {
+            Some(addr) if self.bind.reachable(addr) => addr,
+            _ => return Ok(None),
+        };
+        let socket = self.bind.udp_socket(ip_type).await?;
+        let mut transaction_id = [0u8; 12];
+        getrandom::getrandom(&mut transaction_id)?;
+        let request = binding_request(&transaction_id);
+        let mut buf = [0u8; 1024];
+        // UDP 可能丢包，超时前重发一次
+        for _ in 0..2 {
+            socket.send_to(&request, server_addr).await?;
+            let wait = Duration::from_millis(self.timeout * 1000 / 2);
+            match timeout(wait, socket.recv_from(&mut buf)).await {
+                Ok(Ok((len, from))) if from == server_addr => {
+                    return parse_binding_response(&buf[..len], &transaction_id).map(Some);
+                }
+                Ok(Ok(_)) => continue,
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => continue,
+            }
+        }
+        err!(format!("STUN 服务器 {} 响应超时", server))
+    }
+}
+
+/// 构造 Binding 请求
+pub fn binding_request(transaction_id: &[u8; 12]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(20);
+    request.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+    request.extend_from_slice(&0u16.to_be_bytes());
+    request.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request.extend_from_slice(transaction_id);
+    request
+}
+
+/// 解析 Binding 响应，优先使用 XOR-MAPPED-ADDRESS
+pub fn parse_binding_response(data: &[u8], transaction_id: &[u8; 12]) -> ItdResult<IpAddr> {
+    if data.len() < 20 {
+        return err!("STUN 响应长度错误");
+    }
+    let message_type = u16::from_be_bytes([data[0], data[1]]);
+    let length = u16::from_be_bytes([data[2], data[3]]) as usize;
+    let cookie = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    if message_type != BINDING_RESPONSE || cookie != MAGIC_COOKIE {
+        return err!(format!("不是 STUN Binding 成功响应: {:#06x}", message_type));
+    }
+    if &data[8..20] != transaction_id {
+        return err!("STUN 响应的 transaction id 不匹配");
+    }
+    if data.len() < 20 + length {
+        return err!("STUN 响应长度错误");
+    }
+    let mut mapped = None;
+    let mut offset = 20;
+    while offset + 4 <= 20 + length {
+        let attr_type = u16::from_be_bytes([data[offset], data[offset + 1]]);
+        let attr_len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        let value_start = offset + 4;
+        let value_end = value_start + attr_len;
+        if value_end > data.len() {
+            return err!("STUN 属性长度错误");
+        }
+        let value = &data[value_start..value_end];
+        match attr_type {
+            ATTR_XOR_MAPPED_ADDRESS => {
+                return parse_address(value, Some(&data[4..20]));
+            }
+            ATTR_MAPPED_ADDRESS => {
+                mapped = Some(parse_address(value, None)?);
+            }
+            _ => {}
+        }
+        // 属性按4字节对齐
+        offset = value_start + attr_len.div_ceil(4) * 4;
+    }
+    match mapped {
+        Some(ip) => Ok(ip),
+        None => err!("STUN 响应中没有映射地址"),
+    }
+}
+
+/// 解析地址属性，xor_key 为 magic cookie + transaction id
+fn parse_address(value: &[u8], xor_key: Option<&[u8]>) -> ItdResult<IpAddr> {
+    if value.len() < 4 {
+        return err!("STUN 地址属性长度错误");
+    }
+    let family = value[1];
+    let addr = &value[4..];
+    let mut bytes = addr.to_vec();
+    if let Some(key) = xor_key {
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte ^= key[i % key.len()];
+        }
+    }
+    match (family, bytes.len()) {
+        (0x01, 4) => Ok(IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]))),
+        (0x02, 16) => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&bytes);
+            Ok(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => err!(format!("STUN 地址族错误: {}", family)),
+    }
+}
+
+impl IpAddrExt for Stun {
+    fn frequency(&self) -> i64 {
+        self.frequency
+    }
+    fn lookup(&self, ip_type: IpType) -> BoxFuture<'_, Option<IpAddr>> {
+        Box::pin(async move {
+            let mut unreachable = 0;
+            for server in self.servers.iter() {
+                match self.binding(server, &ip_type).await {
+                    Ok(Some(ip)) => return Ok(Some(ip)),
+                    Ok(None) => unreachable += 1,
+                    Err(e) => info!("STUN server {} failed: {}", server, e),
+                }
+            }
+            // 仅IPv4或仅IPv6的网络中另一类型不可用，不算检测失败
+            if unreachable == self.servers.len() {
+                info!("No STUN server reachable over {:?}", ip_type);
+                return Ok(None);
+            }
+            err!("所有 STUN 服务器均请求失败")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use tokio::net::UdpSocket;
+
+    /// 本地 STUN 服务，以 XOR-MAPPED-ADDRESS 返回请求方地址
+    async fn stun_server(bind: &str) -> ItdResult<SocketAddr> {
+        let socket = UdpSocket::bind(bind).await?;
+        let addr = socket.local_addr()?;
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+                if len < 20 {
+                    continue;
+                }
+                let mut key = vec![];
+                key.extend_from_slice(&buf[4..20]);
+                let (family, ip) = match from.ip() {
+                    IpAddr::V4(ip) => (0x01u8, ip.octets().to_vec()),
+                    IpAddr::V6(ip) => (0x02u8, ip.octets().to_vec()),
+                };
+                let mut value = vec![0u8, family];
+                value.extend_from_slice(&(from.port() ^ (MAGIC_COOKIE >> 16) as u16).to_be_bytes());
+                value.extend(ip.iter().enumerate().map(|(i, b)| b ^ key[i % key.len()]));
+                let mut response = vec![];
+                response.extend_from_slice(&BINDING_RESPONSE.to_be_bytes());
+                response.extend_from_slice(&((value.len() + 4) as u16).to_be_bytes());
+                response.extend_from_slice(&buf[4..20]);
+                response.extend_from_slice(&ATTR_XOR_MAPPED_ADDRESS.to_be_bytes());
+                response.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                response.extend_from_slice(&value);
+                let _ = socket.send_to(&response, from).await;
+            }
+        });
+        Ok(addr)
+    }
+
+    #[test]
+    fn it_parse_mapped_address_works() -> ItdResult<()> {
+        let transaction_id = [7u8; 12];
+        let mut response = vec![];
+        response.extend_from_slice(&BINDING_RESPONSE.to_be_bytes());
+        response.extend_from_slice(&12u16.to_be_bytes());
+        response.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        response.extend_from_slice(&transaction_id);
+        response.extend_from_slice(&ATTR_MAPPED_ADDRESS.to_be_bytes());
+        response.extend_from_slice(&8u16.to_be_bytes());
+        response.extend_from_slice(&[0, 1, 0x1f, 0x90, 203, 0, 113, 7]);
+        let ip = parse_binding_response(&response, &transaction_id)?;
+        assert_eq!(ip, "203.0.113.7".parse::<IpAddr>()?);
+        assert!(parse_binding_response(&response, &[0u8; 12]).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_binding_v4_works() -> ItdResult<()> {
+        let addr = stun_server("127.0.0.1:0").await?;
+        let mut stun = Stun::new(vec!["127.0.0.1:1".to_string(), addr.to_string()]);
+        stun.timeout = 1;
+        let ip = stun.lookup(IpType::V4).await?;
+        assert_eq!(ip, Some("127.0.0.1".parse()?));
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn it_binding_local_address_works() -> ItdResult<()> {
+        let addr = stun_server("127.0.0.1:0").await?;
+        let mut stun = Stun::new(vec![addr.to_string()]);
+        stun.bind.local_address = Some("127.0.0.2".parse()?);
+        let ip = stun.lookup(IpType::V4).await?;
+        assert_eq!(ip, Some("127.0.0.2".parse()?));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_binding_v6_works() -> ItdResult<()> {
+        // 没有IPv6回环地址的环境跳过
+        let addr = match stun_server("[::1]:0").await {
+            Ok(addr) => addr,
+            Err(_) => return Ok(()),
+        };
+        let stun = Stun::new(vec![addr.to_string()]);
+        let ip = stun.binding(&addr.to_string(), &IpType::V6).await?;
+        assert_eq!(ip, Some("::1".parse::<IpAddr>()?));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_lookup_without_family_works() -> ItdResult<()> {
+        // 服务器只有IPv4地址时，IPv6 不可用而不是检测失败
+        let addr = stun_server("127.0.0.1:0").await?;
+        let stun = Stun::new(vec![addr.to_string()]);
+        assert_eq!(stun.lookup(IpType::V6).await?, None);
+        let mut stun = Stun::new(vec!["127.0.0.1:1".to_string()]);
+        stun.timeout = 1;
+        assert!(stun.lookup(IpType::V4).await.is_err());
+        Ok(())
+    }
+}
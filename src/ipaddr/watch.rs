@@ -1,73 +1,521 @@
-use crate::dnspod::action::PodAction;
+use crate::err;
 use crate::error::ItdResult;
-use crate::ipaddr::IpAddrExt;
-use crate::ipaddr::IpType;
-use crate::model::records::Records;
-use crate::IpState;
+use crate::ipaddr::confirm::{get_confirm_config, observe};
+use crate::ipaddr::guard::{check_cgnat, publish_denied};
+use crate::ipaddr::prefix::record_address;
+use crate::ipaddr::source::{load_named_source, load_source, source_state, SourceStates};
+use crate::ipaddr::{IpAddrExt, IpStateChanged, IpType};
+use crate::model::failover::FailoverModel;
+use crate::model::ip_state::{IpStateModel, DEFAULT_SOURCE};
+use crate::model::records::{Record, Records};
+use crate::model::sync_job::SyncJobs;
+use crate::notify::{self, EVENT_IP_CHANGED};
+use crate::{AppState, IpState};
+use chrono::{Local, TimeDelta};
+use serde_json::json;
 use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tracing::info;
+use tracing::{error, info, warn};
 //use tokio::task;
-use crate::ipaddr::ipv6_net::Ipv6Net;
 use tokio::sync::RwLock;
 
-pub async fn task(db: SqlitePool, ip_state: Arc<RwLock<IpState>>) -> ItdResult<()> {
-    // Do some work here
-    info!("Thread is working...");
-    let ipaddr = Ipv6Net::new();
-    //task::spawn(async move {
-    let (ip_v4_changed, ip_v6_changed) = ipaddr
-        .get_ip(vec![IpType::V4, IpType::V6], ip_state.clone())
+/// 单个IP来源的检测结果
+struct Detected {
+    ipv4: Option<String>,
+    ipv6: Option<String>,
+    changed: IpStateChanged,
+    /// 经过确认需要发布的地址族
+    publish: IpStateChanged,
+}
+
+/// 通知订阅了IP变化的用户，失败只记录日志
+async fn ip_changed(db: &SqlitePool, source: &str, family: &str, address: &str, asn: Option<i64>, isp: &Option<String>) {
+    let data = json!({ "source": source, "family": family, "address": address, "asn": asn, "isp": isp });
+    if let Err(e) = notify::emit(db, EVENT_IP_CHANGED, None, data).await {
+        error!("Notify IP change of {} failed: {}", source, e);
+    }
+}
+
+/// 检测IP并保存状态，新地址经过确认后才记录历史、通知并发布
+async fn detect(
+    db: &SqlitePool,
+    source: &str,
+    ipaddr: Box<dyn IpAddrExt>,
+    ip_state: Arc<RwLock<IpState>>,
+    force: bool,
+) -> ItdResult<Detected> {
+    let detection = ipaddr
+        .get_ip(vec![IpType::V4, IpType::V6], ip_state.clone(), force)
         .await?;
-    if ip_v4_changed || ip_v6_changed {
-        info!("IP changed!");
-        let record_model = Records::new(&db);
-        let lists = record_model.get_record_list().await?;
-        let ip_state = ip_state.read().await;
-        let ipv4 = ip_state.ipv4.clone();
-        let ipv6 = ip_state.ipv6.clone();
-        drop(ip_state); 
-        for item in lists {
-            let ip_value = match item.ip_type.as_str() {
-                "A" => {
-                    if !ip_v4_changed {
-                        continue;
-                    }
-                    ipv4.clone().unwrap()
+    let changed = detection.changed;
+    let ip_state = ip_state.read().await;
+    // IP没有变化时也保存，auto 线路按最近一次查询到的ASN选择
+    let state_model = IpStateModel::new(db);
+    if detection.looked_up.0 || detection.looked_up.1 {
+        state_model.save(source, &ip_state).await?;
+    }
+    // 只有实际查询过的地址才算一次观察，检测频率内沿用的旧值不计数
+    let config = get_confirm_config(db).await?;
+    let mut publish = (false, false);
+    if let (true, Some(ipv4)) = (detection.looked_up.0, &ip_state.ipv4) {
+        publish.0 = observe(db, &config, source, "ipv4", ipv4).await?;
+    }
+    if let (true, Some(ipv6)) = (detection.looked_up.1, &ip_state.ipv6) {
+        publish.1 = observe(db, &config, source, "ipv6", ipv6).await?;
+    }
+    // 确认后的地址才记录历史和通知，未确认就消失的地址不留痕迹
+    if let (true, Some(ipv4)) = (publish.0, &ip_state.ipv4) {
+        state_model
+            .add_history(ipv4, "ipv4", source, ip_state.ipv4_asn, ip_state.ipv4_isp.as_deref())
+            .await?;
+        ip_changed(db, source, "ipv4", ipv4, ip_state.ipv4_asn, &ip_state.ipv4_isp).await;
+    }
+    if let (true, Some(ipv6)) = (publish.1, &ip_state.ipv6) {
+        state_model
+            .add_history(ipv6, "ipv6", source, ip_state.ipv6_asn, ip_state.ipv6_isp.as_deref())
+            .await?;
+        ip_changed(db, source, "ipv6", ipv6, ip_state.ipv6_asn, &ip_state.ipv6_isp).await;
+    }
+    Ok(Detected {
+        ipv4: ip_state.ipv4.clone(),
+        ipv6: ip_state.ipv6.clone(),
+        changed,
+        publish,
+    })
+}
+
+/// 立即检测命名来源，用于记录引用了还没有被后台任务检测过的来源
+pub async fn detect_named(db: &SqlitePool, source_states: &SourceStates, name: &str) -> ItdResult<()> {
+    let ipaddr = load_named_source(db, name).await?;
+    detect(db, name, ipaddr, source_state(source_states, name).await, true).await?;
+    Ok(())
+}
+
+/// 记录应发布的地址，来源尚未检测到对应类型的IP时返回 None
+/// AAAA 记录配置了接口标识时，用检测到的前缀拼接局域网主机地址
+pub fn record_value(item: &Record, ipv4: Option<&String>, ipv6: Option<&String>) -> ItdResult<Option<String>> {
+    match item.ip_type.as_str() {
+        "A" => Ok(ipv4.cloned()),
+        "AAAA" => match ipv6 {
+            Some(ipv6) => Ok(Some(record_address(ipv6, item.interface_id.as_deref(), item.prefix_len)?)),
+            None => Ok(None),
+        },
+        _ => err!(format!("Invalid ip type! {}", item.ip_type)),
+    }
+}
+
+/// 不管IP是否变化，把记录当前应有的值重新加入同步队列，返回任务id
+/// record_id 为空时处理所有记录
+pub async fn resync(
+    db: &SqlitePool,
+    ip_state: &Arc<RwLock<IpState>>,
+    source_states: &SourceStates,
+    record_id: Option<i64>,
+) -> ItdResult<Vec<i64>> {
+    let record_model = Records::new(db);
+    let lists = match record_id {
+        Some(id) => record_model.get_record(id).await?.into_iter().collect(),
+        None => record_model.get_record_list().await?,
+    };
+    let failover: HashSet<i64> = FailoverModel::new(db).list().await?.iter().map(|group| group.domain_id).collect();
+    let job_model = SyncJobs::new(db);
+    let mut jobs = vec![];
+    for item in lists {
+        if failover.contains(&(item.id as i64)) {
+            info!("Skip record {}.{}: 由故障切换组管理", item.host, item.domain);
+            continue;
+        }
+        if let Some(reason) = item.skip_reason() {
+            info!("Skip record {}.{}: {}", item.host, item.domain, reason);
+            record_model.set_skipped(item.id as i64, &reason).await?;
+            continue;
+        }
+        let state = match &item.source {
+            None => ip_state.read().await.clone(),
+            Some(name) => source_state(source_states, name).await.read().await.clone(),
+        };
+        match record_value(&item, state.ipv4.as_ref(), state.ipv6.as_ref()) {
+            Ok(Some(ip_value)) => match publish_denied(&ip_value, item.allow_reserved) {
+                Some(reason) => {
+                    warn!("Skip record {}.{}: {}", item.host, item.domain, reason);
+                    record_model.set_skipped(item.id as i64, &reason).await?;
                 }
-                "AAAA" => {
-                    if !ip_v6_changed {
-                        continue;
-                    }
-                    ipv6.clone().unwrap()
+                None => jobs.push(job_model.enqueue(item.id as i64, &ip_value).await?),
+            },
+            Ok(None) => {
+                let reason = "IP来源尚未检测到地址";
+                info!("Skip record {}.{}: {}", item.host, item.domain, reason);
+                record_model.set_skipped(item.id as i64, reason).await?;
+            }
+            Err(e) => error!("Record {}.{} address failed: {}", item.host, item.domain, e),
+        }
+    }
+    Ok(jobs)
+}
+
+/// force 为 true 时忽略各来源的检测频率，用于地址变化事件触发的检测
+pub async fn task(db: SqlitePool, ip_state: Arc<RwLock<IpState>>, source_states: SourceStates, force: bool) -> ItdResult<()> {
+    // Do some work here
+    info!("Thread is working...");
+    let record_model = Records::new(&db);
+    let lists = record_model.get_record_list().await?;
+    // 失败的IP来源和记录数量，单个失败不影响其它来源和记录的更新
+    let mut failed = 0;
+    // 全局来源总是检测，命名来源只检测被记录引用的
+    let mut sources: HashSet<Option<String>> = lists.iter().map(|item| item.source.clone()).collect();
+    sources.insert(None);
+    let mut detected: HashMap<Option<String>, Detected> = HashMap::new();
+    for source in sources {
+        let result = match &source {
+            None => match load_source(&db).await {
+                Ok(ipaddr) => detect(&db, DEFAULT_SOURCE, ipaddr, ip_state.clone(), force).await,
+                Err(e) => Err(e),
+            },
+            Some(name) => match load_named_source(&db, name).await {
+                Ok(ipaddr) => detect(&db, name, ipaddr, source_state(&source_states, name).await, force).await,
+                Err(e) => Err(e),
+            },
+        };
+        match result {
+            Ok(result) => {
+                if result.changed.0 || result.changed.1 {
+                    info!("IP changed! source: {}", source.as_deref().unwrap_or(DEFAULT_SOURCE));
                 }
-                _ => {
-                    info!("Invalid ip type! {}", item.ip_type);
-                    continue;
+                detected.insert(source, result);
+            }
+            Err(e) => {
+                failed += 1;
+                error!("IP source {} failed: {}", source.as_deref().unwrap_or(DEFAULT_SOURCE), e);
+            }
+        }
+    }
+    // 全局来源的IPv4地址确认后，在后台比较WAN地址判断是否处于运营商级NAT后
+    if let Some(Detected { ipv4: Some(ipv4), publish: (true, _), .. }) = detected.get(&None) {
+        if let Ok(public) = ipv4.parse() {
+            let ip_state = ip_state.clone();
+            tokio::spawn(async move {
+                let warning = check_cgnat(public).await;
+                ip_state.write().await.nat_warning = warning;
+            });
+        }
+    }
+    let job_model = SyncJobs::new(&db);
+    let confirm_config = get_confirm_config(&db).await?;
+    // 有故障切换组的记录由探测任务决定发布的值
+    let failover: HashSet<i64> = FailoverModel::new(&db).list().await?.iter().map(|group| group.domain_id).collect();
+    for item in lists {
+        if failover.contains(&(item.id as i64)) {
+            continue;
+        }
+        let source = match detected.get(&item.source) {
+            Some(source) => source,
+            None => continue,
+        };
+        let (ip_v4_changed, ip_v6_changed) = source.publish;
+        let changed = match item.ip_type.as_str() {
+            "A" => ip_v4_changed,
+            "AAAA" => ip_v6_changed,
+            _ => {
+                info!("Invalid ip type! {}", item.ip_type);
+                continue;
+            }
+        };
+        if !changed {
+            continue;
+        }
+        let ip_value = match record_value(&item, source.ipv4.as_ref(), source.ipv6.as_ref()) {
+            Ok(Some(ip)) => ip,
+            Ok(None) => continue,
+            Err(e) => {
+                failed += 1;
+                error!("Record {}.{} address failed: {}", item.host, item.domain, e);
+                continue;
+            }
+        };
+        if let Some(reason) = item.skip_reason() {
+            info!("Skip record {}.{}: {}", item.host, item.domain, reason);
+            record_model.set_skipped(item.id as i64, &reason).await?;
+            continue;
+        }
+        // 私有、CGNAT等保留地址默认不发布，记录显式放行时除外
+        if let Some(reason) = publish_denied(&ip_value, item.allow_reserved) {
+            warn!("Skip record {}.{}: {}", item.host, item.domain, reason);
+            record_model.set_skipped(item.id as i64, &reason).await?;
+            continue;
+        }
+        // 超过每小时变更次数时推迟到最早的一次变更满一小时后
+        if confirm_config.max_changes_per_hour > 0 {
+            let since = Local::now().naive_local() - TimeDelta::hours(1);
+            let changes = job_model.changes_since(item.id as i64, since).await?;
+            if changes.len() >= confirm_config.max_changes_per_hour as usize {
+                let not_before = changes[0] + TimeDelta::hours(1);
+                let reason = format!("每小时最多变更 {} 次，推迟到 {}", confirm_config.max_changes_per_hour, not_before);
+                info!("Suppress change of {}.{} to {}: {}", item.host, item.domain, ip_value, reason);
+                record_model.set_skipped(item.id as i64, &reason).await?;
+                job_model.enqueue_at(item.id as i64, &ip_value, not_before).await?;
+                continue;
+            }
+        }
+        // 由同步队列发布，失败时按退避时间重试
+        info!("Enqueue record {}.{} ,ip: {}", item.host, item.domain, &ip_value);
+        job_model.enqueue(item.id as i64, &ip_value).await?;
+    }
+    if failed > 0 {
+        return err!(format!("{} 个IP来源或记录地址处理失败", failed));
+    }
+    Ok(())
+}
+
+/// 持有检测锁运行 task，后台任务和手动运行不会同时检测同一个来源
+pub async fn task_exclusive(state: &AppState, force: bool) -> ItdResult<()> {
+    let _detecting = state.detecting.lock().await;
+    task(state.db.clone(), state.ip_state.clone(), state.source_states.clone(), force).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipaddr::isp::{resolve_line, AsnInfo, LINE_AUTO, LINE_DEFAULT};
+    use crate::ipaddr::source::SourceConfig;
+    use crate::model::ip_source::{IpSourceForm, IpSourceModel};
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_named_source_state_works() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        crate::tests::local_commands();
+        let config: SourceConfig =
+            serde_json::from_str(r#"{"kind":"command","name":"echo20","frequency":0}"#)?;
+        IpSourceModel::new(&db)
+            .add(IpSourceForm {
+                name: "vpn".to_string(),
+                config,
+            })
+            .await?;
+        let source_states = SourceStates::default();
+        let state = source_state(&source_states, "vpn").await;
+        let detected = detect(&db, "vpn", load_named_source(&db, "vpn").await?, state.clone(), false).await?;
+        assert_eq!(detected.ipv4, Some("198.51.100.20".to_string()));
+        assert!(detected.changed.0);
+        // 再次检测IP未变化
+        let detected = detect(&db, "vpn", load_named_source(&db, "vpn").await?, state, false).await?;
+        assert!(!detected.changed.0);
+        assert!(source_states.read().await.contains_key("vpn"));
+        let history = IpStateModel::new(&db).history(Some("vpn".to_string()), 1).await?;
+        assert_eq!(history.count, 1);
+        assert!(load_named_source(&db, "wan2").await.is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_gated_detection_is_not_observed() -> ItdResult<()> {
+        use crate::ipaddr::confirm::{set_confirm_config, ConfirmConfig};
+        let db = crate::tests::memory_db().await;
+        crate::tests::local_commands();
+        set_confirm_config(&db, &ConfirmConfig { confirmations: 2, ..Default::default() }).await?;
+        let config: SourceConfig =
+            serde_json::from_str(r#"{"kind":"command","name":"echo70","frequency":3600}"#)?;
+        IpSourceModel::new(&db)
+            .add(IpSourceForm {
+                name: "slow".to_string(),
+                config,
+            })
+            .await?;
+        let state = Arc::new(RwLock::new(IpState::default()));
+        let detected = detect(&db, "slow", load_named_source(&db, "slow").await?, state.clone(), false).await?;
+        assert_eq!(detected.ipv4, Some("198.51.100.70".to_string()));
+        assert!(!detected.publish.0);
+        // 检测频率内沿用的旧值不算第二次观察
+        let detected = detect(&db, "slow", load_named_source(&db, "slow").await?, state.clone(), false).await?;
+        assert!(!detected.publish.0);
+        let seen: i64 = sqlx::query_scalar(r#"SELECT seen_count FROM ip_confirm WHERE source='slow' AND family='ipv4'"#)
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(seen, 1);
+        // 未确认的地址不记录历史
+        let history = IpStateModel::new(&db).history(Some("slow".to_string()), 1).await?;
+        assert_eq!(history.count, 0);
+        let detected = detect(&db, "slow", load_named_source(&db, "slow").await?, state, true).await?;
+        assert!(detected.publish.0);
+        let history = IpStateModel::new(&db).history(Some("slow".to_string()), 1).await?;
+        assert_eq!(history.count, 1);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_task_exclusive_waits_for_running_detection() -> ItdResult<()> {
+        use crate::ipaddr::source::set_source_config;
+        let db = crate::tests::memory_db().await;
+        crate::tests::local_commands();
+        let config: SourceConfig =
+            serde_json::from_str(r#"{"kind":"command","name":"echo71","frequency":0}"#)?;
+        set_source_config(&db, &config).await?;
+        let state = crate::tests::app_state(db.clone());
+        // 另一个检测正在运行时等待它完成
+        let running = state.detecting.lock().await;
+        let handle = tokio::spawn({
+            let state = state.clone();
+            async move { task_exclusive(&state, true).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(state.ip_state.read().await.ipv4.is_none());
+        drop(running);
+        handle.await.unwrap()?;
+        assert_eq!(state.ip_state.read().await.ipv4.as_deref(), Some("198.51.100.71"));
+        Ok(())
+    }
+
+    /// 地址固定，ASN 每次查询可能不同
+    struct FixedAsn(i64);
+
+    impl IpAddrExt for FixedAsn {
+        fn frequency(&self) -> i64 {
+            0
+        }
+        fn lookup(&self, ip_type: IpType) -> crate::BoxFuture<'_, Option<std::net::IpAddr>> {
+            Box::pin(async move {
+                match ip_type {
+                    IpType::V4 => Ok(Some("203.0.113.9".parse()?)),
+                    IpType::V6 => Ok(None),
                 }
-            };
-            let domain = format!("{}.{}", item.host, item.domain);
-            info!("Update record domain: {} ,ip: {}", domain, &ip_value);
-            //let query_db = Arc::clone(&db);
-            let _ = sqlx::query!(
-                r#"UPDATE user_domain SET ip = ? WHERE id = ?"#,
-                ip_value,
-                item.id
-            )
+            })
+        }
+        fn asn(&self, _ip_type: &IpType) -> Option<AsnInfo> {
+            Some(AsnInfo { asn: Some(self.0), isp: None })
+        }
+    }
+
+    #[tokio::test]
+    async fn it_asn_saved_without_ip_change() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        let state = Arc::new(RwLock::new(IpState::default()));
+        let detected = detect(&db, "wan", Box::new(FixedAsn(64496)), state.clone(), false).await?;
+        assert!(detected.changed.0);
+        assert_eq!(resolve_line(&db, Some(LINE_AUTO), Some("wan"), "A").await?.as_deref(), Some(LINE_DEFAULT));
+        // IP没变但ASN变了，auto 线路按新的ASN选择
+        let detected = detect(&db, "wan", Box::new(FixedAsn(4134)), state, false).await?;
+        assert!(!detected.changed.0);
+        assert_eq!(resolve_line(&db, Some(LINE_AUTO), Some("wan"), "A").await?.as_deref(), Some("电信"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_sync_failure_is_isolated() -> ItdResult<()> {
+        use crate::ipaddr::source::set_source_config;
+        use crate::model::webhook::{WebhookForm, Webhooks};
+        use crate::notify::{EVENT_CREDENTIALS_INVALID, EVENT_RECORD_UPDATED};
+        use crate::verify::{set_verify_config, VerifyConfig};
+        use axum::{routing::put, Router};
+        let app = Router::new()
+            .route("/ok/:id", put(|| async { "ok" }))
+            .route("/fail/:id", put(|| async { (http::StatusCode::UNAUTHORIZED, "bad credentials") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        crate::tests::local_commands();
+        let config: SourceConfig =
+            serde_json::from_str(r#"{"kind":"command","name":"echo30","frequency":0}"#)?;
+        set_source_config(&db, &config).await?;
+        set_verify_config(&db, &VerifyConfig { enabled: false, ..Default::default() }).await?;
+        let fail_app = crate::tests::insert_custom_app(&db, &format!("http://{}/fail/{{record_id}}", addr)).await?;
+        let ok_app = crate::tests::insert_custom_app(&db, &format!("http://{}/ok/{{record_id}}", addr)).await?;
+        sqlx::query(
+            r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id,allow_reserved)
+               VALUES (1,?,'a','example.com','A','198.51.100.1',1,1), (2,?,'b','example.com','A','198.51.100.1',2,1)"#,
+        )
+        .bind(fail_app)
+        .bind(ok_app)
+        .execute(&db)
+        .await?;
+        sqlx::query(
+            r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id,status)
+               VALUES (3,?,'c','example.com','A','198.51.100.1',3,0), (4,?,'d','example.com','A','198.51.100.1',4,1)"#,
+        )
+        .bind(ok_app)
+        .bind(ok_app)
+        .execute(&db)
+        .await?;
+        let webhook = WebhookForm {
+            url: "http://127.0.0.1:9/hook".to_string(),
+            secret: None,
+            events: vec![EVENT_CREDENTIALS_INVALID.to_string(), EVENT_RECORD_UPDATED.to_string()],
+            failure_threshold: None,
+            status: None,
+        };
+        let (webhook, _) = Webhooks::new(&db).add(1, webhook).await?;
+        let ip_state = Arc::new(RwLock::new(IpState::default()));
+        task(db.clone(), ip_state, SourceStates::default(), false).await?;
+        assert_eq!(crate::queue::drain(&db, &Arc::default()).await?, 0);
+        // 401 视为密钥失效，成功的记录发送 record_updated
+        let mut events: Vec<String> =
+            Webhooks::new(&db).deliveries(webhook, 1).await?.data.iter().map(|delivery| delivery.event.clone()).collect();
+        events.sort();
+        assert_eq!(events, vec![EVENT_CREDENTIALS_INVALID, EVENT_RECORD_UPDATED]);
+        let record_model = Records::new(&db);
+        let failed = record_model.get_record(1).await?.unwrap();
+        assert_eq!(failed.ip, "198.51.100.1");
+        assert_eq!(failed.consecutive_failures, 1);
+        assert!(failed.last_error.is_some());
+        assert!(failed.last_attempt_at.is_some());
+        assert!(failed.last_synced_at.is_none());
+        let synced = record_model.get_record(2).await?.unwrap();
+        assert_eq!(synced.ip, "198.51.100.30");
+        assert_eq!(synced.consecutive_failures, 0);
+        assert!(synced.last_synced_at.is_some());
+        // 停用的记录不加入队列
+        let skipped = record_model.get_record(3).await?.unwrap();
+        assert_eq!(skipped.ip, "198.51.100.1");
+        assert!(skipped.skip_reason.is_some());
+        // 文档地址未显式放行，不发布
+        let guarded = record_model.get_record(4).await?.unwrap();
+        assert_eq!(guarded.ip, "198.51.100.1");
+        assert!(guarded.skip_reason.unwrap().contains("198.51.100.30"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_change_cap_delays_update() -> ItdResult<()> {
+        use crate::ipaddr::confirm::{set_confirm_config, ConfirmConfig};
+        use crate::ipaddr::source::set_source_config;
+        use crate::model::sync_job::{JOB_DONE, JOB_PENDING};
+        let db = crate::tests::memory_db().await;
+        crate::tests::local_commands();
+        let config: SourceConfig =
+            serde_json::from_str(r#"{"kind":"command","name":"echo50","frequency":0}"#)?;
+        set_source_config(&db, &config).await?;
+        set_confirm_config(&db, &ConfirmConfig { max_changes_per_hour: 1, ..Default::default() }).await?;
+        let appid = crate::tests::insert_custom_app(&db, "http://127.0.0.1:9/{record_id}").await?;
+        sqlx::query(
+            r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id,allow_reserved)
+               VALUES (1,?,'a','example.com','A','198.51.100.1',1,1)"#,
+        )
+        .bind(appid)
+        .execute(&db)
+        .await?;
+        // 半小时前已变更过一次
+        let job_model = SyncJobs::new(&db);
+        let done = job_model.enqueue(1, "198.51.100.1").await?;
+        job_model.set_status(done, "198.51.100.1", JOB_DONE, 1, None, None).await?;
+        sqlx::query(r#"UPDATE sync_job SET updated_at = ? WHERE id = ?"#)
+            .bind(Local::now().naive_local() - TimeDelta::minutes(30))
+            .bind(done)
             .execute(&db)
             .await?;
-            let action = PodAction::new(&db, item.appid).await?;
-            let _ = action
-                .modify_record(
-                    &item.host,
-                    &item.domain,
-                    item.record_id,
-                    &item.ip_type,
-                    &ip_value,
-                    600,
-                )
-                .await?;
-        }
+        let ip_state = Arc::new(RwLock::new(IpState::default()));
+        task(db.clone(), ip_state, SourceStates::default(), false).await?;
+        let jobs = job_model.list(Some(JOB_PENDING.to_string()), 1).await?;
+        assert_eq!(jobs.count, 1);
+        let delay = jobs.data[0].next_attempt_at - Local::now().naive_local();
+        assert!(delay > TimeDelta::minutes(25) && delay <= TimeDelta::minutes(30));
+        assert!(Records::new(&db).get_record(1).await?.unwrap().skip_reason.is_some());
+        Ok(())
     }
-    Ok(())
 }
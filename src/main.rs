@@ -3,33 +3,66 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 use tracing::{error, info};
+use serde::Serialize;
 use sqlx::sqlite::SqlitePool;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, Notify, RwLock};
 use tokio::time::sleep;
 use tokio::sync::broadcast::channel;
 
 use crate::error::ItdResult;
 use crate::utils::log_setup;
 use crate::web::main::http_server;
-use crate::ipaddr::watch::task;
+use crate::ipaddr::netlink;
+use crate::model::ip_state::{IpStateModel, DEFAULT_SOURCE};
+use crate::ipaddr::source::SourceStates;
+use crate::ipaddr::watch::task_exclusive;
+use crate::queue::run::SyncRuns;
+use crate::queue::limiter::RateLimiter;
 
 mod dnspod;
 mod error;
+mod failover;
 mod ipaddr;
+mod migration;
 mod model;
+mod notify;
+mod provider;
+mod queue;
 mod utils;
+mod verify;
 mod web;
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct IpState {
     ipv4: Option<String>,
     ipv4_updated_at: i64,
     ipv6: Option<String>,
     ipv6_updated_at: i64,
+    /// IPv6 地址的选择原因
+    ipv6_reason: Option<String>,
+    /// 出口网络的ASN和运营商，仅部分IP来源提供
+    ipv4_asn: Option<i64>,
+    ipv4_isp: Option<String>,
+    ipv6_asn: Option<i64>,
+    ipv6_isp: Option<String>,
+    /// WAN 地址与公网地址不同时的运营商级NAT提示
+    nat_warning: Option<String>,
 }
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub db: SqlitePool,
     pub ip_state: Arc<RwLock<IpState>>,
+    /// 各命名IP来源的检测状态
+    pub source_states: SourceStates,
+    /// 唤醒后台任务立即检测
+    pub notify: Arc<Notify>,
+    /// 手动触发的检测和同步
+    pub runs: SyncRuns,
+    /// 服务商请求限速，所有调用服务商接口的地方共用
+    pub limiter: Arc<RateLimiter>,
+    /// 同步队列同一时间只有一个 drain 在处理
+    pub draining: Arc<Mutex<()>>,
+    /// IP检测同一时间只有一个在运行，后台任务和手动运行共用
+    pub detecting: Arc<Mutex<()>>,
 }
 pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = ItdResult<T>> + Send + 'a>>;
 
@@ -39,25 +72,92 @@ async fn main() {
     info!("Welcome to Ip Tell DnsPod!");
     
     let app_state = get_app_state().await;
-    let ip_state = app_state.ip_state.clone();
     let db = app_state.db.clone();
     let (shutdown_tx, shutdown_rx) = channel::<()>(1);
     
+    let notify = app_state.notify.clone();
+    match netlink::start(&db, notify.clone()).await {
+        Ok(true) => {}
+        Ok(false) => info!("Netlink address watcher disabled"),
+        Err(err) => error!("Netlink address watcher failed: {}", err),
+    }
+    
     let handle = tokio::spawn({
         let mut rx = shutdown_tx.subscribe();
+        let app_state = app_state.clone();
         async move {
+            // 被地址变化事件唤醒时忽略检测频率
+            let mut force = false;
             loop {
                 tokio::select! {
                     _ = rx.recv() => {
                         info!("Background task received shutdown signal");
                         break;
                     }
-                    _ = async {
-                        let handle = task(db.clone(), ip_state.clone());
-                        if let Err(err) = handle.await {
+                    woken = async {
+                        if let Err(err) = task_exclusive(&app_state, force).await {
                             error!("Task failed: {}", err);
                         }
-                        sleep(Duration::from_secs(60)).await;
+                        tokio::select! {
+                            _ = sleep(Duration::from_secs(60)) => false,
+                            _ = notify.notified() => true,
+                        }
+                    } => {
+                        force = woken;
+                    }
+                }
+            }
+        }
+    });
+    // 故障切换探测，每个切换组按自己的间隔运行
+    tokio::spawn({
+        let mut rx = shutdown_tx.subscribe();
+        let app_state = app_state.clone();
+        async move {
+            loop {
+                tokio::select! {
+                    _ = rx.recv() => break,
+                    _ = async {
+                        if let Err(err) = failover::task(&app_state.db, &app_state.ip_state, &app_state.source_states, &app_state.limiter).await {
+                            error!("Failover task failed: {}", err);
+                        }
+                        sleep(Duration::from_secs(5)).await;
+                    } => {}
+                }
+            }
+        }
+    });
+    // Webhook 通知，投递到期的事件
+    tokio::spawn({
+        let mut rx = shutdown_tx.subscribe();
+        let db = app_state.db.clone();
+        async move {
+            loop {
+                tokio::select! {
+                    _ = rx.recv() => break,
+                    _ = async {
+                        if let Err(err) = notify::drain(&db).await {
+                            error!("Webhook delivery failed: {}", err);
+                        }
+                        sleep(Duration::from_secs(5)).await;
+                    } => {}
+                }
+            }
+        }
+    });
+    // 同步队列，处理到期的记录更新
+    tokio::spawn({
+        let mut rx = shutdown_tx.subscribe();
+        let app_state = app_state.clone();
+        async move {
+            loop {
+                tokio::select! {
+                    _ = rx.recv() => break,
+                    _ = async {
+                        if let Err(err) = queue::drain_exclusive(&app_state).await {
+                            error!("Sync queue failed: {}", err);
+                        }
+                        sleep(Duration::from_secs(5)).await;
                     } => {}
                 }
             }
@@ -79,20 +179,57 @@ pub async fn get_app_state() -> Arc<AppState> {
         ipv4_updated_at: 0,
         ipv6: None,
         ipv6_updated_at: 0,
+        ipv6_reason: None,
+        ipv4_asn: None,
+        ipv4_isp: None,
+        ipv6_asn: None,
+        ipv6_isp: None,
+        nat_warning: None,
     }));
+    let source_states = SourceStates::default();
+    let notify = Arc::new(Notify::new());
     let db = get_conn().await;
     if db.is_err() {
         info!("db connect failed");
         return Arc::new(AppState {
             db: get_conn().await.unwrap(),
             ip_state,
+            source_states,
+            notify,
+            runs: SyncRuns::default(),
+            limiter: Arc::default(),
+            draining: Arc::default(),
+            detecting: Arc::default(),
         });
     }
     let db = db.unwrap();
+    // 旧版本数据库补齐新增的表和列
+    if let Err(e) = migration::migrate(&db).await {
+        error!("migrate db failed: {}", e);
+    }
+    // 恢复上次保存的IP状态，避免重启后把首次检测当作变化
+    match IpStateModel::new(&db).load_all().await {
+        Ok(mut states) => {
+            if let Some(state) = states.remove(DEFAULT_SOURCE) {
+                *ip_state.write().await = state;
+            }
+            let mut sources = source_states.write().await;
+            for (source, state) in states {
+                sources.insert(source, Arc::new(RwLock::new(state)));
+            }
+        }
+        Err(e) => error!("load ip state failed: {}", e),
+    }
 
     let share_state = Arc::new(AppState {
         db: db.clone(),
         ip_state: ip_state.clone(),
+        source_states: source_states.clone(),
+        notify: notify.clone(),
+        runs: SyncRuns::default(),
+        limiter: Arc::default(),
+        draining: Arc::default(),
+        detecting: Arc::default(),
     });
     share_state
 }
@@ -118,4 +255,70 @@ pub mod tests {
         let secret_key = env_map.get("secret_key").unwrap().to_string();
         return (api_id, secret_key);
     }
+    /// 创建按 init.sql 初始化的内存数据库
+    pub async fn memory_db() -> sqlx::SqlitePool {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::raw_sql(include_str!("migration/init.sql"))
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool
+    }
+    /// 使用指定数据库的应用状态，IP状态为空
+    pub fn app_state(db: sqlx::SqlitePool) -> std::sync::Arc<crate::AppState> {
+        std::sync::Arc::new(crate::AppState {
+            db,
+            ip_state: Default::default(),
+            source_states: Default::default(),
+            notify: Default::default(),
+            runs: Default::default(),
+            limiter: Default::default(),
+            draining: Default::default(),
+            detecting: Default::default(),
+        })
+    }
+    /// 写入测试用的本地命令文件，命令 echoN 输出 198.51.100.N
+    pub fn local_commands() {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            let commands = (0..100)
+                .map(|n| (format!("echo{}", n), serde_json::json!({ "command": format!("echo 198.51.100.{}", n) })))
+                .collect::<serde_json::Map<_, _>>();
+            let path = env::temp_dir().join(format!("itd_commands_{}.json", std::process::id()));
+            std::fs::write(&path, serde_json::Value::Object(commands).to_string()).unwrap();
+            env::set_var(crate::ipaddr::command::COMMANDS_ENV, &path);
+        });
+    }
+    /// 添加自定义服务商应用，所有请求都发到 url
+    pub async fn insert_custom_app(db: &sqlx::SqlitePool, url: &str) -> crate::error::ItdResult<i64> {
+        use crate::provider::custom::{CustomConfig, RequestTemplate};
+        let template = RequestTemplate {
+            method: "PUT".to_string(),
+            url: url.to_string(),
+            headers: HashMap::new(),
+            body: Some("{ip}".to_string()),
+            success: None,
+            record_id: None,
+        };
+        let config = CustomConfig {
+            create: template.clone(),
+            update: template.clone(),
+            delete: template,
+        };
+        let secret = crate::utils::encrypt_data(b"secret".to_vec())?;
+        let result = sqlx::query(
+            r#"INSERT INTO user_apps (uid,title,secret_id,secret_key,provider,provider_config)
+               VALUES (1,'mirror',?,?,'custom',?)"#,
+        )
+        .bind(&secret)
+        .bind(&secret)
+        .bind(serde_json::to_string(&config)?)
+        .execute(db)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
 }
@@ -1,38 +1,207 @@
-CREATE TABLE "user" (
-  "id" integer PRIMARY KEY AUTOINCREMENT,
-  "username" varchar(255) NOT NULL,
-  "password" varchar(255) NOT NULL,
-  "created_at" datetime DEFAULT CURRENT_TIMESTAMP,
-  "updated_at" datetime DEFAULT CURRENT_TIMESTAMP,
-  "status" tinyint(1) DEFAULT 1
-);
-CREATE TABLE "user_apps" (
-  "id" integer PRIMARY KEY AUTOINCREMENT,
-  "uid" integer NOT NULL,
-  "title" varchar(255) NOT NULL,
-  "secret_id" varchar(255) NOT NULL,
-  "secret_key" varchar(255) NOT NULL,
-  "created_at" datetime DEFAULT CURRENT_TIMESTAMP,
-  "updated_at" datetime DEFAULT CURRENT_TIMESTAMP,
-  "status" tinyint(1) DEFAULT 1
-);
-
-CREATE TABLE "user_domain" (
-  "id" integer PRIMARY KEY AUTOINCREMENT,
-  "appid" integer NOT NULL,
-  "host" varchar(255) NOT NULL,
-  "domain" varchar(255) NOT NULL,
-  "ip_type" varchar(255) DEFAULT 1,
-  "ip" varchar(255) NOT NULL,
-  "record_id" integer NOT NULL,
-  "weight" integer DEFAULT 1,
-  "ttl" integer DEFAULT 600
-  "created_at" datetime DEFAULT CURRENT_TIMESTAMP,
-  "updated_at" datetime DEFAULT CURRENT_TIMESTAMP,
-);
--- Add up migration script here
-create table setting (
-    "key" varchar(255) primary key,
-    "value" varchar(255) ,
-    description varchar(255)
-);
\ No newline at end of file
+CREATE TABLE IF NOT EXISTS "user" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "username" varchar(255) NOT NULL,
+  "password" varchar(255) NOT NULL,
+  "created_at" datetime DEFAULT CURRENT_TIMESTAMP,
+  "updated_at" datetime DEFAULT CURRENT_TIMESTAMP,
+  "status" tinyint(1) DEFAULT 1
+);
+CREATE TABLE IF NOT EXISTS "user_apps" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "uid" integer NOT NULL,
+  "title" varchar(255) NOT NULL,
+  "secret_id" varchar(255) NOT NULL,
+  "secret_key" varchar(255) NOT NULL,
+  "provider" varchar(32) DEFAULT 'dnspod',
+  "provider_config" text,
+  "bind" text,
+  "created_at" datetime DEFAULT CURRENT_TIMESTAMP,
+  "updated_at" datetime DEFAULT CURRENT_TIMESTAMP,
+  "status" tinyint(1) DEFAULT 1
+);
+
+CREATE TABLE IF NOT EXISTS "user_domain" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "appid" integer NOT NULL,
+  "host" varchar(255) NOT NULL,
+  "domain" varchar(255) NOT NULL,
+  "ip_type" varchar(255) DEFAULT 1,
+  "ip" varchar(255) NOT NULL,
+  "record_id" varchar(255) NOT NULL,
+  "weight" integer DEFAULT 1,
+  "ttl" integer DEFAULT 600,
+  "interface_id" varchar(64),
+  "prefix_len" integer DEFAULT 64,
+  "source" varchar(64),
+  "verify_status" varchar(16),
+  "verify_detail" text,
+  "propagation_ms" integer,
+  "verified_at" datetime,
+  "last_synced_at" datetime,
+  "last_attempt_at" datetime,
+  "last_error" text,
+  "consecutive_failures" integer NOT NULL DEFAULT 0,
+  "skip_reason" text,
+  "status" tinyint(1) DEFAULT 1,
+  "allow_reserved" tinyint(1) NOT NULL DEFAULT 0,
+  "line" varchar(32),
+  "created_at" datetime DEFAULT CURRENT_TIMESTAMP,
+  "updated_at" datetime DEFAULT CURRENT_TIMESTAMP
+);
+-- Add up migration script here
+create table if not exists setting (
+    "key" varchar(255) primary key,
+    "value" varchar(255) ,
+    description varchar(255)
+);
+-- 同一条解析记录同步到多个服务商，主目标仍为 user_domain.appid/record_id
+CREATE TABLE IF NOT EXISTS "record_target" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "domain_id" integer NOT NULL,
+  "appid" integer NOT NULL,
+  "record_id" varchar(255) NOT NULL,
+  "sync_status" varchar(32) NOT NULL DEFAULT 'pending',
+  "last_error" text,
+  "synced_at" datetime,
+  "created_at" datetime DEFAULT CURRENT_TIMESTAMP
+);
+
+-- IP回显服务的健康统计
+CREATE TABLE IF NOT EXISTS "ip_echo_health" (
+  "url" varchar(255) PRIMARY KEY,
+  "success_count" integer NOT NULL DEFAULT 0,
+  "failure_count" integer NOT NULL DEFAULT 0,
+  "last_ip" varchar(64),
+  "last_error" text,
+  "last_latency_ms" integer,
+  "updated_at" datetime DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 命名的IP来源配置，解析记录通过 user_domain.source 引用
+CREATE TABLE IF NOT EXISTS "ip_source" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "name" varchar(64) NOT NULL UNIQUE,
+  "config" text NOT NULL,
+  "created_at" datetime DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 各IP来源最近一次检测到的地址，重启后恢复
+CREATE TABLE IF NOT EXISTS "ip_state" (
+  "source" varchar(64) PRIMARY KEY,
+  "ipv4" varchar(64),
+  "ipv4_updated_at" integer NOT NULL DEFAULT 0,
+  "ipv6" varchar(64),
+  "ipv6_updated_at" integer NOT NULL DEFAULT 0,
+  "ipv6_reason" text,
+  "ipv4_asn" integer,
+  "ipv4_isp" varchar(255),
+  "ipv6_asn" integer,
+  "ipv6_isp" varchar(255),
+  "updated_at" datetime DEFAULT CURRENT_TIMESTAMP
+);
+
+-- IP变化历史
+CREATE TABLE IF NOT EXISTS "ip_history" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "address" varchar(64) NOT NULL,
+  "family" varchar(8) NOT NULL,
+  "source" varchar(64) NOT NULL,
+  "asn" integer,
+  "isp" varchar(255),
+  "detected_at" datetime DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 记录的故障切换组，主值探测失败时切换到备用值
+CREATE TABLE IF NOT EXISTS "failover_group" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "domain_id" integer NOT NULL UNIQUE,
+  "primary_value" varchar(255),
+  "primary_source" varchar(64),
+  "backup_value" varchar(255),
+  "backup_source" varchar(64),
+  "probe_type" varchar(16) NOT NULL DEFAULT 'tcp',
+  "probe_port" integer NOT NULL,
+  "probe_path" varchar(255),
+  "interval" integer NOT NULL DEFAULT 30,
+  "timeout" integer NOT NULL DEFAULT 3,
+  "fail_threshold" integer NOT NULL DEFAULT 3,
+  "recover_threshold" integer NOT NULL DEFAULT 3,
+  "active" varchar(16) NOT NULL DEFAULT 'primary',
+  "fail_count" integer NOT NULL DEFAULT 0,
+  "success_count" integer NOT NULL DEFAULT 0,
+  "last_probe_at" datetime,
+  "created_at" datetime DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 故障切换记录
+CREATE TABLE IF NOT EXISTS "failover_switch" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "group_id" integer NOT NULL,
+  "from_value" varchar(255) NOT NULL,
+  "to_value" varchar(255) NOT NULL,
+  "active" varchar(16) NOT NULL,
+  "reason" text NOT NULL,
+  "created_at" datetime DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 记录同步队列，失败后按指数退避重试
+CREATE TABLE IF NOT EXISTS "sync_job" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "domain_id" integer NOT NULL,
+  "value" varchar(255) NOT NULL,
+  "status" varchar(16) NOT NULL DEFAULT 'pending',
+  "attempts" integer NOT NULL DEFAULT 0,
+  "next_attempt_at" datetime NOT NULL,
+  "last_error" text,
+  "created_at" datetime NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  "updated_at" datetime
+);
+
+-- 新地址的确认状态，连续检测到或持续一段时间后才发布
+CREATE TABLE IF NOT EXISTS "ip_confirm" (
+  "source" varchar(64) NOT NULL,
+  "family" varchar(8) NOT NULL,
+  "confirmed" varchar(64),
+  "candidate" varchar(64),
+  "seen_count" integer NOT NULL DEFAULT 0,
+  "first_seen_at" integer NOT NULL DEFAULT 0,
+  PRIMARY KEY ("source", "family")
+);
+
+-- 用户的 Webhook 通知目标，events 为逗号分隔的订阅事件
+CREATE TABLE IF NOT EXISTS "webhook" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "uid" integer NOT NULL,
+  "url" varchar(1024) NOT NULL,
+  "secret" varchar(255) NOT NULL,
+  "events" text NOT NULL,
+  "failure_threshold" integer NOT NULL DEFAULT 3,
+  "status" tinyint(1) DEFAULT 1,
+  "created_at" datetime DEFAULT CURRENT_TIMESTAMP,
+  "updated_at" datetime DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Webhook 投递记录，失败后按指数退避重试
+CREATE TABLE IF NOT EXISTS "webhook_delivery" (
+  "id" integer PRIMARY KEY AUTOINCREMENT,
+  "webhook_id" integer NOT NULL,
+  "event" varchar(64) NOT NULL,
+  "payload" text NOT NULL,
+  "status" varchar(16) NOT NULL DEFAULT 'pending',
+  "attempts" integer NOT NULL DEFAULT 0,
+  "next_attempt_at" datetime NOT NULL,
+  "response_status" integer,
+  "last_error" text,
+  "created_at" datetime NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  "updated_at" datetime
+);
+
+-- 用户的邮件通知设置，recipients 和 events 为逗号分隔
+CREATE TABLE IF NOT EXISTS "notify_email" (
+  "uid" integer PRIMARY KEY,
+  "recipients" text NOT NULL,
+  "events" text NOT NULL,
+  "failure_threshold" integer NOT NULL DEFAULT 3,
+  "status" tinyint(1) DEFAULT 1,
+  "updated_at" datetime DEFAULT CURRENT_TIMESTAMP
+);
@@ -1 +1,133 @@
-
+//! 数据库迁移
+//! init.sql 只创建不存在的表，旧版本表中缺少的列在启动时按 COLUMNS 补齐，可以重复执行
+use crate::error::ItdResult;
+use sqlx::SqlitePool;
+use tracing::info;
+
+/// 在旧版本表上新增的列 (表名, 列名, 定义)
+/// SQLite 添加的列不能是主键或唯一列，NOT NULL 必须带默认值
+const COLUMNS: [(&str, &str, &str); 18] = [
+    ("user_apps", "provider", "varchar(32) DEFAULT 'dnspod'"),
+    ("user_apps", "provider_config", "text"),
+    ("user_apps", "bind", "text"),
+    ("user_domain", "interface_id", "varchar(64)"),
+    ("user_domain", "prefix_len", "integer DEFAULT 64"),
+    ("user_domain", "source", "varchar(64)"),
+    ("user_domain", "verify_status", "varchar(16)"),
+    ("user_domain", "verify_detail", "text"),
+    ("user_domain", "propagation_ms", "integer"),
+    ("user_domain", "verified_at", "datetime"),
+    ("user_domain", "last_synced_at", "datetime"),
+    ("user_domain", "last_attempt_at", "datetime"),
+    ("user_domain", "last_error", "text"),
+    ("user_domain", "consecutive_failures", "integer NOT NULL DEFAULT 0"),
+    ("user_domain", "skip_reason", "text"),
+    ("user_domain", "status", "tinyint(1) DEFAULT 1"),
+    ("user_domain", "allow_reserved", "tinyint(1) NOT NULL DEFAULT 0"),
+    ("user_domain", "line", "varchar(32)"),
+];
+
+/// 创建缺少的表并补齐缺少的列
+pub async fn migrate(db: &SqlitePool) -> ItdResult<()> {
+    sqlx::raw_sql(include_str!("init.sql")).execute(db).await?;
+    for (table, column, definition) in COLUMNS {
+        let exists: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?"#)
+            .bind(table)
+            .bind(column)
+            .fetch_one(db)
+            .await?;
+        if exists == 0 {
+            info!("Migrate: add column {}.{}", table, column);
+            sqlx::raw_sql(&format!(r#"ALTER TABLE "{}" ADD COLUMN "{}" {}"#, table, column, definition))
+                .execute(db)
+                .await?;
+        }
+    }
+    migrate_record_id(db).await
+}
+
+/// 旧版本的 user_domain.record_id 是整数列，服务商返回的文本id（如 "00123"）会被转成数字，
+/// 按 init.sql 重建表并复制数据，把列改为文本
+async fn migrate_record_id(db: &SqlitePool) -> ItdResult<()> {
+    let kind: String = sqlx::query_scalar(r#"SELECT type FROM pragma_table_info('user_domain') WHERE name = 'record_id'"#)
+        .fetch_one(db)
+        .await?;
+    if !kind.to_uppercase().contains("INT") {
+        return Ok(());
+    }
+    info!("Migrate: rebuild user_domain with text record_id");
+    let mut tx = db.begin().await?;
+    sqlx::raw_sql(r#"ALTER TABLE "user_domain" RENAME TO "user_domain_old""#)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::raw_sql(include_str!("init.sql")).execute(&mut *tx).await?;
+    let columns: Vec<String> = sqlx::query_scalar(r#"SELECT name FROM pragma_table_info('user_domain') ORDER BY cid"#)
+        .fetch_all(&mut *tx)
+        .await?;
+    let select = columns
+        .iter()
+        .map(|column| match column.as_str() {
+            "record_id" => r#"CAST("record_id" AS TEXT)"#.to_string(),
+            _ => format!(r#""{}""#, column),
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+    let insert = columns.iter().map(|column| format!(r#""{}""#, column)).collect::<Vec<String>>().join(",");
+    sqlx::raw_sql(&format!(
+        r#"INSERT INTO "user_domain" ({}) SELECT {} FROM "user_domain_old"; DROP TABLE "user_domain_old";"#,
+        insert, select
+    ))
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_migrate_old_database_works() -> ItdResult<()> {
+        let db = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        // 最初版本的表结构
+        sqlx::raw_sql(
+            r#"CREATE TABLE "user_apps" (
+                 "id" integer PRIMARY KEY AUTOINCREMENT, "uid" integer NOT NULL, "title" varchar(255) NOT NULL,
+                 "secret_id" varchar(255) NOT NULL, "secret_key" varchar(255) NOT NULL,
+                 "created_at" datetime DEFAULT CURRENT_TIMESTAMP, "updated_at" datetime DEFAULT CURRENT_TIMESTAMP,
+                 "status" tinyint(1) DEFAULT 1);
+               CREATE TABLE "user_domain" (
+                 "id" integer PRIMARY KEY AUTOINCREMENT, "appid" integer NOT NULL, "host" varchar(255) NOT NULL,
+                 "domain" varchar(255) NOT NULL, "ip_type" varchar(255) DEFAULT 1, "ip" varchar(255) NOT NULL,
+                 "record_id" integer NOT NULL, "weight" integer DEFAULT 1, "ttl" integer DEFAULT 600,
+                 "created_at" datetime DEFAULT CURRENT_TIMESTAMP, "updated_at" datetime DEFAULT CURRENT_TIMESTAMP);
+               INSERT INTO user_apps (uid,title,secret_id,secret_key) VALUES (1,'pod','id','key');
+               INSERT INTO user_domain (appid,host,domain,ip_type,ip,record_id) VALUES (1,'www','example.com','A','198.51.100.1',1904088010);"#,
+        )
+        .execute(&db)
+        .await?;
+        migrate(&db).await?;
+        // 重复执行不报错
+        migrate(&db).await?;
+        let kind: String = sqlx::query_scalar(r#"SELECT type FROM pragma_table_info('user_domain') WHERE name = 'record_id'"#)
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(kind, "varchar(255)");
+        let record = crate::model::records::Records::new(&db).get_record(1).await?.unwrap();
+        assert_eq!(record.record_id, "1904088010");
+        // 重建后文本id保留前导零
+        sqlx::query(r#"UPDATE user_domain SET record_id = '00123' WHERE id = 1"#).execute(&db).await?;
+        let record = crate::model::records::Records::new(&db).get_record(1).await?.unwrap();
+        assert_eq!(record.record_id, "00123");
+        assert_eq!(record.consecutive_failures, 0);
+        assert_eq!(record.status, Some(1));
+        assert!(!record.allow_reserved);
+        let count: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM sync_job"#).fetch_one(&db).await?;
+        assert_eq!(count, 0);
+        Ok(())
+    }
+}
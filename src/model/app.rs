@@ -1,151 +1,224 @@
-use crate::add_conn;
-use crate::error::ItdResult;
-use crate::model::constants::RespMsg;
-use crate::utils::encrypt_data;
-
-use axum::Json;
-use chrono::Local;
-use chrono::NaiveDateTime;
-
-use serde::{Deserialize, Serialize};
-
-use validator::Validate;
-
-add_conn!(AppModel);
-
-#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
-pub struct AppItem {
-    pub id: i64,
-    pub uid: i64,
-    pub title: String,
-    pub secret_id: String,
-    pub secret_key: String,
-    pub created_at: Option<NaiveDateTime>,
-    pub updated_at: Option<NaiveDateTime>,
-    pub status: Option<i64>,
-}
-impl<'db> AppModel<'db> {
-    /// create app item
-    pub async fn create_app(&self, payload: AppForm) -> ItdResult<Json<AppItem>> {
-        let AppForm {
-            uid,
-            title,
-            secret_id,
-            secret_key,
-        } = payload;
-        let secret_id = encrypt_data(secret_id.as_bytes().to_vec())?;
-        //println!("secret_id: {:?}", secret_id);
-        let secret_key = encrypt_data(secret_key.as_bytes().to_vec())?;
-        let result = sqlx::query!(
-            r#"INSERT INTO user_apps (uid,title,secret_id,secret_key) VALUES (?,?,?,?)"#,
-            uid,
-            title,
-            secret_id,
-            secret_key
-        )
-        .execute(self.db)
-        .await?;
-        let now = Local::now().naive_local();
-        Ok(Json(AppItem {
-            id: result.last_insert_rowid(),
-            uid,
-            title,
-            secret_id,
-            secret_key,
-            created_at: Some(now),
-            updated_at: Some(now),
-            status: Some(1),
-        }))
-    }
-    /// update app item
-    pub async fn update_app(&self, id: i64, payload: AppForm) -> ItdResult<Json<AppItem>> {
-        let AppForm {
-            uid,
-            title,
-            secret_id,
-            secret_key,
-        } = payload;
-        let app = sqlx::query_as!(
-            AppItem,
-            r#"SELECT id,uid,title,secret_id,secret_key,created_at,updated_at,status FROM user_apps WHERE id=?"#,
-            id
-        )
-        .fetch_one(self.db)
-        .await?;
-        
-        let secret_id = if app.secret_id !=secret_id {
-            encrypt_data(secret_id.as_bytes().to_vec())?
-        }else{
-            secret_id
-        };
-        //println!("secret_id: {:?}", secret_id);
-        let secret_key = if app.secret_key != secret_key {
-            encrypt_data(secret_key.as_bytes().to_vec())?
-        }else{
-            secret_key
-        };
-        let now = Local::now().naive_local();
-        sqlx::query!(
-            r#"UPDATE user_apps SET uid=?,title=?,secret_id=?,secret_key=?,updated_at=? WHERE id=?"#,
-            uid,
-            title,
-            secret_id,
-            secret_key,
-            now,
-            id
-        )
-        .execute(self.db)
-        .await?;
-        let old = sqlx::query_as!(
-            AppItem,
-            r#"SELECT id,uid,title,secret_id,secret_key,created_at,updated_at,status FROM user_apps WHERE id=?"#,
-            id
-        )
-        .fetch_one(self.db)
-        .await?;
-        Ok(Json(old))
-    }
-    /// delete app item
-    pub async fn delete_app(&self, id: i64) -> ItdResult<Json<RespMsg>> {
-        sqlx::query!(r#"DELETE FROM user_apps WHERE id=?"#, id)
-            .execute(self.db)
-            .await?;
-        Ok(Json(RespMsg {
-            code: Some(1000),
-            message: "删除成功".to_string(),
-        }))
-    }
-    /// set app status
-    pub async fn set_status(&self, id: i64, status: i64) -> ItdResult<Json<RespMsg>> {
-        sqlx::query!(r#"UPDATE user_apps SET status=? WHERE id=?"#, status, id)
-            .execute(self.db)
-            .await?;
-        Ok(Json(RespMsg {
-            code: Some(1000),
-            message: "设置成功".to_string(),
-        }))
-    }
-}
-
-#[derive(Deserialize, Debug, Validate)]
-pub struct AppForm {
-    pub uid: i64,
-    pub title: String,
-    pub secret_id: String,
-    pub secret_key: String,
-}
-#[derive(Deserialize, Debug, Validate)]
-pub struct StatusForm {
-    pub status: i64,
-}
-#[cfg(test)]
-mod tests {
-    use chrono::Local;
- 
-    #[test]
-    pub fn it_test_now_time() {
-        let now = Local::now().naive_local();
-        println!("now: {:?}", now);
-    }
-
-}
+use crate::add_conn;
+use crate::error::ItdResult;
+use crate::ipaddr::bind::Bind;
+use crate::model::constants::RespMsg;
+use crate::provider::custom::CustomConfig;
+use crate::provider::{PROVIDER_CUSTOM, PROVIDER_DNSPOD};
+use crate::utils::encrypt_data;
+use crate::err;
+
+use axum::Json;
+use chrono::Local;
+use chrono::NaiveDateTime;
+
+use serde::{Deserialize, Serialize};
+
+use validator::Validate;
+
+add_conn!(AppModel);
+
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct AppItem {
+    pub id: i64,
+    pub uid: i64,
+    pub title: String,
+    pub secret_id: String,
+    pub secret_key: String,
+    pub provider: Option<String>,
+    pub provider_config: Option<String>,
+    /// 服务商请求的出口绑定(JSON)
+    pub bind: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub status: Option<i64>,
+}
+impl<'db> AppModel<'db> {
+    /// create app item
+    pub async fn create_app(&self, payload: AppForm) -> ItdResult<Json<AppItem>> {
+        let AppForm {
+            uid,
+            title,
+            secret_id,
+            secret_key,
+            provider,
+            provider_config,
+            bind,
+        } = payload;
+        let (provider, provider_config) = check_provider(provider, provider_config)?;
+        let bind = check_bind(bind)?;
+        let secret_id = encrypt_data(secret_id.as_bytes().to_vec())?;
+        //println!("secret_id: {:?}", secret_id);
+        let secret_key = encrypt_data(secret_key.as_bytes().to_vec())?;
+        let result = sqlx::query!(
+            r#"INSERT INTO user_apps (uid,title,secret_id,secret_key,provider,provider_config,bind) VALUES (?,?,?,?,?,?,?)"#,
+            uid,
+            title,
+            secret_id,
+            secret_key,
+            provider,
+            provider_config,
+            bind
+        )
+        .execute(self.db)
+        .await?;
+        let now = Local::now().naive_local();
+        Ok(Json(AppItem {
+            id: result.last_insert_rowid(),
+            uid,
+            title,
+            secret_id,
+            secret_key,
+            provider: Some(provider),
+            provider_config,
+            bind,
+            created_at: Some(now),
+            updated_at: Some(now),
+            status: Some(1),
+        }))
+    }
+    /// update app item
+    pub async fn update_app(&self, id: i64, payload: AppForm) -> ItdResult<Json<AppItem>> {
+        let AppForm {
+            uid,
+            title,
+            secret_id,
+            secret_key,
+            provider,
+            provider_config,
+            bind,
+        } = payload;
+        let (provider, provider_config) = check_provider(provider, provider_config)?;
+        let bind = check_bind(bind)?;
+        let app = sqlx::query_as!(
+            AppItem,
+            r#"SELECT id,uid,title,secret_id,secret_key,provider,provider_config,bind,created_at,updated_at,status FROM user_apps WHERE id=?"#,
+            id
+        )
+        .fetch_one(self.db)
+        .await?;
+        
+        let secret_id = if app.secret_id !=secret_id {
+            encrypt_data(secret_id.as_bytes().to_vec())?
+        }else{
+            secret_id
+        };
+        //println!("secret_id: {:?}", secret_id);
+        let secret_key = if app.secret_key != secret_key {
+            encrypt_data(secret_key.as_bytes().to_vec())?
+        }else{
+            secret_key
+        };
+        let now = Local::now().naive_local();
+        sqlx::query!(
+            r#"UPDATE user_apps SET uid=?,title=?,secret_id=?,secret_key=?,provider=?,provider_config=?,bind=?,updated_at=? WHERE id=?"#,
+            uid,
+            title,
+            secret_id,
+            secret_key,
+            provider,
+            provider_config,
+            bind,
+            now,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        let old = sqlx::query_as!(
+            AppItem,
+            r#"SELECT id,uid,title,secret_id,secret_key,provider,provider_config,bind,created_at,updated_at,status FROM user_apps WHERE id=?"#,
+            id
+        )
+        .fetch_one(self.db)
+        .await?;
+        Ok(Json(old))
+    }
+    /// delete app item
+    pub async fn delete_app(&self, id: i64) -> ItdResult<Json<RespMsg>> {
+        sqlx::query!(r#"DELETE FROM user_apps WHERE id=?"#, id)
+            .execute(self.db)
+            .await?;
+        Ok(Json(RespMsg {
+            code: Some(1000),
+            message: "删除成功".to_string(),
+        }))
+    }
+    /// set app status
+    pub async fn set_status(&self, id: i64, status: i64) -> ItdResult<Json<RespMsg>> {
+        sqlx::query!(r#"UPDATE user_apps SET status=? WHERE id=?"#, status, id)
+            .execute(self.db)
+            .await?;
+        Ok(Json(RespMsg {
+            code: Some(1000),
+            message: "设置成功".to_string(),
+        }))
+    }
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct AppForm {
+    pub uid: i64,
+    pub title: String,
+    pub secret_id: String,
+    pub secret_key: String,
+    /// 服务商类型 dnspod | custom，默认为 dnspod
+    pub provider: Option<String>,
+    /// 自定义服务商的请求模板配置
+    pub provider_config: Option<CustomConfig>,
+    /// 服务商请求的出口绑定，多WAN环境下指定API请求从哪条线路发出
+    #[serde(default)]
+    pub bind: Bind,
+}
+/// 校验出口绑定，未绑定时不入库
+fn check_bind(bind: Bind) -> ItdResult<Option<String>> {
+    if bind.is_empty() {
+        return Ok(None);
+    }
+    bind.client_builder()?.build()?;
+    Ok(Some(serde_json::to_string(&bind)?))
+}
+/// 校验服务商配置，返回入库的 (provider, provider_config)
+fn check_provider(
+    provider: Option<String>,
+    provider_config: Option<CustomConfig>,
+) -> ItdResult<(String, Option<String>)> {
+    let provider = provider.unwrap_or(PROVIDER_DNSPOD.to_string());
+    match provider.as_str() {
+        PROVIDER_DNSPOD => Ok((provider, None)),
+        PROVIDER_CUSTOM => {
+            let config = match provider_config {
+                Some(config) => config,
+                None => return err!("自定义服务商缺少请求模板配置"),
+            };
+            config.check()?;
+            let config = serde_json::to_string(&config)?;
+            Ok((provider, Some(config)))
+        }
+        _ => err!(format!("不支持的服务商类型: {}", provider)),
+    }
+}
+#[derive(Deserialize, Debug, Validate)]
+pub struct StatusForm {
+    /// 1 启用 0 停用
+    #[validate(range(min = 0, max = 1, message = "状态只能为0或1"))]
+    pub status: i64,
+}
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+ 
+    #[test]
+    fn it_status_form_validate_works() {
+        use super::StatusForm;
+        use validator::Validate;
+        assert!(StatusForm { status: 1 }.validate().is_ok());
+        assert!(StatusForm { status: 0 }.validate().is_ok());
+        assert!(StatusForm { status: 2 }.validate().is_err());
+        assert!(StatusForm { status: -1 }.validate().is_err());
+    }
+    #[test]
+    pub fn it_test_now_time() {
+        let now = Local::now().naive_local();
+        println!("now: {:?}", now);
+    }
+
+}
@@ -1,23 +1,23 @@
-use once_cell::sync::Lazy;
-use regex::Regex;
-use serde::Serialize;
-
-pub static USERNAME_REGEX: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^[a-zA-Z0-9\-\_@]{4,}$").unwrap());
-
-pub static JWT_SECRET: &str = "EDs-ARpmZLI_eSX-LOMzt6B6abs07dmgj4sSe7woO-4";
-pub static AES_KEY: &str = "mBZV0TBF5kX8RbX2kxEX4g==";
-
-#[derive(Debug, Serialize)]
-pub struct RespMsg {
-    pub code: Option<i32>,
-    pub message: String,
-}
-
-///分页数据
-#[derive(Debug, Serialize)]
-pub struct Pagination<T> {
-    pub count: i32,
-    pub page: i32,
-    pub data: Vec<T>,
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+pub static USERNAME_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[a-zA-Z0-9\-\_@]{4,}$").unwrap());
+
+pub static JWT_SECRET: &str = "EDs-ARpmZLI_eSX-LOMzt6B6abs07dmgj4sSe7woO-4";
+pub static AES_KEY: &str = "mBZV0TBF5kX8RbX2kxEX4g==";
+
+#[derive(Debug, Serialize)]
+pub struct RespMsg {
+    pub code: Option<i32>,
+    pub message: String,
+}
+
+///分页数据
+#[derive(Debug, Serialize)]
+pub struct Pagination<T> {
+    pub count: i32,
+    pub page: i32,
+    pub data: Vec<T>,
 }
\ No newline at end of file
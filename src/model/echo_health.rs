@@ -0,0 +1,60 @@
+use crate::add_conn;
+use crate::error::ItdResult;
+use chrono::{Local, NaiveDateTime};
+use serde::Serialize;
+
+add_conn!(EchoHealthModel);
+
+/// 单个IP回显服务的健康统计
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct EchoHealth {
+    pub url: String,
+    pub success_count: i64,
+    pub failure_count: i64,
+    pub last_ip: Option<String>,
+    pub last_error: Option<String>,
+    pub last_latency_ms: Option<i64>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl<'db> EchoHealthModel<'db> {
+    /// 记录一次请求结果，result 为获取到的IP或错误信息
+    pub async fn record(&self, url: &str, result: &Result<String, String>, latency_ms: i64) -> ItdResult<()> {
+        let now = Local::now().naive_local();
+        let (success, failure, last_ip, last_error) = match result {
+            Ok(ip) => (1, 0, Some(ip.clone()), None),
+            Err(e) => (0, 1, None, Some(e.clone())),
+        };
+        sqlx::query(
+            r#"INSERT INTO ip_echo_health (url,success_count,failure_count,last_ip,last_error,last_latency_ms,updated_at)
+               VALUES (?,?,?,?,?,?,?)
+               ON CONFLICT(url) DO UPDATE SET
+                 success_count = success_count + excluded.success_count,
+                 failure_count = failure_count + excluded.failure_count,
+                 last_ip = COALESCE(excluded.last_ip, last_ip),
+                 last_error = excluded.last_error,
+                 last_latency_ms = excluded.last_latency_ms,
+                 updated_at = excluded.updated_at"#,
+        )
+        .bind(url)
+        .bind(success)
+        .bind(failure)
+        .bind(last_ip)
+        .bind(last_error)
+        .bind(latency_ms)
+        .bind(now)
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+    /// 获取所有回显服务的健康统计
+    pub async fn list(&self) -> ItdResult<Vec<EchoHealth>> {
+        let list: Vec<EchoHealth> = sqlx::query_as(
+            r#"SELECT url,success_count,failure_count,last_ip,last_error,last_latency_ms,updated_at
+               FROM ip_echo_health ORDER BY url"#,
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(list)
+    }
+}
@@ -0,0 +1,221 @@
+use crate::add_conn;
+use crate::err;
+use crate::error::ItdResult;
+use crate::model::records::Records;
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+add_conn!(FailoverModel);
+
+pub const ACTIVE_PRIMARY: &str = "primary";
+pub const ACTIVE_BACKUP: &str = "backup";
+pub const PROBE_TCP: &str = "tcp";
+pub const PROBE_HTTP: &str = "http";
+
+/// 记录的故障切换组，主值不可用时切换到备用值
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct FailoverGroup {
+    pub id: i64,
+    pub domain_id: i64,
+    /// 固定值，为空时取 primary_source 来源检测到的IP
+    pub primary_value: Option<String>,
+    pub primary_source: Option<String>,
+    pub backup_value: Option<String>,
+    pub backup_source: Option<String>,
+    /// tcp | http
+    pub probe_type: String,
+    pub probe_port: i64,
+    /// http 探测的路径
+    pub probe_path: Option<String>,
+    /// 探测间隔(秒)
+    pub interval: i64,
+    pub timeout: i64,
+    /// 连续失败次数达到后切换到备用值
+    pub fail_threshold: i64,
+    /// 连续成功次数达到后切回主值
+    pub recover_threshold: i64,
+    /// primary | backup
+    pub active: String,
+    pub fail_count: i64,
+    pub success_count: i64,
+    pub last_probe_at: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+/// 切换记录
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct FailoverSwitch {
+    pub id: i64,
+    pub group_id: i64,
+    pub from_value: String,
+    pub to_value: String,
+    pub active: String,
+    pub reason: String,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+/// 切换组及最近的切换记录
+#[derive(Debug, Clone, Serialize)]
+pub struct FailoverDetail {
+    pub group: FailoverGroup,
+    pub switches: Vec<FailoverSwitch>,
+}
+
+const GROUP_COLUMNS: &str = "id,domain_id,primary_value,primary_source,backup_value,backup_source,probe_type,probe_port,probe_path,interval,timeout,fail_threshold,recover_threshold,active,fail_count,success_count,last_probe_at,created_at";
+
+impl<'db> FailoverModel<'db> {
+    pub async fn list(&self) -> ItdResult<Vec<FailoverGroup>> {
+        let groups: Vec<FailoverGroup> =
+            sqlx::query_as(&format!("SELECT {} FROM failover_group ORDER BY id", GROUP_COLUMNS))
+                .fetch_all(self.db)
+                .await?;
+        Ok(groups)
+    }
+    pub async fn get(&self, id: i64) -> ItdResult<Option<FailoverGroup>> {
+        let group: Option<FailoverGroup> =
+            sqlx::query_as(&format!("SELECT {} FROM failover_group WHERE id=?", GROUP_COLUMNS))
+                .bind(id)
+                .fetch_optional(self.db)
+                .await?;
+        Ok(group)
+    }
+    pub async fn get_by_record(&self, domain_id: i64) -> ItdResult<Option<FailoverGroup>> {
+        let group: Option<FailoverGroup> =
+            sqlx::query_as(&format!("SELECT {} FROM failover_group WHERE domain_id=?", GROUP_COLUMNS))
+                .bind(domain_id)
+                .fetch_optional(self.db)
+                .await?;
+        Ok(group)
+    }
+    /// 为记录添加故障切换组，每条记录只能有一个
+    pub async fn add(&self, domain_id: i64, payload: FailoverForm) -> ItdResult<i64> {
+        if Records::new(self.db).get_record(domain_id).await?.is_none() {
+            return err!("Record not found");
+        }
+        if self.get_by_record(domain_id).await?.is_some() {
+            return err!("该记录已经有故障切换组");
+        }
+        if payload.primary_value.is_none() && payload.primary_source.is_none() {
+            return err!("请填写主值或主IP来源");
+        }
+        if payload.backup_value.is_none() && payload.backup_source.is_none() {
+            return err!("请填写备用值或备用IP来源");
+        }
+        let result = sqlx::query(
+            r#"INSERT INTO failover_group
+               (domain_id,primary_value,primary_source,backup_value,backup_source,probe_type,probe_port,probe_path,interval,timeout,fail_threshold,recover_threshold)
+               VALUES (?,?,?,?,?,?,?,?,?,?,?,?)"#,
+        )
+        .bind(domain_id)
+        .bind(payload.primary_value)
+        .bind(payload.primary_source)
+        .bind(payload.backup_value)
+        .bind(payload.backup_source)
+        .bind(payload.probe_type)
+        .bind(payload.probe_port)
+        .bind(payload.probe_path)
+        .bind(payload.interval.unwrap_or(30))
+        .bind(payload.timeout.unwrap_or(3))
+        .bind(payload.fail_threshold.unwrap_or(3))
+        .bind(payload.recover_threshold.unwrap_or(3))
+        .execute(self.db)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+    pub async fn delete(&self, id: i64) -> ItdResult<u64> {
+        sqlx::query(r#"DELETE FROM failover_switch WHERE group_id=?"#)
+            .bind(id)
+            .execute(self.db)
+            .await?;
+        let result = sqlx::query(r#"DELETE FROM failover_group WHERE id=?"#)
+            .bind(id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+    /// 删除记录的故障切换组
+    pub async fn delete_by_record(&self, domain_id: i64) -> ItdResult<()> {
+        if let Some(group) = self.get_by_record(domain_id).await? {
+            self.delete(group.id).await?;
+        }
+        Ok(())
+    }
+    /// 保存探测计数和当前生效的值
+    pub async fn set_probe_state(&self, id: i64, active: &str, fail_count: i64, success_count: i64) -> ItdResult<()> {
+        let now = Local::now().naive_local();
+        sqlx::query(
+            r#"UPDATE failover_group SET active=?, fail_count=?, success_count=?, last_probe_at=? WHERE id=?"#,
+        )
+        .bind(active)
+        .bind(fail_count)
+        .bind(success_count)
+        .bind(now)
+        .bind(id)
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+    pub async fn add_switch(&self, group_id: i64, from_value: &str, to_value: &str, active: &str, reason: &str) -> ItdResult<i64> {
+        let now = Local::now().naive_local();
+        let result = sqlx::query(
+            r#"INSERT INTO failover_switch (group_id,from_value,to_value,active,reason,created_at) VALUES (?,?,?,?,?,?)"#,
+        )
+        .bind(group_id)
+        .bind(from_value)
+        .bind(to_value)
+        .bind(active)
+        .bind(reason)
+        .bind(now)
+        .execute(self.db)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+    pub async fn detail(&self, domain_id: i64) -> ItdResult<Option<FailoverDetail>> {
+        let group = match self.get_by_record(domain_id).await? {
+            Some(group) => group,
+            None => return Ok(None),
+        };
+        let switches = self.switches(group.id).await?;
+        Ok(Some(FailoverDetail { group, switches }))
+    }
+    /// 获取切换记录，按时间倒序
+    pub async fn switches(&self, group_id: i64) -> ItdResult<Vec<FailoverSwitch>> {
+        let switches: Vec<FailoverSwitch> = sqlx::query_as(
+            r#"SELECT id,group_id,from_value,to_value,active,reason,created_at
+               FROM failover_switch WHERE group_id=? ORDER BY id DESC LIMIT 100"#,
+        )
+        .bind(group_id)
+        .fetch_all(self.db)
+        .await?;
+        Ok(switches)
+    }
+}
+
+fn check_probe_type(probe_type: &str) -> Result<(), ValidationError> {
+    match probe_type {
+        PROBE_TCP | PROBE_HTTP => Ok(()),
+        _ => Err(ValidationError::new("探测类型为 tcp 或 http")),
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct FailoverForm {
+    pub primary_value: Option<String>,
+    pub primary_source: Option<String>,
+    pub backup_value: Option<String>,
+    pub backup_source: Option<String>,
+    #[validate(custom(function = "check_probe_type"))]
+    pub probe_type: String,
+    #[validate(range(min = 1, max = 65535, message = "请填写正确的端口"))]
+    pub probe_port: i64,
+    pub probe_path: Option<String>,
+    #[validate(range(min = 5, message = "探测间隔至少5秒"))]
+    pub interval: Option<i64>,
+    #[validate(range(min = 1, max = 60))]
+    pub timeout: Option<i64>,
+    #[validate(range(min = 1))]
+    pub fail_threshold: Option<i64>,
+    #[validate(range(min = 1))]
+    pub recover_threshold: Option<i64>,
+}
@@ -0,0 +1,160 @@
+use crate::add_conn;
+use crate::err;
+use crate::error::ItdResult;
+use crate::ipaddr::source::SourceConfig;
+use crate::model::ip_state::DEFAULT_SOURCE;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+add_conn!(IpSourceModel);
+
+/// 命名的IP来源配置，解析记录通过 user_domain.source 引用
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct IpSource {
+    pub id: i64,
+    pub name: String,
+    /// SourceConfig 的JSON
+    pub config: String,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl IpSource {
+    pub fn source_config(&self) -> ItdResult<SourceConfig> {
+        Ok(serde_json::from_str(&self.config)?)
+    }
+}
+
+impl<'db> IpSourceModel<'db> {
+    pub async fn list(&self) -> ItdResult<Vec<IpSource>> {
+        let sources: Vec<IpSource> =
+            sqlx::query_as(r#"SELECT id,name,config,created_at FROM ip_source ORDER BY id"#)
+                .fetch_all(self.db)
+                .await?;
+        Ok(sources)
+    }
+    pub async fn get(&self, id: i64) -> ItdResult<Option<IpSource>> {
+        let source: Option<IpSource> =
+            sqlx::query_as(r#"SELECT id,name,config,created_at FROM ip_source WHERE id=?"#)
+                .bind(id)
+                .fetch_optional(self.db)
+                .await?;
+        Ok(source)
+    }
+    pub async fn find_by_name(&self, name: &str) -> ItdResult<Option<IpSource>> {
+        let source: Option<IpSource> =
+            sqlx::query_as(r#"SELECT id,name,config,created_at FROM ip_source WHERE name=?"#)
+                .bind(name)
+                .fetch_optional(self.db)
+                .await?;
+        Ok(source)
+    }
+    pub async fn add(&self, payload: IpSourceForm) -> ItdResult<i64> {
+        if self.find_by_name(&payload.name).await?.is_some() {
+            return err!("IP来源名称已存在");
+        }
+        payload.config.check()?;
+        let config = serde_json::to_string(&payload.config)?;
+        let result = sqlx::query(r#"INSERT INTO ip_source (name,config) VALUES (?,?)"#)
+            .bind(payload.name)
+            .bind(config)
+            .execute(self.db)
+            .await?;
+        Ok(result.last_insert_rowid())
+    }
+    /// 修改配置，名称被记录引用，不允许修改
+    pub async fn update(&self, id: i64, config: &SourceConfig) -> ItdResult<u64> {
+        config.check()?;
+        let config = serde_json::to_string(config)?;
+        let result = sqlx::query(r#"UPDATE ip_source SET config=? WHERE id=?"#)
+            .bind(config)
+            .bind(id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+    pub async fn delete(&self, id: i64) -> ItdResult<u64> {
+        let source = match self.get(id).await? {
+            Some(source) => source,
+            None => return err!("IP来源不存在"),
+        };
+        let used: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM user_domain WHERE source=?"#)
+            .bind(&source.name)
+            .fetch_one(self.db)
+            .await?;
+        if used > 0 {
+            return err!(format!("IP来源被 {} 条解析记录使用", used));
+        }
+        let result = sqlx::query(r#"DELETE FROM ip_source WHERE id=?"#)
+            .bind(id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// 全局来源的状态和历史以 DEFAULT_SOURCE 保存，命名来源不能使用这个名称
+fn check_name(name: &str) -> Result<(), ValidationError> {
+    if name == DEFAULT_SOURCE {
+        return Err(ValidationError::new("来源名称不能是 default"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct IpSourceForm {
+    #[validate(length(min = 1, max = 64, message = "请填写来源名称"), custom(function = "check_name"))]
+    pub name: String,
+    pub config: SourceConfig,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_ip_source_works() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        let model = IpSourceModel::new(&db);
+        let config: SourceConfig = serde_json::from_str(r#"{"kind":"interface","name":"wg0"}"#)?;
+        let id = model
+            .add(IpSourceForm {
+                name: "vpn".to_string(),
+                config: config.clone(),
+            })
+            .await?;
+        assert!(model
+            .add(IpSourceForm {
+                name: "vpn".to_string(),
+                config,
+            })
+            .await
+            .is_err());
+        let source = model.find_by_name("vpn").await?.unwrap();
+        assert_eq!(source.id, id);
+        assert!(matches!(source.source_config()?, SourceConfig::Interface { .. }));
+        sqlx::query(r#"INSERT INTO user_domain (appid,host,domain,ip,record_id,source) VALUES (1,'vpn','example.com','',1,'vpn')"#)
+            .execute(&db)
+            .await?;
+        assert!(model.delete(id).await.is_err());
+        sqlx::query(r#"DELETE FROM user_domain"#).execute(&db).await?;
+        assert_eq!(model.delete(id).await?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn it_ip_source_form_validate_works() -> ItdResult<()> {
+        let config: SourceConfig = serde_json::from_str(r#"{"kind":"interface","name":"wg0"}"#)?;
+        let form = IpSourceForm {
+            name: DEFAULT_SOURCE.to_string(),
+            config: config.clone(),
+        };
+        assert!(form.validate().is_err());
+        let form = IpSourceForm {
+            name: "vpn".to_string(),
+            config,
+        };
+        assert!(form.validate().is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use crate::add_conn;
+use crate::error::ItdResult;
+use crate::model::constants::Pagination;
+use crate::IpState;
+use axum::Json;
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+
+add_conn!(IpStateModel);
+
+/// 全局IP来源在 ip_state / ip_history 中的名称
+pub const DEFAULT_SOURCE: &str = "default";
+
+/// IP变化历史
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct IpHistory {
+    pub id: i64,
+    pub address: String,
+    /// ipv4 | ipv6
+    pub family: String,
+    pub source: String,
+    pub asn: Option<i64>,
+    /// 运营商，来自 ASN 注册名称
+    pub isp: Option<String>,
+    pub detected_at: NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow)]
+struct IpStateRow {
+    source: String,
+    ipv4: Option<String>,
+    ipv4_updated_at: i64,
+    ipv6: Option<String>,
+    ipv6_updated_at: i64,
+    ipv6_reason: Option<String>,
+    ipv4_asn: Option<i64>,
+    ipv4_isp: Option<String>,
+    ipv6_asn: Option<i64>,
+    ipv6_isp: Option<String>,
+}
+
+impl From<IpStateRow> for IpState {
+    fn from(row: IpStateRow) -> Self {
+        IpState {
+            ipv4: row.ipv4,
+            ipv4_updated_at: row.ipv4_updated_at,
+            ipv6: row.ipv6,
+            ipv6_updated_at: row.ipv6_updated_at,
+            ipv6_reason: row.ipv6_reason,
+            ipv4_asn: row.ipv4_asn,
+            ipv4_isp: row.ipv4_isp,
+            ipv6_asn: row.ipv6_asn,
+            ipv6_isp: row.ipv6_isp,
+            nat_warning: None,
+        }
+    }
+}
+
+impl<'db> IpStateModel<'db> {
+    /// 读取所有来源保存的IP状态
+    pub async fn load_all(&self) -> ItdResult<HashMap<String, IpState>> {
+        let rows: Vec<IpStateRow> = sqlx::query_as(
+            r#"SELECT source,ipv4,ipv4_updated_at,ipv6,ipv6_updated_at,ipv6_reason,ipv4_asn,ipv4_isp,ipv6_asn,ipv6_isp FROM ip_state"#,
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows.into_iter().map(|row| (row.source.clone(), row.into())).collect())
+    }
+    /// 保存来源的IP状态
+    pub async fn save(&self, source: &str, state: &IpState) -> ItdResult<()> {
+        let now = Local::now().naive_local();
+        sqlx::query(
+            r#"INSERT INTO ip_state (source,ipv4,ipv4_updated_at,ipv6,ipv6_updated_at,ipv6_reason,ipv4_asn,ipv4_isp,ipv6_asn,ipv6_isp,updated_at)
+               VALUES (?,?,?,?,?,?,?,?,?,?,?)
+               ON CONFLICT(source) DO UPDATE SET
+                 ipv4 = excluded.ipv4,
+                 ipv4_updated_at = excluded.ipv4_updated_at,
+                 ipv6 = excluded.ipv6,
+                 ipv6_updated_at = excluded.ipv6_updated_at,
+                 ipv6_reason = excluded.ipv6_reason,
+                 ipv4_asn = excluded.ipv4_asn,
+                 ipv4_isp = excluded.ipv4_isp,
+                 ipv6_asn = excluded.ipv6_asn,
+                 ipv6_isp = excluded.ipv6_isp,
+                 updated_at = excluded.updated_at"#,
+        )
+        .bind(source)
+        .bind(&state.ipv4)
+        .bind(state.ipv4_updated_at)
+        .bind(&state.ipv6)
+        .bind(state.ipv6_updated_at)
+        .bind(&state.ipv6_reason)
+        .bind(state.ipv4_asn)
+        .bind(&state.ipv4_isp)
+        .bind(state.ipv6_asn)
+        .bind(&state.ipv6_isp)
+        .bind(now)
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+    /// 记录一次IP变化
+    pub async fn add_history(
+        &self,
+        address: &str,
+        family: &str,
+        source: &str,
+        asn: Option<i64>,
+        isp: Option<&str>,
+    ) -> ItdResult<i64> {
+        let now = Local::now().naive_local();
+        let result = sqlx::query(
+            r#"INSERT INTO ip_history (address,family,source,asn,isp,detected_at) VALUES (?,?,?,?,?,?)"#,
+        )
+        .bind(address)
+        .bind(family)
+        .bind(source)
+        .bind(asn)
+        .bind(isp)
+        .bind(now)
+        .execute(self.db)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+    /// 分页获取IP变化历史，按时间倒序
+    pub async fn history(&self, source: Option<String>, page: i32) -> ItdResult<Json<Pagination<IpHistory>>> {
+        let offset = (page - 1) * 10;
+        let count: i32 = sqlx::query_scalar(
+            r#"SELECT COUNT(*) FROM ip_history WHERE ? IS NULL OR source = ?"#,
+        )
+        .bind(&source)
+        .bind(&source)
+        .fetch_one(self.db)
+        .await?;
+        let data: Vec<IpHistory> = sqlx::query_as(
+            r#"SELECT id,address,family,source,asn,isp,detected_at FROM ip_history
+               WHERE ? IS NULL OR source = ?
+               ORDER BY id DESC LIMIT ?,10"#,
+        )
+        .bind(&source)
+        .bind(&source)
+        .bind(offset)
+        .fetch_all(self.db)
+        .await?;
+        Ok(Json(Pagination { count, page, data }))
+    }
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct HistoryQuery {
+    pub page: Option<i32>,
+    pub source: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_ip_state_works() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        let model = IpStateModel::new(&db);
+        let mut state = IpState {
+            ipv4: Some("203.0.113.1".to_string()),
+            ipv4_updated_at: 100,
+            ..Default::default()
+        };
+        model.save(DEFAULT_SOURCE, &state).await?;
+        state.ipv4 = Some("203.0.113.2".to_string());
+        state.ipv4_asn = Some(4134);
+        state.ipv4_isp = Some("CHINANET-BACKBONE".to_string());
+        model.save(DEFAULT_SOURCE, &state).await?;
+        let states = model.load_all().await?;
+        assert_eq!(states.len(), 1);
+        assert_eq!(states[DEFAULT_SOURCE].ipv4, Some("203.0.113.2".to_string()));
+        assert_eq!(states[DEFAULT_SOURCE].ipv4_updated_at, 100);
+        assert_eq!(states[DEFAULT_SOURCE].ipv4_asn, Some(4134));
+        assert_eq!(states[DEFAULT_SOURCE].ipv4_isp.as_deref(), Some("CHINANET-BACKBONE"));
+
+        for i in 0..12 {
+            model.add_history(&format!("203.0.113.{}", i), "ipv4", DEFAULT_SOURCE, None, None).await?;
+        }
+        model.add_history("2001:db8::1", "ipv6", "vpn", Some(4134), Some("CHINANET-BACKBONE")).await?;
+        let page = model.history(None, 1).await?;
+        assert_eq!(page.count, 13);
+        assert_eq!(page.data.len(), 10);
+        assert_eq!(page.data[0].address, "2001:db8::1");
+        assert_eq!(page.data[0].asn, Some(4134));
+        let page = model.history(Some(DEFAULT_SOURCE.to_string()), 2).await?;
+        assert_eq!(page.count, 12);
+        assert_eq!(page.data.len(), 2);
+        Ok(())
+    }
+}
@@ -1,5 +1,13 @@
-pub mod app;
-pub mod constants;
-pub mod records;
-//pub mod setting;
-pub mod user;
+pub mod app;
+pub mod constants;
+pub mod echo_health;
+pub mod failover;
+pub mod ip_source;
+pub mod ip_state;
+pub mod notify_email;
+pub mod records;
+pub mod setting;
+pub mod sync_job;
+pub mod targets;
+pub mod user;
+pub mod webhook;
@@ -0,0 +1,125 @@
+use crate::add_conn;
+use crate::error::ItdResult;
+use crate::model::webhook::check_events;
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+add_conn!(EmailSettings);
+
+#[derive(sqlx::FromRow)]
+struct EmailSettingRow {
+    uid: i64,
+    recipients: String,
+    events: String,
+    failure_threshold: i64,
+    status: Option<i64>,
+    updated_at: Option<NaiveDateTime>,
+}
+
+/// 用户的邮件通知设置
+#[derive(Debug, Clone, Serialize)]
+pub struct EmailSetting {
+    pub uid: i64,
+    /// 收件人
+    pub recipients: Vec<String>,
+    /// 订阅的事件
+    pub events: Vec<String>,
+    /// 记录连续失败达到该次数时发送 record_failed
+    pub failure_threshold: i64,
+    /// 0 为停用
+    pub status: Option<i64>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+fn split(value: &str) -> Vec<String> {
+    value.split(',').filter(|item| !item.is_empty()).map(str::to_string).collect()
+}
+
+impl From<EmailSettingRow> for EmailSetting {
+    fn from(row: EmailSettingRow) -> Self {
+        EmailSetting {
+            uid: row.uid,
+            recipients: split(&row.recipients),
+            events: split(&row.events),
+            failure_threshold: row.failure_threshold,
+            status: row.status,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+impl EmailSetting {
+    pub fn subscribes(&self, event: &str) -> bool {
+        self.status != Some(0) && !self.recipients.is_empty() && self.events.iter().any(|item| item == event)
+    }
+}
+
+const SETTING_COLUMNS: &str = "uid,recipients,events,failure_threshold,status,updated_at";
+
+impl<'db> EmailSettings<'db> {
+    pub async fn get(&self, uid: i64) -> ItdResult<Option<EmailSetting>> {
+        let row: Option<EmailSettingRow> =
+            sqlx::query_as(&format!("SELECT {} FROM notify_email WHERE uid=?", SETTING_COLUMNS))
+                .bind(uid)
+                .fetch_optional(self.db)
+                .await?;
+        Ok(row.map(EmailSetting::from))
+    }
+    /// 订阅了事件的用户，uid 为空时不限用户
+    pub async fn subscribers(&self, event: &str, uid: Option<i64>) -> ItdResult<Vec<EmailSetting>> {
+        let rows: Vec<EmailSettingRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM notify_email WHERE (? IS NULL OR uid = ?) ORDER BY uid",
+            SETTING_COLUMNS
+        ))
+        .bind(uid)
+        .bind(uid)
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows.into_iter().map(EmailSetting::from).filter(|setting| setting.subscribes(event)).collect())
+    }
+    /// 保存用户的邮件通知设置
+    pub async fn set(&self, uid: i64, payload: EmailSettingForm) -> ItdResult<()> {
+        let now = Local::now().naive_local();
+        sqlx::query(
+            r#"INSERT INTO notify_email (uid,recipients,events,failure_threshold,status,updated_at)
+               VALUES (?,?,?,?,?,?)
+               ON CONFLICT(uid) DO UPDATE SET
+                 recipients = excluded.recipients,
+                 events = excluded.events,
+                 failure_threshold = excluded.failure_threshold,
+                 status = excluded.status,
+                 updated_at = excluded.updated_at"#,
+        )
+        .bind(uid)
+        .bind(payload.recipients.join(","))
+        .bind(payload.events.join(","))
+        .bind(payload.failure_threshold.unwrap_or(3))
+        .bind(payload.status.unwrap_or(1))
+        .bind(now)
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+}
+
+fn check_recipients(recipients: &[String]) -> Result<(), ValidationError> {
+    if recipients.is_empty() {
+        return Err(ValidationError::new("请至少填写一个收件人"));
+    }
+    match recipients.iter().all(|item| item.parse::<lettre::Address>().is_ok()) {
+        true => Ok(()),
+        false => Err(ValidationError::new("请填写正确的邮箱")),
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct EmailSettingForm {
+    #[validate(custom(function = "check_recipients"))]
+    pub recipients: Vec<String>,
+    #[validate(custom(function = "check_events"))]
+    pub events: Vec<String>,
+    #[validate(range(min = 1, message = "失败次数至少为1"))]
+    pub failure_threshold: Option<i64>,
+    pub status: Option<i64>,
+}
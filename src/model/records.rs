@@ -1,289 +1,563 @@
-use crate::err;
-use crate::{add_conn, dnspod::action::PodAction};
-use crate::error::ItdResult;
-use axum::Json;
-use chrono::NaiveDateTime;
-use crate::model::constants::Pagination;
-use validator::Validate;
-
-add_conn!(Records);
-use serde::{Deserialize, Serialize};
-
-
-#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
-pub struct Record {
-    pub id: i32,
-    pub appid: i32,
-    pub host: String,
-    pub domain: String,
-    pub ip_type: String,
-    /// 原始记录id来源于Dnspod
-    pub record_id: i32,
-    pub ip: String,
-    pub weight: i32,
-    pub ttl: i32,
-    pub created_at: NaiveDateTime,
-    pub updated_at: NaiveDateTime,
-    pub secret_id: Option<String>,
-    pub secret_key: Option<String>,
-}
-
-impl<'db> Records<'db> {
-    /// get all records
- 
-    pub async fn get_record_list(&self) -> ItdResult<Vec<Record>> {
-        let record_list: Vec<Record> = sqlx::query_as(
-            r#"SELECT 
-                     i.id ,appid,host,domain,ip_type,ip,weight,ttl,i.created_at,i.updated_at
-                     ,i.record_id
-                     ,ii.secret_id,ii.secret_key
-                     FROM user_domain i left join user_apps ii on i.appid=ii.id
-                    "#,
-        )
-        .fetch_all(self.db)
-        .await?;
-        Ok(record_list)
-    }
-    /// search by appid and return 
-    pub async fn search(&self,appid: Option<i32>, page: i32) -> ItdResult<Json<Pagination<Record>>> {
-        let offset = (page - 1) * 10;
-        let sql_base = r#"SELECT 
-                     i.id ,appid,host,domain,ip_type,ip,weight,ttl,i.created_at,i.updated_at
-                     ,i.record_id
-                     ,NULL as secret_id, NULL as secret_key
-                     FROM user_domain i left join user_apps ii on i.appid=ii.id "#;
-        let sql_total = if let Some(appid) = appid {
-            format!("{} where i.appid= {}",sql_base,appid)
-        }else{
-            sql_base.to_string()
-        };
-        let total: Option<_> = sqlx::query_scalar(&sql_total)
-        .bind(appid)
-        .fetch_optional(self.db)
-        .await?;
-        if total.is_none() {
-            return Ok(Json(Pagination {
-                count: 0,
-                page,
-                data: vec![],
-            }));
-        }
-        let total = total.unwrap();
-        let sql_query = if let Some(appid) = appid {
-            format!("{} where i.appid={} limit {},10",sql_base,appid,offset)
-        }else{
-            format!("{} limit {},10",sql_base,offset)
-        };
-        let record_list: Vec<Record> = sqlx::query_as(&sql_query)
-        .bind(appid)
-        .bind(offset)
-        .fetch_all(self.db)
-        .await?;
-        let pagination = Json(Pagination {
-            count: total,
-            page,
-            data: record_list,
-        });
-        Ok(pagination)
-    }
-    /// get one record
-    pub async fn get_record(&self, id: i64) -> ItdResult<Option<Record>> {
-        let record: Option<Record> = sqlx::query_as(
-            r#"SELECT 
-             i.id ,appid,host,domain,ip_type,ip,weight,ttl,i.created_at,i.updated_at
-             ,i.record_id
-             ,NULL as secret_id, NULL as secret_key
-             FROM user_domain i left join user_apps ii on i.appid=ii.id
-             where i.id=?
-            "#,
-        )
-        .bind(id)
-        .fetch_optional(self.db)
-        .await?;
-        Ok(record)
-    }
-    /// get one recored by domain
-    #[allow(dead_code)]
-    pub async fn get_record_by_domain(&self, domain: &str) -> ItdResult<Vec<Record>> {
-        let record_list: Vec<Record> = sqlx::query_as(
-            r#"SELECT 
-             i.id ,appid,host,domain,ip_type,ip,weight,ttl,i.created_at,i.updated_at
-             ,i.record_id
-             ,NULL as secret_id,NULL as secret_key
-             FROM user_domain i left join user_apps ii on i.appid=ii.id
-             where i.domain=?
-            "#,
-        )
-        .bind(domain)
-        .fetch_all(self.db)
-        .await?;
-        Ok(record_list)
-    }
-    pub async fn create_record(&self, payload: RecordForm) -> ItdResult<i64> {
-        let RecordForm {
-            appid,
-            host,
-            domain,
-            ip,
-            ip_type,
-            weight,
-            record_id:_,
-            ttl,
-        } = payload;
-        // check if record exists
-        let record_exists = sqlx::query_scalar!(
-            r#"SELECT EXISTS(SELECT 1 FROM user_domain WHERE host=? AND domain=?)"#,
-            host,
-            domain
-        )
-        .fetch_one(self.db)
-        .await?;
-        if record_exists > 0  {
-            return err!("已经添加过此域名，请修改记录实现");
-        };
-        let action = PodAction::new(self.db, appid).await?;
-        let new_ip = ip.clone().unwrap();
-        let record_id = action.add_domain( &host, &domain,&ip_type, &new_ip, ttl).await?;
-        let result = sqlx::query!(
-            r#"
-            INSERT INTO user_domain (appid,host,domain,ip,ip_type,weight,record_id,ttl)
-            VALUES (?,?,?,?,?,?,?,?)
-            "#,
-            appid,
-            host,
-            domain,
-            ip,
-            ip_type,
-            weight,
-            record_id,
-            ttl
-        )
-        .execute(self.db)
-        .await?;
-        
-        Ok(result.last_insert_rowid())
-    }
-    /// update record
-    pub async fn update_record(&self, id: i64, payload: RecordForm) -> ItdResult<u64> {
-        let RecordForm {
-            appid,
-            host,
-            domain,
-            ip,
-            ip_type,
-            weight,
-            record_id,
-            ttl,
-        } = payload;
-        if record_id.is_none() {
-            return err!("缺少原始record_id!");
-        }
-        let action = PodAction::new(self.db, appid).await?;
-        let new_ip = ip.clone().unwrap();
-        action.modify_record(&host, &domain,record_id.unwrap(), &ip_type, &new_ip, ttl).await?;
-        
-        let result = sqlx::query!(r#"UPDATE user_domain SET appid = ?, host = ?, domain = ?, ip = ?, ip_type = ?, weight = ?, record_id = ?, ttl = ? WHERE id = ?"#,
-            appid,
-            host,
-            domain,
-            ip,
-            ip_type,
-            weight,
-            record_id,
-            ttl,
-            id)
-            .execute(self.db)
-            .await?;
-        Ok(result.rows_affected())
-    }
-    /// delete record
-    pub async fn delete_record(&self, id: i64) -> ItdResult<u64> {
-        let rs = self.get_record(id).await?;
-        if rs.is_none() {
-            return err!("Record not found");
-        }
-        let rs = rs.unwrap();
-        let action = PodAction::new(self.db, rs.appid).await?;
-        action.delete_record(&rs.domain,rs.record_id).await?;
-        let result = sqlx::query!(r#"delete from user_domain where id = ?"#, id)
-            .execute(self.db)
-            .await?;
-        Ok(result.rows_affected())
-    }
-}
-
-#[derive(Debug, Deserialize, Validate,Clone)]
-pub struct RecordForm {
-    pub appid: i32,
-    pub host: String,
-    pub domain: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub ip: Option<String>,
-    pub ip_type: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub weight: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub record_id: Option<i32>,
-    pub ttl: i32,
-}
-#[derive(Deserialize, Debug, Validate)]
-pub struct QueryForm {
-    pub page: Option<i32>,
-    pub appid: Option<i32>,
-}
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::dnspod::action::PodAction;
-    use crate::get_conn;
-    /// 添加域名测试
-    #[tokio::test]
-    async fn add_domain_test() -> ItdResult<()> {
-        let db = get_conn().await?;
-        let records = Records::new(&db);
-        let ip = "27.214.7.126".to_string();
-
-        let action = PodAction::new(&db, 1).await?;
-        let record_id = action.add_domain("itd", "guoran.cn", "A", &ip, 600).await?;
-        let data_record = RecordForm {
-            appid: 1,
-            host: "itd".to_string(),
-            domain: "guoran.cn".to_string(),
-            ip: Some(ip.clone()),
-            ip_type: "A".to_string(),
-            weight: None,
-            record_id: Some(record_id),
-            ttl: 600,
-        };
-        let result = records.create_record(data_record).await?;
-        assert_eq!(result > 0, true);
-        Ok(())
-    }
-    /// 删除域名
-    #[tokio::test]
-    async fn delete_domain_test() -> ItdResult<()> {
-        let db = get_conn().await?;
-        let action = PodAction::new(&db, 1).await?;
-        let result = action.delete_record("guoran.cn", 1884171111).await?;
-        assert_eq!(result, ());
-        Ok(())
-    }
-    /// 获取所有记录
-    #[tokio::test]
-    async fn test_get_records_list() -> ItdResult<()> {
-        let db = get_conn().await?;
-        let action = PodAction::new(&db, 1).await?;
-        let record_list = action.find_records("guoran.cn").await?;
-        //println!("record_list is : {:?}", record_list);
-        for record in record_list {
-            if record.name == "itd" {
-                println!("record is : {:?}", record);
-                let result = action
-                    .modify_record("ltd", "guoran.cn", 1884171111, "A", "27.214.7.125", 600)
-                    .await?;
-                assert_eq!(result > 0, true);
-            }
-        }
-        Ok(())
-    }
-}
+use crate::err;
+use crate::add_conn;
+use crate::model::ip_source::IpSourceModel;
+use crate::model::failover::FailoverModel;
+use crate::model::sync_job::SyncJobs;
+use crate::model::targets::Targets;
+use crate::provider::load_provider;
+use crate::queue::limiter::RateLimiter;
+use crate::verify::spawn_verify;
+use crate::error::ItdResult;
+use axum::Json;
+use chrono::{Local, NaiveDateTime};
+use crate::model::constants::Pagination;
+use crate::ipaddr::guard::publish_denied;
+use crate::ipaddr::isp::resolve_line;
+use crate::ipaddr::prefix::{parse_interface_id, record_address};
+use validator::{Validate, ValidationError};
+
+add_conn!(Records);
+use serde::{Deserialize, Deserializer, Serialize};
+use std::sync::Arc;
+
+
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct Record {
+    pub id: i32,
+    pub appid: i32,
+    pub host: String,
+    pub domain: String,
+    pub ip_type: String,
+    /// 服务商返回的原始记录id，Dnspod为数字，自定义服务商可以是任意字符串
+    pub record_id: String,
+    pub ip: String,
+    pub weight: i32,
+    pub ttl: i32,
+    /// IPv6 接口标识，配置后与检测到的前缀拼接
+    pub interface_id: Option<String>,
+    pub prefix_len: Option<i32>,
+    /// IP来源名称，为空时使用全局来源
+    pub source: Option<String>,
+    /// 发布校验状态 pending | consistent | inconsistent | failed
+    pub verify_status: Option<String>,
+    pub verify_detail: Option<String>,
+    /// 发布后所有DNS服务器一致的耗时
+    pub propagation_ms: Option<i64>,
+    pub verified_at: Option<NaiveDateTime>,
+    /// 最近一次成功同步到服务商的时间
+    pub last_synced_at: Option<NaiveDateTime>,
+    pub last_attempt_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    /// 连续同步失败次数，成功后清零
+    pub consecutive_failures: i32,
+    /// 最近一次跳过同步的原因，同步成功后清空
+    pub skip_reason: Option<String>,
+    /// 0 为停用，停用的记录不自动同步
+    pub status: Option<i64>,
+    /// 所属应用的状态
+    pub app_status: Option<i64>,
+    /// 允许发布私有、CGNAT等保留地址
+    pub allow_reserved: bool,
+    /// 解析线路，为空时使用默认线路，auto 为按检测到的运营商选择
+    pub line: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub secret_id: Option<String>,
+    pub secret_key: Option<String>,
+}
+
+impl Record {
+    /// 记录或应用未启用时返回跳过同步的原因，状态只有为1时才同步
+    pub fn skip_reason(&self) -> Option<String> {
+        if self.status != Some(1) {
+            return Some("记录已停用".to_string());
+        }
+        if self.app_status != Some(1) {
+            return Some(format!("应用 {} 已停用", self.appid));
+        }
+        None
+    }
+}
+
+impl<'db> Records<'db> {
+    /// get all records
+ 
+    pub async fn get_record_list(&self) -> ItdResult<Vec<Record>> {
+        let record_list: Vec<Record> = sqlx::query_as(
+            r#"SELECT 
+                     i.id ,appid,host,domain,ip_type,ip,weight,ttl,interface_id,prefix_len,source,verify_status,verify_detail,propagation_ms,verified_at,last_synced_at,last_attempt_at,last_error,consecutive_failures,skip_reason,i.status,ii.status as app_status,allow_reserved,line,i.created_at,i.updated_at
+                     ,CAST(i.record_id AS TEXT) AS record_id
+                     ,ii.secret_id,ii.secret_key
+                     FROM user_domain i left join user_apps ii on i.appid=ii.id
+                    "#,
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(record_list)
+    }
+    /// search by appid and return 
+    pub async fn search(&self,appid: Option<i32>, page: i32) -> ItdResult<Json<Pagination<Record>>> {
+        let offset = (page - 1) * 10;
+        let sql_base = r#"SELECT 
+                     i.id ,appid,host,domain,ip_type,ip,weight,ttl,interface_id,prefix_len,source,verify_status,verify_detail,propagation_ms,verified_at,last_synced_at,last_attempt_at,last_error,consecutive_failures,skip_reason,i.status,ii.status as app_status,allow_reserved,line,i.created_at,i.updated_at
+                     ,CAST(i.record_id AS TEXT) AS record_id
+                     ,NULL as secret_id, NULL as secret_key
+                     FROM user_domain i left join user_apps ii on i.appid=ii.id "#;
+        let sql_total = if let Some(appid) = appid {
+            format!("{} where i.appid= {}",sql_base,appid)
+        }else{
+            sql_base.to_string()
+        };
+        let total: Option<_> = sqlx::query_scalar(&sql_total)
+        .bind(appid)
+        .fetch_optional(self.db)
+        .await?;
+        if total.is_none() {
+            return Ok(Json(Pagination {
+                count: 0,
+                page,
+                data: vec![],
+            }));
+        }
+        let total = total.unwrap();
+        let sql_query = if let Some(appid) = appid {
+            format!("{} where i.appid={} limit {},10",sql_base,appid,offset)
+        }else{
+            format!("{} limit {},10",sql_base,offset)
+        };
+        let record_list: Vec<Record> = sqlx::query_as(&sql_query)
+        .bind(appid)
+        .bind(offset)
+        .fetch_all(self.db)
+        .await?;
+        let pagination = Json(Pagination {
+            count: total,
+            page,
+            data: record_list,
+        });
+        Ok(pagination)
+    }
+    /// get one record
+    pub async fn get_record(&self, id: i64) -> ItdResult<Option<Record>> {
+        let record: Option<Record> = sqlx::query_as(
+            r#"SELECT 
+             i.id ,appid,host,domain,ip_type,ip,weight,ttl,interface_id,prefix_len,source,verify_status,verify_detail,propagation_ms,verified_at,last_synced_at,last_attempt_at,last_error,consecutive_failures,skip_reason,i.status,ii.status as app_status,allow_reserved,line,i.created_at,i.updated_at
+             ,CAST(i.record_id AS TEXT) AS record_id
+             ,NULL as secret_id, NULL as secret_key
+             FROM user_domain i left join user_apps ii on i.appid=ii.id
+             where i.id=?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self.db)
+        .await?;
+        Ok(record)
+    }
+    /// get one recored by domain
+    #[allow(dead_code)]
+    pub async fn get_record_by_domain(&self, domain: &str) -> ItdResult<Vec<Record>> {
+        let record_list: Vec<Record> = sqlx::query_as(
+            r#"SELECT 
+             i.id ,appid,host,domain,ip_type,ip,weight,ttl,interface_id,prefix_len,source,verify_status,verify_detail,propagation_ms,verified_at,last_synced_at,last_attempt_at,last_error,consecutive_failures,skip_reason,i.status,ii.status as app_status,allow_reserved,line,i.created_at,i.updated_at
+             ,CAST(i.record_id AS TEXT) AS record_id
+             ,NULL as secret_id,NULL as secret_key
+             FROM user_domain i left join user_apps ii on i.appid=ii.id
+             where i.domain=?
+            "#,
+        )
+        .bind(domain)
+        .fetch_all(self.db)
+        .await?;
+        Ok(record_list)
+    }
+    pub async fn create_record(&self, payload: RecordForm, limiter: &Arc<RateLimiter>) -> ItdResult<i64> {
+        let RecordForm {
+            appid,
+            host,
+            domain,
+            ip,
+            ip_type,
+            weight,
+            record_id:_,
+            ttl,
+            interface_id,
+            prefix_len,
+            source,
+            allow_reserved,
+            line,
+        } = payload;
+        // check if record exists
+        // 多线路解析时同一主机名可以按线路添加多条，auto 线路按IP来源区分
+        let record_exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM user_domain WHERE host=? AND domain=? AND line IS ? AND (line IS NOT 'auto' OR source IS ?))"#,
+            host,
+            domain,
+            line,
+            source
+        )
+        .fetch_one(self.db)
+        .await?;
+        if record_exists > 0  {
+            return err!("已经添加过此域名，请修改记录实现");
+        };
+        if interface_id.is_some() && ip_type != "AAAA" {
+            return err!("接口标识仅支持AAAA记录");
+        }
+        if let Some(source) = &source {
+            if IpSourceModel::new(self.db).find_by_name(source).await?.is_none() {
+                return err!(format!("IP来源不存在: {}", source));
+            }
+        }
+        let provider = load_provider(self.db, limiter, appid).await?;
+        let new_ip = record_address(&ip.unwrap(), interface_id.as_deref(), prefix_len)?;
+        if let Some(reason) = publish_denied(&new_ip, allow_reserved) {
+            return err!(reason);
+        }
+        let record_line = resolve_line(self.db, line.as_deref(), source.as_deref(), &ip_type).await?;
+        let record_id = provider
+            .add_record(&host, &domain, &ip_type, &new_ip, ttl, record_line.as_deref())
+            .await?;
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO user_domain (appid,host,domain,ip,ip_type,weight,record_id,ttl,interface_id,prefix_len,source,allow_reserved,line)
+            VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?)
+            "#,
+            appid,
+            host,
+            domain,
+            new_ip,
+            ip_type,
+            weight,
+            record_id,
+            ttl,
+            interface_id,
+            prefix_len,
+            source,
+            allow_reserved,
+            line
+        )
+        .execute(self.db)
+        .await?;
+        spawn_verify(self.db, result.last_insert_rowid(), &host, &domain, &ip_type, &new_ip);
+        Ok(result.last_insert_rowid())
+    }
+    /// update record
+    pub async fn update_record(&self, id: i64, payload: RecordForm, limiter: &Arc<RateLimiter>) -> ItdResult<u64> {
+        let RecordForm {
+            appid,
+            host,
+            domain,
+            ip,
+            ip_type,
+            weight,
+            record_id,
+            ttl,
+            interface_id,
+            prefix_len,
+            source,
+            allow_reserved,
+            line,
+        } = payload;
+        if record_id.is_none() {
+            return err!("缺少原始record_id!");
+        }
+        if interface_id.is_some() && ip_type != "AAAA" {
+            return err!("接口标识仅支持AAAA记录");
+        }
+        if let Some(source) = &source {
+            if IpSourceModel::new(self.db).find_by_name(source).await?.is_none() {
+                return err!(format!("IP来源不存在: {}", source));
+            }
+        }
+        let provider = load_provider(self.db, limiter, appid).await?;
+        let new_ip = record_address(&ip.unwrap(), interface_id.as_deref(), prefix_len)?;
+        if let Some(reason) = publish_denied(&new_ip, allow_reserved) {
+            return err!(reason);
+        }
+        let remote_id = record_id.clone().unwrap();
+        let record_line = resolve_line(self.db, line.as_deref(), source.as_deref(), &ip_type).await?;
+        provider
+            .modify_record(&host, &domain, &remote_id, &ip_type, &new_ip, ttl, weight, record_line.as_deref())
+            .await?;
+        
+        let result = sqlx::query!(r#"UPDATE user_domain SET appid = ?, host = ?, domain = ?, ip = ?, ip_type = ?, weight = ?, record_id = ?, ttl = ?, interface_id = ?, prefix_len = ?, source = ?, allow_reserved = ?, line = ? WHERE id = ?"#,
+            appid,
+            host,
+            domain,
+            new_ip,
+            ip_type,
+            weight,
+            record_id,
+            ttl,
+            interface_id,
+            prefix_len,
+            source,
+            allow_reserved,
+            line,
+            id)
+            .execute(self.db)
+            .await?;
+        spawn_verify(self.db, id, &host, &domain, &ip_type, &new_ip);
+        // 同步到镜像目标，失败状态记录在 record_target 中
+        if let Some(record) = self.get_record(id).await? {
+            Targets::new(self.db).sync_targets(&record, &new_ip, limiter).await?;
+        }
+        Ok(result.rows_affected())
+    }
+    /// 写入发布校验结果
+    pub async fn set_verify(
+        &self,
+        id: i64,
+        status: &str,
+        detail: Option<String>,
+        propagation_ms: Option<i64>,
+    ) -> ItdResult<()> {
+        let now = Local::now().naive_local();
+        sqlx::query(
+            r#"UPDATE user_domain SET verify_status = ?, verify_detail = ?, propagation_ms = ?, verified_at = ? WHERE id = ?"#,
+        )
+        .bind(status)
+        .bind(detail)
+        .bind(propagation_ms)
+        .bind(now)
+        .bind(id)
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+    /// 保存同步结果，成功时才写入新的IP
+    pub async fn set_sync_state(&self, id: i64, ip: &str, result: &ItdResult<()>) -> ItdResult<()> {
+        let now = Local::now().naive_local();
+        match result {
+            Ok(_) => {
+                sqlx::query(
+                    r#"UPDATE user_domain SET ip = ?, last_synced_at = ?, last_attempt_at = ?, last_error = NULL, consecutive_failures = 0, skip_reason = NULL WHERE id = ?"#,
+                )
+                .bind(ip)
+                .bind(now)
+                .bind(now)
+                .bind(id)
+                .execute(self.db)
+                .await?;
+            }
+            Err(e) => {
+                sqlx::query(
+                    r#"UPDATE user_domain SET last_attempt_at = ?, last_error = ?, consecutive_failures = consecutive_failures + 1 WHERE id = ?"#,
+                )
+                .bind(now)
+                .bind(e.to_string())
+                .bind(id)
+                .execute(self.db)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+    /// 记录跳过同步的原因
+    pub async fn set_skipped(&self, id: i64, reason: &str) -> ItdResult<()> {
+        sqlx::query(r#"UPDATE user_domain SET skip_reason = ? WHERE id = ?"#)
+            .bind(reason)
+            .bind(id)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+    /// 启用或停用记录
+    pub async fn set_status(&self, id: i64, status: i64) -> ItdResult<u64> {
+        let result = sqlx::query(r#"UPDATE user_domain SET status = ?, skip_reason = NULL WHERE id = ?"#)
+            .bind(status)
+            .bind(id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+    /// delete record
+    pub async fn delete_record(&self, id: i64, limiter: &Arc<RateLimiter>) -> ItdResult<u64> {
+        let rs = self.get_record(id).await?;
+        if rs.is_none() {
+            return err!("Record not found");
+        }
+        let rs = rs.unwrap();
+        let provider = load_provider(self.db, limiter, rs.appid).await?;
+        provider.delete_record(&rs.domain, &rs.record_id).await?;
+        Targets::new(self.db).delete_by_record(&rs, limiter).await?;
+        FailoverModel::new(self.db).delete_by_record(id).await?;
+        SyncJobs::new(self.db).cancel_by_record(id).await?;
+        let result = sqlx::query!(r#"delete from user_domain where id = ?"#, id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(Debug, Deserialize, Validate,Clone)]
+pub struct RecordForm {
+    pub appid: i32,
+    pub host: String,
+    pub domain: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    pub ip_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<i32>,
+    #[serde(default, deserialize_with = "de_record_id")]
+    pub record_id: Option<String>,
+    pub ttl: i32,
+    /// IPv6 接口标识，如 ::1234:5678 或MAC地址
+    #[serde(default)]
+    #[validate(custom(function = "check_interface_id"))]
+    pub interface_id: Option<String>,
+    #[serde(default)]
+    #[validate(range(min = 0, max = 128, message = "前缀长度为0-128"))]
+    pub prefix_len: Option<i32>,
+    /// IP来源名称，为空时使用全局来源
+    #[serde(default)]
+    pub source: Option<String>,
+    /// 允许发布私有、CGNAT等保留地址
+    #[serde(default)]
+    pub allow_reserved: bool,
+    /// 解析线路，如 电信、联通、移动，auto 为按检测到的运营商选择
+    #[serde(default)]
+    pub line: Option<String>,
+}
+
+/// 兼容数字和字符串形式的记录ID
+fn de_record_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::String(record_id)) => Ok(Some(record_id)),
+        Some(serde_json::Value::Number(record_id)) => Ok(Some(record_id.to_string())),
+        Some(_) => Err(serde::de::Error::custom("record_id 必须是字符串或数字")),
+    }
+}
+
+fn check_interface_id(interface_id: &str) -> Result<(), ValidationError> {
+    match parse_interface_id(interface_id) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new("接口标识格式错误")),
+    }
+}
+#[derive(Deserialize, Debug, Validate)]
+pub struct QueryForm {
+    pub page: Option<i32>,
+    pub appid: Option<i32>,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dnspod::action::PodAction;
+    use crate::get_conn;
+    /// 添加域名测试
+    #[tokio::test]
+    async fn add_domain_test() -> ItdResult<()> {
+        let db = get_conn().await?;
+        let records = Records::new(&db);
+        let ip = "27.214.7.126".to_string();
+
+        let action = PodAction::new(&db, 1).await?;
+        let record_id = action.add_domain("itd", "guoran.cn", "A", &ip, 600, None).await?;
+        let data_record = RecordForm {
+            appid: 1,
+            host: "itd".to_string(),
+            domain: "guoran.cn".to_string(),
+            ip: Some(ip.clone()),
+            ip_type: "A".to_string(),
+            weight: None,
+            record_id: Some(record_id.to_string()),
+            ttl: 600,
+            interface_id: None,
+            prefix_len: None,
+            source: None,
+            allow_reserved: false,
+            line: None,
+        };
+        let result = records.create_record(data_record, &Arc::default()).await?;
+        assert_eq!(result > 0, true);
+        Ok(())
+    }
+    /// 自定义服务商返回字符串记录ID
+    #[tokio::test]
+    async fn it_create_record_with_text_id_works() -> ItdResult<()> {
+        use crate::provider::custom::{CustomConfig, Extractor};
+        use crate::verify::{set_verify_config, VerifyConfig};
+        use axum::{extract::Path, routing::put, Router};
+        use std::sync::{Arc, Mutex};
+
+        let updated = Arc::new(Mutex::new(vec![]));
+        let seen = updated.clone();
+        let app = Router::new()
+            .route("/records/", put(|| async { r#"{"result":{"id":"5f3a-b2"}}"# }))
+            .route(
+                "/records/:id",
+                put(move |Path(id): Path<String>| async move {
+                    seen.lock().unwrap().push(id);
+                    "{}"
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        set_verify_config(&db, &VerifyConfig { enabled: false, ..Default::default() }).await?;
+        let appid = crate::tests::insert_custom_app(&db, &format!("http://{}/records/{{record_id}}", addr)).await?;
+        let config: String = sqlx::query_scalar(r#"SELECT provider_config FROM user_apps WHERE id=?"#)
+            .bind(appid)
+            .fetch_one(&db)
+            .await?;
+        let mut config: CustomConfig = serde_json::from_str(&config)?;
+        config.create.record_id = Some(Extractor::Jsonpath { path: "$.result.id".to_string() });
+        sqlx::query(r#"UPDATE user_apps SET provider_config=? WHERE id=?"#)
+            .bind(serde_json::to_string(&config)?)
+            .bind(appid)
+            .execute(&db)
+            .await?;
+
+        let form: RecordForm = serde_json::from_value(serde_json::json!({
+            "appid": appid, "host": "www", "domain": "example.com", "ip": "198.51.100.1",
+            "ip_type": "A", "ttl": 600, "allow_reserved": true
+        }))?;
+        let records = Records::new(&db);
+        let id = records.create_record(form.clone(), &Arc::default()).await?;
+        let record = records.get_record(id).await?.unwrap();
+        assert_eq!(record.record_id, "5f3a-b2");
+
+        let form = RecordForm { record_id: Some(record.record_id.clone()), ip: Some("198.51.100.2".to_string()), ..form };
+        records.update_record(id, form, &Arc::default()).await?;
+        assert_eq!(updated.lock().unwrap().clone(), vec!["5f3a-b2".to_string()]);
+        // 兼容数字形式的记录ID
+        let form: RecordForm = serde_json::from_str(
+            r#"{"appid":1,"host":"www","domain":"example.com","ip_type":"A","ttl":600,"record_id":1904088010}"#,
+        )?;
+        assert_eq!(form.record_id.as_deref(), Some("1904088010"));
+        Ok(())
+    }
+    /// 删除域名
+    #[tokio::test]
+    async fn delete_domain_test() -> ItdResult<()> {
+        let db = get_conn().await?;
+        let action = PodAction::new(&db, 1).await?;
+        let result = action.delete_record("guoran.cn", 1884171111).await?;
+        assert_eq!(result, ());
+        Ok(())
+    }
+    /// 获取所有记录
+    #[tokio::test]
+    async fn test_get_records_list() -> ItdResult<()> {
+        let db = get_conn().await?;
+        let action = PodAction::new(&db, 1).await?;
+        let record_list = action.find_records("guoran.cn").await?;
+        //println!("record_list is : {:?}", record_list);
+        for record in record_list {
+            if record.name == "itd" {
+                println!("record is : {:?}", record);
+                let result = action
+                    .modify_record("ltd", "guoran.cn", 1884171111, "A", "27.214.7.125", 600, None, None)
+                    .await?;
+                assert_eq!(result > 0, true);
+            }
+        }
+        Ok(())
+    }
+}
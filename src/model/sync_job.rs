@@ -0,0 +1,194 @@
+use crate::add_conn;
+use crate::error::ItdResult;
+use crate::model::constants::Pagination;
+use axum::Json;
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+add_conn!(SyncJobs);
+
+pub const JOB_PENDING: &str = "pending";
+pub const JOB_DONE: &str = "done";
+pub const JOB_FAILED: &str = "failed";
+pub const JOB_CANCELLED: &str = "cancelled";
+
+/// 待同步到服务商的记录值
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct SyncJob {
+    pub id: i64,
+    pub domain_id: i64,
+    pub value: String,
+    /// pending | done | failed | cancelled
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+const JOB_COLUMNS: &str = "id,domain_id,value,status,attempts,next_attempt_at,last_error,created_at,updated_at";
+
+impl<'db> SyncJobs<'db> {
+    /// 加入队列，记录已有待处理任务时用新值替换
+    pub async fn enqueue(&self, domain_id: i64, value: &str) -> ItdResult<i64> {
+        self.enqueue_at(domain_id, value, Local::now().naive_local()).await
+    }
+    /// 加入队列，不早于 not_before 处理
+    pub async fn enqueue_at(&self, domain_id: i64, value: &str, not_before: NaiveDateTime) -> ItdResult<i64> {
+        let now = Local::now().naive_local();
+        let pending: Option<i64> =
+            sqlx::query_scalar(r#"SELECT id FROM sync_job WHERE domain_id=? AND status=?"#)
+                .bind(domain_id)
+                .bind(JOB_PENDING)
+                .fetch_optional(self.db)
+                .await?;
+        if let Some(id) = pending {
+            sqlx::query(
+                r#"UPDATE sync_job SET value=?, attempts=0, next_attempt_at=?, last_error=NULL, created_at=?, updated_at=? WHERE id=?"#,
+            )
+            .bind(value)
+            .bind(not_before)
+            .bind(now)
+            .bind(now)
+            .bind(id)
+            .execute(self.db)
+            .await?;
+            return Ok(id);
+        }
+        let result = sqlx::query(
+            r#"INSERT INTO sync_job (domain_id,value,status,next_attempt_at,created_at,updated_at) VALUES (?,?,?,?,?,?)"#,
+        )
+        .bind(domain_id)
+        .bind(value)
+        .bind(JOB_PENDING)
+        .bind(not_before)
+        .bind(now)
+        .bind(now)
+        .execute(self.db)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+    pub async fn get(&self, id: i64) -> ItdResult<Option<SyncJob>> {
+        let job: Option<SyncJob> = sqlx::query_as(&format!("SELECT {} FROM sync_job WHERE id=?", JOB_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.db)
+            .await?;
+        Ok(job)
+    }
+    /// 到期需要处理的任务
+    pub async fn due(&self) -> ItdResult<Vec<SyncJob>> {
+        let now = Local::now().naive_local();
+        let jobs: Vec<SyncJob> = sqlx::query_as(&format!(
+            "SELECT {} FROM sync_job WHERE status=? AND next_attempt_at<=? ORDER BY id",
+            JOB_COLUMNS
+        ))
+        .bind(JOB_PENDING)
+        .bind(now)
+        .fetch_all(self.db)
+        .await?;
+        Ok(jobs)
+    }
+    pub async fn list(&self, status: Option<String>, page: i32) -> ItdResult<Json<Pagination<SyncJob>>> {
+        let offset = (page - 1) * 10;
+        let count: i32 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM sync_job WHERE ? IS NULL OR status = ?"#)
+            .bind(&status)
+            .bind(&status)
+            .fetch_one(self.db)
+            .await?;
+        let data: Vec<SyncJob> = sqlx::query_as(&format!(
+            "SELECT {} FROM sync_job WHERE ? IS NULL OR status = ? ORDER BY id DESC LIMIT ?,10",
+            JOB_COLUMNS
+        ))
+        .bind(&status)
+        .bind(&status)
+        .bind(offset)
+        .fetch_all(self.db)
+        .await?;
+        Ok(Json(Pagination { count, page, data }))
+    }
+    /// 更新任务状态，next_attempt_at 为空时保持不变
+    /// 只在任务的值仍为 value 时更新，处理期间被 enqueue 替换了新值的任务保持待处理，返回更新的行数
+    pub async fn set_status(
+        &self,
+        id: i64,
+        value: &str,
+        status: &str,
+        attempts: i64,
+        next_attempt_at: Option<NaiveDateTime>,
+        last_error: Option<String>,
+    ) -> ItdResult<u64> {
+        let now = Local::now().naive_local();
+        let result = sqlx::query(
+            r#"UPDATE sync_job SET status=?, attempts=?, next_attempt_at=COALESCE(?, next_attempt_at), last_error=?, updated_at=? WHERE id=? AND value=?"#,
+        )
+        .bind(status)
+        .bind(attempts)
+        .bind(next_attempt_at)
+        .bind(last_error)
+        .bind(now)
+        .bind(id)
+        .bind(value)
+        .execute(self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+    /// 记录自 since 以来成功发布的时间，按时间排序
+    pub async fn changes_since(&self, domain_id: i64, since: NaiveDateTime) -> ItdResult<Vec<NaiveDateTime>> {
+        let changes: Vec<NaiveDateTime> = sqlx::query_scalar(
+            r#"SELECT updated_at FROM sync_job WHERE domain_id=? AND status=? AND updated_at>=? ORDER BY updated_at"#,
+        )
+        .bind(domain_id)
+        .bind(JOB_DONE)
+        .bind(since)
+        .fetch_all(self.db)
+        .await?;
+        Ok(changes)
+    }
+    /// 重新排队失败或已取消的任务，立即处理
+    pub async fn retry(&self, id: i64) -> ItdResult<u64> {
+        let now = Local::now().naive_local();
+        let result = sqlx::query(
+            r#"UPDATE sync_job SET status=?, attempts=0, next_attempt_at=?, created_at=?, updated_at=? WHERE id=? AND status IN (?,?)"#,
+        )
+        .bind(JOB_PENDING)
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .bind(id)
+        .bind(JOB_FAILED)
+        .bind(JOB_CANCELLED)
+        .execute(self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+    /// 取消待处理的任务
+    pub async fn cancel(&self, id: i64) -> ItdResult<u64> {
+        let now = Local::now().naive_local();
+        let result = sqlx::query(r#"UPDATE sync_job SET status=?, updated_at=? WHERE id=? AND status=?"#)
+            .bind(JOB_CANCELLED)
+            .bind(now)
+            .bind(id)
+            .bind(JOB_PENDING)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+    /// 记录删除后取消它的待处理任务
+    pub async fn cancel_by_record(&self, domain_id: i64) -> ItdResult<()> {
+        sqlx::query(r#"UPDATE sync_job SET status=? WHERE domain_id=? AND status=?"#)
+            .bind(JOB_CANCELLED)
+            .bind(domain_id)
+            .bind(JOB_PENDING)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct JobQuery {
+    pub page: Option<i32>,
+    pub status: Option<String>,
+}
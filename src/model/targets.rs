@@ -0,0 +1,263 @@
+use crate::add_conn;
+use crate::err;
+use crate::error::ItdResult;
+use crate::model::records::{Record, Records};
+use crate::provider::load_provider;
+use crate::queue::limiter::RateLimiter;
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{error, info};
+use validator::Validate;
+
+add_conn!(Targets);
+
+pub const SYNC_SYNCED: &str = "synced";
+pub const SYNC_FAILED: &str = "failed";
+
+/// 解析记录的镜像目标，每个目标对应一个服务商应用及其远程记录ID
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct Target {
+    pub id: i64,
+    pub domain_id: i64,
+    pub appid: i32,
+    /// 服务商侧的记录ID
+    pub record_id: String,
+    /// pending | synced | failed
+    pub sync_status: String,
+    pub last_error: Option<String>,
+    pub synced_at: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl<'db> Targets<'db> {
+    /// 获取记录的所有镜像目标
+    pub async fn list(&self, domain_id: i64) -> ItdResult<Vec<Target>> {
+        let targets: Vec<Target> = sqlx::query_as(
+            r#"SELECT id,domain_id,appid,record_id,sync_status,last_error,synced_at,created_at
+               FROM record_target WHERE domain_id=? ORDER BY id"#,
+        )
+        .bind(domain_id)
+        .fetch_all(self.db)
+        .await?;
+        Ok(targets)
+    }
+    /// 获取一个镜像目标
+    pub async fn get(&self, id: i64) -> ItdResult<Option<Target>> {
+        let target: Option<Target> = sqlx::query_as(
+            r#"SELECT id,domain_id,appid,record_id,sync_status,last_error,synced_at,created_at
+               FROM record_target WHERE id=?"#,
+        )
+        .bind(id)
+        .fetch_optional(self.db)
+        .await?;
+        Ok(target)
+    }
+    /// 为记录添加镜像目标，在目标服务商上创建同样的记录
+    pub async fn add_target(&self, domain_id: i64, appid: i32, limiter: &Arc<RateLimiter>) -> ItdResult<i64> {
+        let record = Records::new(self.db).get_record(domain_id).await?;
+        if record.is_none() {
+            return err!("Record not found");
+        }
+        let record = record.unwrap();
+        if record.appid == appid {
+            return err!("该应用已经是此记录的主目标");
+        }
+        let exists = self
+            .list(domain_id)
+            .await?
+            .iter()
+            .any(|target| target.appid == appid);
+        if exists {
+            return err!("已经添加过此应用");
+        }
+        let provider = load_provider(self.db, limiter, appid).await?;
+        let record_id = provider
+            .add_record(
+                &record.host,
+                &record.domain,
+                &record.ip_type,
+                &record.ip,
+                record.ttl,
+                None,
+            )
+            .await?;
+        let now = Local::now().naive_local();
+        let result = sqlx::query(
+            r#"INSERT INTO record_target (domain_id,appid,record_id,sync_status,synced_at)
+               VALUES (?,?,?,?,?)"#,
+        )
+        .bind(domain_id)
+        .bind(appid)
+        .bind(record_id)
+        .bind(SYNC_SYNCED)
+        .bind(now)
+        .execute(self.db)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+    /// 删除镜像目标，同时删除服务商上的记录
+    pub async fn delete_target(&self, id: i64, limiter: &Arc<RateLimiter>) -> ItdResult<u64> {
+        let target = self.get(id).await?;
+        if target.is_none() {
+            return err!("Target not found");
+        }
+        let target = target.unwrap();
+        let record = Records::new(self.db).get_record(target.domain_id).await?;
+        if let Some(record) = record {
+            let provider = load_provider(self.db, limiter, target.appid).await?;
+            provider
+                .delete_record(&record.domain, &target.record_id)
+                .await?;
+        }
+        let result = sqlx::query(r#"DELETE FROM record_target WHERE id=?"#)
+            .bind(id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+    /// 删除记录的所有镜像目标，服务商侧删除失败时仅记录日志
+    pub async fn delete_by_record(&self, record: &Record, limiter: &Arc<RateLimiter>) -> ItdResult<()> {
+        let targets = self.list(record.id as i64).await?;
+        for target in targets {
+            let result = match load_provider(self.db, limiter, target.appid).await {
+                Ok(provider) => {
+                    provider
+                        .delete_record(&record.domain, &target.record_id)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!(
+                    "Delete target {} of {}.{} failed: {}",
+                    target.id, record.host, record.domain, e
+                );
+            }
+        }
+        sqlx::query(r#"DELETE FROM record_target WHERE domain_id=?"#)
+            .bind(record.id)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+    /// 将记录值同步到所有镜像目标，单个目标失败不影响其它目标
+    /// 返回失败的目标数量
+    pub async fn sync_targets(&self, record: &Record, value: &str, limiter: &Arc<RateLimiter>) -> ItdResult<usize> {
+        let targets = self.list(record.id as i64).await?;
+        let mut failed = 0;
+        for target in targets {
+            let result = match load_provider(self.db, limiter, target.appid).await {
+                Ok(provider) => {
+                    provider
+                        .modify_record(
+                            &record.host,
+                            &record.domain,
+                            &target.record_id,
+                            &record.ip_type,
+                            value,
+                            record.ttl,
+                            Some(record.weight),
+                            // 线路名称因服务商而异，镜像目标使用默认线路
+                            None,
+                        )
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            match &result {
+                Ok(_) => info!(
+                    "Update target {} of {}.{} ok",
+                    target.id, record.host, record.domain
+                ),
+                Err(e) => {
+                    failed += 1;
+                    error!(
+                        "Update target {} of {}.{} failed: {}",
+                        target.id, record.host, record.domain, e
+                    );
+                }
+            }
+            self.set_state(target.id, &result).await?;
+        }
+        Ok(failed)
+    }
+    /// 保存目标的同步结果
+    pub async fn set_state(&self, id: i64, result: &ItdResult<()>) -> ItdResult<()> {
+        match result {
+            Ok(_) => {
+                let now = Local::now().naive_local();
+                sqlx::query(
+                    r#"UPDATE record_target SET sync_status=?,last_error=NULL,synced_at=? WHERE id=?"#,
+                )
+                .bind(SYNC_SYNCED)
+                .bind(now)
+                .bind(id)
+                .execute(self.db)
+                .await?;
+            }
+            Err(e) => {
+                sqlx::query(r#"UPDATE record_target SET sync_status=?,last_error=? WHERE id=?"#)
+                    .bind(SYNC_FAILED)
+                    .bind(e.to_string())
+                    .bind(id)
+                    .execute(self.db)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, Clone)]
+pub struct TargetForm {
+    pub appid: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::insert_custom_app as insert_app;
+    use axum::{routing::put, Router};
+
+    #[tokio::test]
+    async fn it_sync_targets_reports_partial_failure() -> ItdResult<()> {
+        let app = Router::new()
+            .route("/ok/:id", put(|| async { "ok" }))
+            .route(
+                "/fail/:id",
+                put(|| async { (http::StatusCode::BAD_GATEWAY, "down") }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        let ok_app = insert_app(&db, &format!("http://{}/ok/{{record_id}}", addr)).await?;
+        let fail_app = insert_app(&db, &format!("http://{}/fail/{{record_id}}", addr)).await?;
+        sqlx::query(
+            r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id)
+               VALUES (1,99,'www','example.com','A','1.1.1.1',1)"#,
+        )
+        .execute(&db)
+        .await?;
+        for (appid, record_id) in [(ok_app, "11"), (fail_app, "12")] {
+            sqlx::query(r#"INSERT INTO record_target (domain_id,appid,record_id) VALUES (1,?,?)"#)
+                .bind(appid)
+                .bind(record_id)
+                .execute(&db)
+                .await?;
+        }
+        let record = Records::new(&db).get_record(1).await?.unwrap();
+        let target_model = Targets::new(&db);
+        let failed = target_model.sync_targets(&record, "2.2.2.2", &Arc::default()).await?;
+        assert_eq!(failed, 1);
+        let targets = target_model.list(1).await?;
+        assert_eq!(targets[0].sync_status, SYNC_SYNCED);
+        assert!(targets[0].synced_at.is_some());
+        assert_eq!(targets[1].sync_status, SYNC_FAILED);
+        assert!(targets[1].last_error.is_some());
+        Ok(())
+    }
+}
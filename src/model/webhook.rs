@@ -0,0 +1,317 @@
+use crate::add_conn;
+use crate::err;
+use crate::error::ItdResult;
+use crate::model::constants::Pagination;
+use crate::notify::EVENTS;
+use crate::utils::{decrypt_to_str, encrypt_data};
+use axum::Json;
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+add_conn!(Webhooks);
+
+pub const DELIVERY_PENDING: &str = "pending";
+pub const DELIVERY_DONE: &str = "done";
+pub const DELIVERY_FAILED: &str = "failed";
+
+#[derive(sqlx::FromRow)]
+struct WebhookRow {
+    id: i64,
+    uid: i64,
+    url: String,
+    secret: String,
+    events: String,
+    failure_threshold: i64,
+    status: Option<i64>,
+    created_at: Option<NaiveDateTime>,
+    updated_at: Option<NaiveDateTime>,
+}
+
+/// 用户的通知目标
+#[derive(Debug, Clone, Serialize)]
+pub struct Webhook {
+    pub id: i64,
+    pub uid: i64,
+    pub url: String,
+    /// 签名密钥，加密保存
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// 订阅的事件
+    pub events: Vec<String>,
+    /// 记录连续失败达到该次数时发送 record_failed
+    pub failure_threshold: i64,
+    /// 0 为停用
+    pub status: Option<i64>,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<WebhookRow> for Webhook {
+    fn from(row: WebhookRow) -> Self {
+        Webhook {
+            id: row.id,
+            uid: row.uid,
+            url: row.url,
+            secret: row.secret,
+            events: row.events.split(',').filter(|event| !event.is_empty()).map(str::to_string).collect(),
+            failure_threshold: row.failure_threshold,
+            status: row.status,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+impl Webhook {
+    pub fn subscribes(&self, event: &str) -> bool {
+        self.status != Some(0) && self.events.iter().any(|item| item == event)
+    }
+    /// 解密后的签名密钥
+    pub fn signing_key(&self) -> ItdResult<String> {
+        decrypt_to_str(&self.secret)
+    }
+}
+
+/// 创建后返回一次明文密钥，用于校验签名
+#[derive(Debug, Serialize)]
+pub struct WebhookCreated {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+/// 一次事件投递
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event: String,
+    pub payload: String,
+    /// pending | done | failed
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: NaiveDateTime,
+    pub response_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+const WEBHOOK_COLUMNS: &str = "id,uid,url,secret,events,failure_threshold,status,created_at,updated_at";
+const DELIVERY_COLUMNS: &str =
+    "id,webhook_id,event,payload,status,attempts,next_attempt_at,response_status,last_error,created_at,updated_at";
+
+impl<'db> Webhooks<'db> {
+    pub async fn list(&self, uid: i64) -> ItdResult<Vec<Webhook>> {
+        let rows: Vec<WebhookRow> =
+            sqlx::query_as(&format!("SELECT {} FROM webhook WHERE uid=? ORDER BY id", WEBHOOK_COLUMNS))
+                .bind(uid)
+                .fetch_all(self.db)
+                .await?;
+        Ok(rows.into_iter().map(Webhook::from).collect())
+    }
+    pub async fn get(&self, id: i64) -> ItdResult<Option<Webhook>> {
+        let row: Option<WebhookRow> =
+            sqlx::query_as(&format!("SELECT {} FROM webhook WHERE id=?", WEBHOOK_COLUMNS))
+                .bind(id)
+                .fetch_optional(self.db)
+                .await?;
+        Ok(row.map(Webhook::from))
+    }
+    /// 获取用户自己的通知目标
+    pub async fn get_owned(&self, uid: i64, id: i64) -> ItdResult<Webhook> {
+        match self.get(id).await? {
+            Some(webhook) if webhook.uid == uid => Ok(webhook),
+            _ => err!("Webhook not found"),
+        }
+    }
+    /// 订阅了事件的通知目标，uid 为空时不限用户
+    pub async fn subscribers(&self, event: &str, uid: Option<i64>) -> ItdResult<Vec<Webhook>> {
+        let rows: Vec<WebhookRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM webhook WHERE (? IS NULL OR uid = ?) ORDER BY id",
+            WEBHOOK_COLUMNS
+        ))
+        .bind(uid)
+        .bind(uid)
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows.into_iter().map(Webhook::from).filter(|webhook| webhook.subscribes(event)).collect())
+    }
+    /// 添加通知目标，未填写密钥时随机生成，返回明文密钥
+    pub async fn add(&self, uid: i64, payload: WebhookForm) -> ItdResult<(i64, String)> {
+        let secret = match payload.secret {
+            Some(secret) => secret,
+            None => {
+                let mut bytes = [0u8; 24];
+                getrandom::getrandom(&mut bytes)?;
+                hex::encode(bytes)
+            }
+        };
+        let now = Local::now().naive_local();
+        let result = sqlx::query(
+            r#"INSERT INTO webhook (uid,url,secret,events,failure_threshold,status,created_at,updated_at)
+               VALUES (?,?,?,?,?,?,?,?)"#,
+        )
+        .bind(uid)
+        .bind(payload.url)
+        .bind(encrypt_data(secret.as_bytes().to_vec())?)
+        .bind(payload.events.join(","))
+        .bind(payload.failure_threshold.unwrap_or(3))
+        .bind(payload.status.unwrap_or(1))
+        .bind(now)
+        .bind(now)
+        .execute(self.db)
+        .await?;
+        Ok((result.last_insert_rowid(), secret))
+    }
+    /// 修改通知目标，未填写密钥时保持不变
+    pub async fn update(&self, id: i64, payload: WebhookForm) -> ItdResult<u64> {
+        let secret = match payload.secret {
+            Some(secret) => Some(encrypt_data(secret.as_bytes().to_vec())?),
+            None => None,
+        };
+        let now = Local::now().naive_local();
+        let result = sqlx::query(
+            r#"UPDATE webhook SET url=?, secret=COALESCE(?, secret), events=?, failure_threshold=?, status=?, updated_at=? WHERE id=?"#,
+        )
+        .bind(payload.url)
+        .bind(secret)
+        .bind(payload.events.join(","))
+        .bind(payload.failure_threshold.unwrap_or(3))
+        .bind(payload.status.unwrap_or(1))
+        .bind(now)
+        .bind(id)
+        .execute(self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+    pub async fn delete(&self, id: i64) -> ItdResult<u64> {
+        sqlx::query(r#"DELETE FROM webhook_delivery WHERE webhook_id=?"#)
+            .bind(id)
+            .execute(self.db)
+            .await?;
+        let result = sqlx::query(r#"DELETE FROM webhook WHERE id=?"#)
+            .bind(id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+    /// 应用所属的用户
+    pub async fn app_owner(&self, appid: i32) -> ItdResult<Option<i64>> {
+        let uid: Option<i64> = sqlx::query_scalar(r#"SELECT uid FROM user_apps WHERE id=?"#)
+            .bind(appid)
+            .fetch_optional(self.db)
+            .await?;
+        Ok(uid)
+    }
+    pub async fn enqueue(&self, webhook_id: i64, event: &str, payload: &str) -> ItdResult<i64> {
+        let now = Local::now().naive_local();
+        let result = sqlx::query(
+            r#"INSERT INTO webhook_delivery (webhook_id,event,payload,status,next_attempt_at,created_at,updated_at)
+               VALUES (?,?,?,?,?,?,?)"#,
+        )
+        .bind(webhook_id)
+        .bind(event)
+        .bind(payload)
+        .bind(DELIVERY_PENDING)
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .execute(self.db)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+    /// 到期需要投递的事件
+    pub async fn due(&self) -> ItdResult<Vec<WebhookDelivery>> {
+        let now = Local::now().naive_local();
+        let deliveries: Vec<WebhookDelivery> = sqlx::query_as(&format!(
+            "SELECT {} FROM webhook_delivery WHERE status=? AND next_attempt_at<=? ORDER BY id",
+            DELIVERY_COLUMNS
+        ))
+        .bind(DELIVERY_PENDING)
+        .bind(now)
+        .fetch_all(self.db)
+        .await?;
+        Ok(deliveries)
+    }
+    /// 分页获取通知目标的投递记录，按时间倒序
+    pub async fn deliveries(&self, webhook_id: i64, page: i32) -> ItdResult<Json<Pagination<WebhookDelivery>>> {
+        let offset = (page - 1) * 10;
+        let count: i32 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM webhook_delivery WHERE webhook_id=?"#)
+            .bind(webhook_id)
+            .fetch_one(self.db)
+            .await?;
+        let data: Vec<WebhookDelivery> = sqlx::query_as(&format!(
+            "SELECT {} FROM webhook_delivery WHERE webhook_id=? ORDER BY id DESC LIMIT ?,10",
+            DELIVERY_COLUMNS
+        ))
+        .bind(webhook_id)
+        .bind(offset)
+        .fetch_all(self.db)
+        .await?;
+        Ok(Json(Pagination { count, page, data }))
+    }
+    /// 更新投递状态，next_attempt_at 为空时保持不变
+    pub async fn set_delivery(
+        &self,
+        id: i64,
+        status: &str,
+        attempts: i64,
+        next_attempt_at: Option<NaiveDateTime>,
+        response_status: Option<i64>,
+        last_error: Option<String>,
+    ) -> ItdResult<()> {
+        let now = Local::now().naive_local();
+        sqlx::query(
+            r#"UPDATE webhook_delivery SET status=?, attempts=?, next_attempt_at=COALESCE(?, next_attempt_at),
+               response_status=?, last_error=?, updated_at=? WHERE id=?"#,
+        )
+        .bind(status)
+        .bind(attempts)
+        .bind(next_attempt_at)
+        .bind(response_status)
+        .bind(last_error)
+        .bind(now)
+        .bind(id)
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+}
+
+pub fn check_events(events: &[String]) -> Result<(), ValidationError> {
+    if events.is_empty() {
+        return Err(ValidationError::new("请至少订阅一个事件"));
+    }
+    match events.iter().all(|event| EVENTS.contains(&event.as_str())) {
+        true => Ok(()),
+        false => Err(ValidationError::new("不支持的事件")),
+    }
+}
+
+fn check_url(url: &str) -> Result<(), ValidationError> {
+    match reqwest::Url::parse(url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+        _ => Err(ValidationError::new("请填写正确的URL")),
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct WebhookForm {
+    #[validate(custom(function = "check_url"))]
+    pub url: String,
+    /// 签名密钥，为空时创建会随机生成，修改时保持不变
+    #[validate(length(min = 16, max = 128, message = "密钥长度为16-128"))]
+    pub secret: Option<String>,
+    #[validate(custom(function = "check_events"))]
+    pub events: Vec<String>,
+    #[validate(range(min = 1, message = "失败次数至少为1"))]
+    pub failure_threshold: Option<i64>,
+    pub status: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct DeliveryQuery {
+    pub page: Option<i32>,
+}
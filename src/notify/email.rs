@@ -0,0 +1,323 @@
+//! 邮件通知
+//! 通过 SMTP 发送事件邮件，正文同时包含纯文本和 HTML，收件人和订阅的事件按用户保存在 notify_email 表
+use crate::err;
+use crate::error::ItdResult;
+use crate::model::notify_email::{EmailSetting, EmailSettings};
+use crate::model::setting::SettingModel;
+use crate::notify::{
+    EVENT_CREDENTIALS_INVALID, EVENT_DRIFT_DETECTED, EVENT_IP_CHANGED, EVENT_JOB_EXPIRED, EVENT_RECORD_FAILED,
+    EVENT_RECORD_UPDATED,
+};
+use crate::utils::{decrypt_to_str, encrypt_data};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::time::Duration;
+use tracing::{error, info};
+
+pub const SMTP_KEY: &str = "smtp";
+/// 连接后通过 STARTTLS 升级，通常为587端口
+pub const SECURITY_STARTTLS: &str = "starttls";
+/// 直接使用 TLS 连接，通常为465端口
+pub const SECURITY_TLS: &str = "tls";
+/// 不加密，只用于本地中继或测试
+pub const SECURITY_NONE: &str = "none";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    /// starttls | tls | none
+    pub security: String,
+    pub username: Option<String>,
+    /// 加密保存，为空时保持不变
+    pub password: Option<String>,
+    /// 发件人，如 "IpTellDnspod <itd@example.com>"
+    pub from: String,
+    /// 连接超时(秒)
+    pub timeout: u64,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        SmtpConfig {
+            enabled: false,
+            host: "".to_string(),
+            port: 587,
+            security: SECURITY_STARTTLS.to_string(),
+            username: None,
+            password: None,
+            from: "".to_string(),
+            timeout: 10,
+        }
+    }
+}
+
+impl SmtpConfig {
+    /// 按配置创建 SMTP 连接
+    fn transport(&self) -> ItdResult<AsyncSmtpTransport<Tokio1Executor>> {
+        let builder = match self.security.as_str() {
+            SECURITY_TLS => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)?,
+            SECURITY_STARTTLS => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?,
+            SECURITY_NONE => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.host),
+            _ => return err!("不支持的加密方式"),
+        };
+        let mut builder = builder.port(self.port).timeout(Some(Duration::from_secs(self.timeout)));
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), decrypt_to_str(password)?));
+        }
+        Ok(builder.build())
+    }
+}
+
+pub async fn get_smtp_config(db: &SqlitePool) -> ItdResult<SmtpConfig> {
+    let setting = SettingModel::new(db);
+    let config = match setting.find(SMTP_KEY).await? {
+        Some(value) => serde_json::from_str(&value)?,
+        None => SmtpConfig::default(),
+    };
+    Ok(config)
+}
+
+/// 保存 SMTP 配置，密码加密保存，未填写时保持原密码
+pub async fn set_smtp_config(db: &SqlitePool, config: &SmtpConfig) -> ItdResult<()> {
+    if !config.from.is_empty() {
+        config.from.parse::<Mailbox>()?;
+    }
+    let mut config = config.clone();
+    config.password = match config.password {
+        Some(password) => Some(encrypt_data(password.into_bytes())?),
+        None => get_smtp_config(db).await?.password,
+    };
+    let setting = SettingModel::new(db);
+    let value = serde_json::to_string(&config)?;
+    setting.set(SMTP_KEY, &value, "SMTP邮件配置").await?;
+    Ok(())
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn field(data: &Value, key: &str) -> String {
+    match &data[key] {
+        Value::String(value) => value.clone(),
+        Value::Null => "".to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// 邮件标题
+fn subject(event: &str, data: &Value) -> String {
+    let domain = format!("{}.{}", field(data, "host"), field(data, "domain"));
+    let title = match event {
+        EVENT_IP_CHANGED => format!("IP已变化: {} {}", field(data, "source"), field(data, "address")),
+        EVENT_RECORD_UPDATED => format!("记录已更新: {} -> {}", domain, field(data, "value")),
+        EVENT_RECORD_FAILED => format!("记录连续同步失败{}次: {}", field(data, "failures"), domain),
+        EVENT_CREDENTIALS_INVALID => format!("服务商密钥失效: {}", domain),
+        EVENT_DRIFT_DETECTED => format!("解析结果不一致: {}", domain),
+        EVENT_JOB_EXPIRED => format!("同步任务已过期: {}", domain),
+        _ => "测试邮件".to_string(),
+    };
+    format!("[IpTellDnspod] {}", title)
+}
+
+/// 生成 (标题, 纯文本, HTML) 邮件内容
+pub fn render(event: &str, data: &Value) -> (String, String, String) {
+    let subject = subject(event, data);
+    let fields: Vec<(String, String)> = match data.as_object() {
+        Some(map) => map.keys().map(|key| (key.clone(), field(data, key))).collect(),
+        None => vec![],
+    };
+    let mut text = format!("{}\n\nevent: {}\n", subject, event);
+    let mut rows = format!("<tr><th align=\"left\">event</th><td>{}</td></tr>", escape_html(event));
+    for (key, value) in fields {
+        text.push_str(&format!("{}: {}\n", key, value));
+        rows.push_str(&format!(
+            "<tr><th align=\"left\">{}</th><td>{}</td></tr>",
+            escape_html(&key),
+            escape_html(&value)
+        ));
+    }
+    let html = format!(
+        "<html><body><h3>{}</h3><table cellpadding=\"4\">{}</table></body></html>",
+        escape_html(&subject),
+        rows
+    );
+    (subject, text, html)
+}
+
+/// 发送一封邮件给多个收件人
+pub async fn send(config: &SmtpConfig, recipients: &[String], event: &str, data: &Value) -> ItdResult<()> {
+    if !config.enabled || config.host.is_empty() {
+        return err!("SMTP未启用");
+    }
+    let (subject, text, html) = render(event, data);
+    let mut builder = Message::builder().from(config.from.parse::<Mailbox>()?).subject(subject);
+    for recipient in recipients {
+        builder = builder.to(recipient.parse::<Mailbox>()?);
+    }
+    let message = builder.multipart(MultiPart::alternative_plain_html(text, html))?;
+    config.transport()?.send(message).await?;
+    Ok(())
+}
+
+/// 按用户设置发送事件邮件，返回发送的数量
+pub async fn emit(db: &SqlitePool, event: &str, uid: Option<i64>, data: &Value) -> ItdResult<usize> {
+    let config = get_smtp_config(db).await?;
+    if !config.enabled {
+        return Ok(0);
+    }
+    let settings: Vec<EmailSetting> = EmailSettings::new(db)
+        .subscribers(event, uid)
+        .await?
+        .into_iter()
+        // 每次连续失败只在达到阈值时通知一次
+        .filter(|setting| event != EVENT_RECORD_FAILED || data["failures"].as_i64() == Some(setting.failure_threshold))
+        .collect();
+    let mut count = 0;
+    for setting in settings {
+        match send(&config, &setting.recipients, event, data).await {
+            Ok(_) => count += 1,
+            Err(e) => error!("Send {} email to user {} failed: {}", event, setting.uid, e),
+        }
+    }
+    if count > 0 {
+        info!("Event {} emailed to {} users", event, count);
+    }
+    Ok(count)
+}
+
+/// 后台发送事件邮件，不阻塞同步
+pub fn spawn_emit(db: &SqlitePool, event: &str, uid: Option<i64>, data: &Value) {
+    let (db, event, data) = (db.clone(), event.to_string(), data.clone());
+    tokio::spawn(async move {
+        if let Err(e) = emit(&db, &event, uid, &data).await {
+            error!("Send {} email failed: {}", event, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::notify_email::EmailSettingForm;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// 本地 SMTP 接收端，保存收到的邮件内容
+    async fn smtp_sink() -> (u16, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(vec![]));
+        let messages = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let messages = messages.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut reader = BufReader::new(reader);
+                    writer.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).await.unwrap() > 0 {
+                        let command = line.trim_end().to_uppercase();
+                        let reply: &[u8] = if command.starts_with("DATA") {
+                            writer.write_all(b"354 go ahead\r\n").await.unwrap();
+                            let mut message = String::new();
+                            loop {
+                                let mut data = String::new();
+                                reader.read_line(&mut data).await.unwrap();
+                                if data == ".\r\n" {
+                                    break;
+                                }
+                                message.push_str(&data);
+                            }
+                            messages.lock().unwrap().push(message);
+                            b"250 queued\r\n"
+                        } else if command.starts_with("QUIT") {
+                            writer.write_all(b"221 bye\r\n").await.unwrap();
+                            break;
+                        } else {
+                            b"250 OK\r\n"
+                        };
+                        writer.write_all(reply).await.unwrap();
+                        line.clear();
+                    }
+                });
+            }
+        });
+        (port, received)
+    }
+
+    #[test]
+    fn it_render_works() {
+        let data = json!({ "host": "www", "domain": "example.com", "value": "<198.51.100.1>" });
+        let (subject, text, html) = render(EVENT_RECORD_UPDATED, &data);
+        assert_eq!(subject, "[IpTellDnspod] 记录已更新: www.example.com -> <198.51.100.1>");
+        assert!(text.contains("value: <198.51.100.1>"));
+        assert!(html.contains("&lt;198.51.100.1&gt;"));
+        assert!(!html.contains("<198.51.100.1>"));
+    }
+
+    #[tokio::test]
+    async fn it_email_emit_works() -> ItdResult<()> {
+        let (port, received) = smtp_sink().await;
+        let db = crate::tests::memory_db().await;
+        let data = json!({ "host": "www", "domain": "example.com", "failures": 2 });
+        // 未启用 SMTP 时不发送
+        assert_eq!(emit(&db, EVENT_RECORD_FAILED, Some(1), &data).await?, 0);
+
+        let config = SmtpConfig {
+            enabled: true,
+            host: "127.0.0.1".to_string(),
+            port,
+            security: SECURITY_NONE.to_string(),
+            password: Some("smtp-password".to_string()),
+            from: "IpTellDnspod <itd@example.com>".to_string(),
+            ..Default::default()
+        };
+        set_smtp_config(&db, &config).await?;
+        let saved = get_smtp_config(&db).await?;
+        assert_ne!(saved.password.as_deref(), Some("smtp-password"));
+        assert_eq!(decrypt_to_str(saved.password.as_deref().unwrap())?, "smtp-password");
+        // 未填写密码时保持原密码
+        set_smtp_config(&db, &SmtpConfig { password: None, ..config }).await?;
+        assert_eq!(get_smtp_config(&db).await?.password, saved.password);
+
+        let model = EmailSettings::new(&db);
+        let form = |recipients: &[&str], threshold: i64| EmailSettingForm {
+            recipients: recipients.iter().map(|item| item.to_string()).collect(),
+            events: vec![EVENT_IP_CHANGED.to_string(), EVENT_RECORD_FAILED.to_string()],
+            failure_threshold: Some(threshold),
+            status: None,
+        };
+        model.set(1, form(&["ops@example.com", "admin@example.com"], 2)).await?;
+        model.set(2, form(&["other@example.com"], 3)).await?;
+        assert_eq!(model.get(1).await?.unwrap().recipients.len(), 2);
+        assert_eq!(emit(&db, EVENT_RECORD_FAILED, Some(1), &json!({ "failures": 1 })).await?, 0);
+        assert_eq!(emit(&db, EVENT_DRIFT_DETECTED, Some(1), &data).await?, 0);
+        assert_eq!(emit(&db, EVENT_RECORD_FAILED, Some(1), &data).await?, 1);
+        assert_eq!(emit(&db, EVENT_IP_CHANGED, None, &json!({ "address": "198.51.100.1" })).await?, 2);
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 3);
+        let message = &received[0];
+        assert!(message.contains("To: ops@example.com, admin@example.com"));
+        assert!(message.contains("Content-Type: text/plain"));
+        assert!(message.contains("Content-Type: text/html"));
+        Ok(())
+    }
+}
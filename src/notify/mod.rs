@@ -0,0 +1,276 @@
+//! Webhook 通知
+//! IP变化、记录更新或失败、密钥失效、解析漂移等事件按用户订阅写入投递表，
+//! 后台任务以 JSON POST 到目标地址，请求带 HMAC-SHA256 签名，失败后按指数退避重试，
+//! 同时按用户的邮件设置发送邮件
+use crate::error::ItdResult;
+use crate::model::records::{Record, Records};
+use crate::model::webhook::{Webhook, WebhookDelivery, Webhooks, DELIVERY_DONE, DELIVERY_FAILED, DELIVERY_PENDING};
+use crate::provider::AUTH_FAILURE;
+use crate::utils::timestamp;
+use chrono::{Local, TimeDelta};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use sqlx::SqlitePool;
+use std::time::Duration;
+use tracing::{error, info};
+
+pub mod email;
+
+pub const EVENT_IP_CHANGED: &str = "ip_changed";
+pub const EVENT_RECORD_UPDATED: &str = "record_updated";
+/// 记录连续同步失败达到通知目标设置的次数
+pub const EVENT_RECORD_FAILED: &str = "record_failed";
+/// 服务商拒绝了应用的密钥
+pub const EVENT_CREDENTIALS_INVALID: &str = "credentials_invalid";
+/// 发布校验发现DNS服务器上的值与期望值不一致
+pub const EVENT_DRIFT_DETECTED: &str = "drift_detected";
+/// 同步任务超过最长保留时间仍未成功
+pub const EVENT_JOB_EXPIRED: &str = "job_expired";
+/// 测试通知目标，不需要订阅
+pub const EVENT_PING: &str = "ping";
+
+pub const EVENTS: [&str; 6] = [
+    EVENT_IP_CHANGED,
+    EVENT_RECORD_UPDATED,
+    EVENT_RECORD_FAILED,
+    EVENT_CREDENTIALS_INVALID,
+    EVENT_DRIFT_DETECTED,
+    EVENT_JOB_EXPIRED,
+];
+
+/// 最多投递次数
+const MAX_ATTEMPTS: i64 = 6;
+/// 首次重试的等待时间(秒)，之后每次翻倍
+const RETRY_DELAY: i64 = 30;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 签名内容为 "{timestamp}.{body}"，结果为十六进制
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn payload(event: &str, data: &Value) -> ItdResult<String> {
+    let body = json!({
+        "event": event,
+        "created_at": Local::now().naive_local(),
+        "data": data,
+    });
+    Ok(serde_json::to_string(&body)?)
+}
+
+/// 把事件加入订阅者的投递队列并在后台发送邮件，uid 为空时发给所有用户，返回 Webhook 投递数量
+pub async fn emit(db: &SqlitePool, event: &str, uid: Option<i64>, data: Value) -> ItdResult<usize> {
+    email::spawn_emit(db, event, uid, &data);
+    let model = Webhooks::new(db);
+    let body = payload(event, &data)?;
+    let mut count = 0;
+    for webhook in model.subscribers(event, uid).await? {
+        // 每次连续失败只在达到阈值时通知一次
+        if event == EVENT_RECORD_FAILED && data["failures"].as_i64() != Some(webhook.failure_threshold) {
+            continue;
+        }
+        model.enqueue(webhook.id, event, &body).await?;
+        count += 1;
+    }
+    if count > 0 {
+        info!("Event {} queued for {} webhooks", event, count);
+    }
+    Ok(count)
+}
+
+/// 向单个通知目标发送测试事件
+pub async fn ping(db: &SqlitePool, webhook: &Webhook) -> ItdResult<i64> {
+    let body = payload(EVENT_PING, &json!({ "webhook_id": webhook.id }))?;
+    Webhooks::new(db).enqueue(webhook.id, EVENT_PING, &body).await
+}
+
+/// 记录相关的事件，发给记录所属应用的用户
+pub async fn emit_record(db: &SqlitePool, event: &str, record: &Record, mut data: Value) -> ItdResult<usize> {
+    let uid = Webhooks::new(db).app_owner(record.appid).await?;
+    data["record_id"] = json!(record.id);
+    data["appid"] = json!(record.appid);
+    data["host"] = json!(record.host);
+    data["domain"] = json!(record.domain);
+    data["ip_type"] = json!(record.ip_type);
+    emit(db, event, uid, data).await
+}
+
+/// 按同步结果发送 record_updated、record_failed 或 credentials_invalid
+/// 通知失败只记录日志，不影响同步
+pub async fn record_synced(db: &SqlitePool, record: &Record, value: &str, result: &ItdResult<()>) {
+    if let Err(e) = sync_events(db, record, value, result).await {
+        error!("Notify sync result of {}.{} failed: {}", record.host, record.domain, e);
+    }
+}
+
+async fn sync_events(db: &SqlitePool, record: &Record, value: &str, result: &ItdResult<()>) -> ItdResult<()> {
+    let e = match result {
+        Ok(_) => {
+            let data = json!({ "old_value": record.ip, "value": value });
+            emit_record(db, EVENT_RECORD_UPDATED, record, data).await?;
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+    let failures = match Records::new(db).get_record(record.id as i64).await? {
+        Some(record) => record.consecutive_failures,
+        None => return Ok(()),
+    };
+    // 密钥失效在连续失败开始时通知一次
+    if e.kind() == AUTH_FAILURE && failures == 1 {
+        let data = json!({ "error": e.to_string() });
+        emit_record(db, EVENT_CREDENTIALS_INVALID, record, data).await?;
+    }
+    let data = json!({ "value": value, "failures": failures, "error": e.to_string() });
+    emit_record(db, EVENT_RECORD_FAILED, record, data).await?;
+    Ok(())
+}
+
+/// 投递一次，失败时按退避时间重新排队
+async fn deliver(db: &SqlitePool, delivery: &WebhookDelivery) -> ItdResult<()> {
+    let model = Webhooks::new(db);
+    let webhook = match model.get(delivery.webhook_id).await? {
+        Some(webhook) => webhook,
+        None => {
+            let detail = Some("Webhook not found".to_string());
+            return model.set_delivery(delivery.id, DELIVERY_FAILED, delivery.attempts, None, None, detail).await;
+        }
+    };
+    let attempts = delivery.attempts + 1;
+    let (response_status, result) = match send(&webhook, delivery).await {
+        Ok(status) if (200..300).contains(&status) => (Some(status), Ok(())),
+        Ok(status) => (Some(status), Err(format!("http status code: {}", status))),
+        Err(e) => (None, Err(e.to_string())),
+    };
+    match result {
+        Ok(_) => model.set_delivery(delivery.id, DELIVERY_DONE, attempts, None, response_status, None).await,
+        Err(e) if attempts >= MAX_ATTEMPTS => {
+            error!("Webhook delivery {} to {} failed after {} attempts: {}", delivery.id, webhook.url, attempts, e);
+            model.set_delivery(delivery.id, DELIVERY_FAILED, attempts, None, response_status, Some(e)).await
+        }
+        Err(e) => {
+            let delay = RETRY_DELAY << (attempts - 1);
+            error!("Webhook delivery {} to {} failed, retry in {}s: {}", delivery.id, webhook.url, delay, e);
+            let next_attempt_at = Local::now().naive_local() + TimeDelta::seconds(delay);
+            model
+                .set_delivery(delivery.id, DELIVERY_PENDING, attempts, Some(next_attempt_at), response_status, Some(e))
+                .await
+        }
+    }
+}
+
+/// 发送请求，返回HTTP状态码
+async fn send(webhook: &Webhook, delivery: &WebhookDelivery) -> ItdResult<i64> {
+    let timestamp = timestamp();
+    let signature = sign(&webhook.signing_key()?, timestamp, &delivery.payload);
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+    let resp = client
+        .post(&webhook.url)
+        .header("Content-Type", "application/json; charset=utf-8")
+        .header("X-Itd-Event", &delivery.event)
+        .header("X-Itd-Delivery", delivery.id.to_string())
+        .header("X-Itd-Timestamp", timestamp.to_string())
+        .header("X-Itd-Signature", format!("sha256={}", signature))
+        .body(delivery.payload.clone())
+        .send()
+        .await?;
+    Ok(resp.status().as_u16() as i64)
+}
+
+/// 投递所有到期的事件，返回失败的数量
+pub async fn drain(db: &SqlitePool) -> ItdResult<usize> {
+    let mut failed = 0;
+    for delivery in Webhooks::new(db).due().await? {
+        if let Err(e) = deliver(db, &delivery).await {
+            failed += 1;
+            error!("Webhook delivery {} failed: {}", delivery.id, e);
+        }
+    }
+    Ok(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::webhook::WebhookForm;
+    use axum::http::HeaderMap;
+    use axum::{routing::post, Router};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn it_sign_works() {
+        let signature = sign("secret", 1700000000, r#"{"event":"ping"}"#);
+        assert_eq!(signature.len(), 64);
+        assert_eq!(signature, sign("secret", 1700000000, r#"{"event":"ping"}"#));
+        assert_ne!(signature, sign("secret", 1700000001, r#"{"event":"ping"}"#));
+    }
+
+    #[tokio::test]
+    async fn it_webhook_delivery_works() -> ItdResult<()> {
+        let received = Arc::new(Mutex::new(vec![]));
+        let seen = received.clone();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(move |headers: HeaderMap, body: String| async move {
+                    let header = |name: &str| headers[name].to_str().unwrap().to_string();
+                    seen.lock().unwrap().push((header("x-itd-event"), header("x-itd-timestamp"), header("x-itd-signature"), body));
+                    "ok"
+                }),
+            )
+            .route("/down", post(|| async { (http::StatusCode::SERVICE_UNAVAILABLE, "down") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        let model = Webhooks::new(&db);
+        let form = |url: &str, events: &[&str]| WebhookForm {
+            url: url.to_string(),
+            secret: Some("0123456789abcdef".to_string()),
+            events: events.iter().map(|event| event.to_string()).collect(),
+            failure_threshold: Some(2),
+            status: None,
+        };
+        let (hook, secret) = model.add(1, form(&format!("http://{}/hook", addr), &[EVENT_IP_CHANGED, EVENT_RECORD_FAILED])).await?;
+        let (down, _) = model.add(2, form(&format!("http://{}/down", addr), &[EVENT_IP_CHANGED])).await?;
+        // 其它用户的记录事件不发给该用户
+        assert_eq!(emit(&db, EVENT_IP_CHANGED, None, json!({ "address": "198.51.100.1" })).await?, 2);
+        assert_eq!(emit(&db, EVENT_RECORD_FAILED, Some(2), json!({ "failures": 2 })).await?, 0);
+        assert_eq!(emit(&db, EVENT_RECORD_FAILED, Some(1), json!({ "failures": 1 })).await?, 0);
+        assert_eq!(emit(&db, EVENT_RECORD_FAILED, Some(1), json!({ "failures": 2 })).await?, 1);
+        assert_eq!(emit(&db, EVENT_DRIFT_DETECTED, Some(1), json!({})).await?, 0);
+        drain(&db).await?;
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 2);
+        let (event, timestamp, signature, body) = &received[0];
+        assert_eq!(event, EVENT_IP_CHANGED);
+        let expected = sign(&secret, timestamp.parse().unwrap(), body);
+        assert_eq!(signature, &format!("sha256={}", expected));
+        let body: Value = serde_json::from_str(body)?;
+        assert_eq!(body["data"]["address"], "198.51.100.1");
+        assert_eq!(model.deliveries(hook, 1).await?.data[0].status, DELIVERY_DONE);
+
+        // 失败后按退避时间重试
+        let failed = &model.deliveries(down, 1).await?.data[0];
+        assert_eq!(failed.status, DELIVERY_PENDING);
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(failed.response_status, Some(503));
+        assert!(failed.next_attempt_at > Local::now().naive_local());
+        sqlx::query(r#"UPDATE webhook_delivery SET attempts=?, next_attempt_at=? WHERE id=?"#)
+            .bind(MAX_ATTEMPTS - 1)
+            .bind(Local::now().naive_local())
+            .bind(failed.id)
+            .execute(&db)
+            .await?;
+        drain(&db).await?;
+        assert_eq!(model.deliveries(down, 1).await?.data[0].status, DELIVERY_FAILED);
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+//! 基于HTTP请求模板的自定义服务商
+//! 用于对接没有原生支持的DNS服务商或内部IPAM系统
+//! 模板中可使用以下占位符:
+//! {host} {domain} {fqdn} {type} {ip} {ttl} {record_id} {secret_id} {secret_key}
+//! 修改记录时还可以使用 {weight}，未设置权重时为空
+//! {line} 为解析线路，未设置线路时为空
+use crate::error::ItdResult;
+use crate::ipaddr::bind::Bind;
+use crate::provider::{http_client, DnsProvider, AUTH_FAILURE};
+use crate::{err, kerr, BoxFuture};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_json_path::JsonPath;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::info;
+
+/// 自定义服务商配置，保存在 user_apps.provider_config 中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomConfig {
+    pub create: RequestTemplate,
+    pub update: RequestTemplate,
+    pub delete: RequestTemplate,
+}
+
+/// 单个请求模板
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestTemplate {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: Option<String>,
+    /// 判断请求成功的规则，为空时仅判断HTTP状态码为2xx
+    #[serde(default)]
+    pub success: Option<Extractor>,
+    /// 提取记录ID的规则，仅 create 请求使用
+    #[serde(default)]
+    pub record_id: Option<Extractor>,
+}
+
+/// 响应内容提取规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Extractor {
+    /// JSONPath 表达式，如 $.result.id
+    Jsonpath { path: String },
+    /// 正则表达式，有分组时取第一个分组
+    Regex { pattern: String },
+}
+
+impl Extractor {
+    /// 校验规则是否合法
+    pub fn check(&self) -> ItdResult<()> {
+        match self {
+            Extractor::Jsonpath { path } => {
+                if JsonPath::parse(path).is_err() {
+                    return err!(format!("JSONPath 格式错误: {}", path));
+                }
+            }
+            Extractor::Regex { pattern } => {
+                if Regex::new(pattern).is_err() {
+                    return err!(format!("正则表达式格式错误: {}", pattern));
+                }
+            }
+        }
+        Ok(())
+    }
+    /// 从响应内容中提取值，未匹配时返回None
+    pub fn extract(&self, text: &str) -> ItdResult<Option<String>> {
+        match self {
+            Extractor::Jsonpath { path } => {
+                let json_path = match JsonPath::parse(path) {
+                    Ok(json_path) => json_path,
+                    Err(_) => return err!(format!("JSONPath 格式错误: {}", path)),
+                };
+                let value: Value = serde_json::from_str(text)?;
+                let found = json_path.query(&value).first().cloned();
+                let result = match found {
+                    None | Some(Value::Null) | Some(Value::Bool(false)) => None,
+                    Some(Value::String(s)) => Some(s.to_string()),
+                    Some(other) => Some(other.to_string()),
+                };
+                Ok(result)
+            }
+            Extractor::Regex { pattern } => {
+                let re = match Regex::new(pattern) {
+                    Ok(re) => re,
+                    Err(_) => return err!(format!("正则表达式格式错误: {}", pattern)),
+                };
+                let result = re.captures(text).map(|caps| {
+                    caps.get(1)
+                        .or_else(|| caps.get(0))
+                        .map(|m| m.as_str().to_string())
+                        .unwrap_or_default()
+                });
+                Ok(result)
+            }
+        }
+    }
+}
+
+impl CustomConfig {
+    /// 校验配置
+    pub fn check(&self) -> ItdResult<()> {
+        for template in [&self.create, &self.update, &self.delete] {
+            if reqwest::Method::from_bytes(template.method.to_uppercase().as_bytes()).is_err() {
+                return err!(format!("不支持的请求方法: {}", template.method));
+            }
+            if template.url.is_empty() {
+                return err!("请求模板缺少url");
+            }
+            if let Some(success) = &template.success {
+                success.check()?;
+            }
+            if let Some(record_id) = &template.record_id {
+                record_id.check()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct CustomProvider {
+    config: CustomConfig,
+    secret_id: String,
+    secret_key: String,
+    /// 请求的出口绑定
+    pub bind: Bind,
+}
+
+impl CustomProvider {
+    pub fn new(config: CustomConfig, secret_id: String, secret_key: String) -> Self {
+        CustomProvider {
+            config,
+            secret_id,
+            secret_key,
+            bind: Bind::default(),
+        }
+    }
+    /// 替换模板中的占位符
+    /// 只扫描一遍模板，替换进来的值不再展开，避免主机名等值里的 {secret_key} 泄露密钥
+    pub fn render(&self, template: &str, vars: &HashMap<&str, String>) -> String {
+        let mut result = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest.find('}').and_then(|end| Some((end, vars.get(&rest[1..end])?)));
+            match value {
+                Some((end, value)) => {
+                    result.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    result.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        result.push_str(rest);
+        result
+    }
+    fn vars(
+        &self,
+        host: &str,
+        domain: &str,
+        record_id: &str,
+        record_type: &str,
+        value: &str,
+        ttl: i32,
+    ) -> HashMap<&'static str, String> {
+        let fqdn = if host.is_empty() || host == "@" {
+            domain.to_string()
+        } else {
+            format!("{}.{}", host, domain)
+        };
+        HashMap::from([
+            ("host", host.to_string()),
+            ("domain", domain.to_string()),
+            ("fqdn", fqdn),
+            ("type", record_type.to_string()),
+            ("ip", value.to_string()),
+            ("ttl", ttl.to_string()),
+            ("record_id", record_id.to_string()),
+            ("secret_id", self.secret_id.clone()),
+            ("secret_key", self.secret_key.clone()),
+        ])
+    }
+    /// 发送模板请求并校验结果，返回响应内容
+    pub async fn do_request(
+        &self,
+        template: &RequestTemplate,
+        vars: &HashMap<&str, String>,
+    ) -> ItdResult<String> {
+        let method = match reqwest::Method::from_bytes(template.method.to_uppercase().as_bytes()) {
+            Ok(method) => method,
+            Err(_) => return err!(format!("不支持的请求方法: {}", template.method)),
+        };
+        let url = self.render(&template.url, vars);
+        let client = http_client(&self.bind, Duration::from_secs(30))?;
+        let mut req = client.request(method, &url);
+        for (name, value) in template.headers.iter() {
+            req = req.header(name.as_str(), self.render(value, vars));
+        }
+        if let Some(body) = &template.body {
+            req = req.body(self.render(body, vars));
+        }
+        // 渲染后的URL可能包含密钥，只记录模板
+        info!("custom provider request ===> {} {}", template.method, template.url);
+        let resp = req.send().await?;
+        let status_code = resp.status();
+        let res_text = resp.text().await?;
+        if status_code == 401 || status_code == 403 {
+            return kerr!(AUTH_FAILURE, format!("http status code: {}, {}", status_code, res_text));
+        }
+        if !status_code.is_success() {
+            return err!(format!("http status code: {}, {}", status_code, res_text));
+        }
+        if let Some(success) = &template.success {
+            if success.extract(&res_text)?.is_none() {
+                return err!(format!("请求未成功: {}", res_text));
+            }
+        }
+        Ok(res_text)
+    }
+}
+
+impl DnsProvider for CustomProvider {
+    fn add_record<'a>(
+        &'a self,
+        host: &'a str,
+        domain: &'a str,
+        record_type: &'a str,
+        value: &'a str,
+        ttl: i32,
+        line: Option<&'a str>,
+    ) -> BoxFuture<'a, String> {
+        Box::pin(async move {
+            let mut vars = self.vars(host, domain, "", record_type, value, ttl);
+            vars.insert("line", line.unwrap_or_default().to_string());
+            let template = &self.config.create;
+            let res_text = self.do_request(template, &vars).await?;
+            let record_id = match &template.record_id {
+                Some(extractor) => match extractor.extract(&res_text)? {
+                    Some(record_id) => record_id,
+                    None => return err!(format!("未能从响应中提取记录ID: {}", res_text)),
+                },
+                // 未配置提取规则时，服务商按主机名识别记录
+                None => "0".to_string(),
+            };
+            Ok(record_id)
+        })
+    }
+    fn modify_record<'a>(
+        &'a self,
+        host: &'a str,
+        domain: &'a str,
+        record_id: &'a str,
+        record_type: &'a str,
+        value: &'a str,
+        ttl: i32,
+        weight: Option<i32>,
+        line: Option<&'a str>,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let mut vars = self.vars(host, domain, record_id, record_type, value, ttl);
+            vars.insert("weight", weight.map(|weight| weight.to_string()).unwrap_or_default());
+            vars.insert("line", line.unwrap_or_default().to_string());
+            self.do_request(&self.config.update, &vars).await?;
+            Ok(())
+        })
+    }
+    fn delete_record<'a>(&'a self, domain: &'a str, record_id: &'a str) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let vars = self.vars("", domain, record_id, "", "", 0);
+            self.do_request(&self.config.delete, &vars).await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+
+    fn template(url: &str) -> RequestTemplate {
+        RequestTemplate {
+            method: "POST".to_string(),
+            url: url.to_string(),
+            headers: HashMap::from([(
+                "Authorization".to_string(),
+                "Bearer {secret_key}".to_string(),
+            )]),
+            body: Some(r#"{"name":"{fqdn}","value":"{ip}","ttl":{ttl}}"#.to_string()),
+            success: Some(Extractor::Jsonpath {
+                path: "$.success".to_string(),
+            }),
+            record_id: Some(Extractor::Jsonpath {
+                path: "$.result.id".to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn it_extractor_works() -> ItdResult<()> {
+        let text = r#"{"success":true,"result":{"id":"5f3a","num":12}}"#;
+        let path = Extractor::Jsonpath {
+            path: "$.result.id".to_string(),
+        };
+        assert_eq!(path.extract(text)?, Some("5f3a".to_string()));
+        let path = Extractor::Jsonpath {
+            path: "$.result.num".to_string(),
+        };
+        assert_eq!(path.extract(text)?, Some("12".to_string()));
+        let path = Extractor::Jsonpath {
+            path: "$.result.missing".to_string(),
+        };
+        assert_eq!(path.extract(text)?, None);
+        let re = Extractor::Regex {
+            pattern: r"id=(\d+)".to_string(),
+        };
+        assert_eq!(re.extract("ok id=42")?, Some("42".to_string()));
+        assert_eq!(re.extract("failed")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn it_render_works() {
+        let provider = CustomProvider::new(
+            CustomConfig {
+                create: template("http://127.0.0.1/"),
+                update: template("http://127.0.0.1/"),
+                delete: template("http://127.0.0.1/"),
+            },
+            "id".to_string(),
+            "key".to_string(),
+        );
+        let vars = provider.vars("www", "example.com", "7", "A", "1.2.3.4", 600);
+        let body = provider.render(r#"{"name":"{fqdn}","id":{record_id},"ttl":{ttl}}"#, &vars);
+        assert_eq!(body, r#"{"name":"www.example.com","id":7,"ttl":600}"#);
+        let vars = provider.vars("@", "example.com", "", "A", "1.2.3.4", 600);
+        assert_eq!(provider.render("{fqdn}", &vars), "example.com");
+        // 值里的占位符原样保留，不会展开成密钥
+        let vars = provider.vars("{secret_key}", "example.com", "7", "TXT", "{secret_id}", 600);
+        assert_eq!(
+            provider.render("/{host}?v={ip}&k={secret_key}{", &vars),
+            "/{secret_key}?v={secret_id}&k=key{"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_add_record_works() -> ItdResult<()> {
+        let app = Router::new().route(
+            "/records",
+            post(|body: String| async move {
+                if body.contains("www.example.com") && body.contains("1.2.3.4") {
+                    r#"{"success":true,"result":{"id":"abc123"}}"#
+                } else {
+                    r#"{"success":false}"#
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/records", listener.local_addr()?);
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let config = CustomConfig {
+            create: template(&url),
+            update: template(&url),
+            delete: template(&url),
+        };
+        config.check()?;
+        let provider = CustomProvider::new(config, "id".to_string(), "key".to_string());
+        let record_id = provider
+            .add_record("www", "example.com", "A", "1.2.3.4", 600, None)
+            .await?;
+        assert_eq!(record_id, "abc123");
+        let result = provider
+            .add_record("www", "example.com", "A", "5.6.7.8", 600, None)
+            .await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn it_bind_works() -> ItdResult<()> {
+        use axum::extract::ConnectInfo;
+        use std::net::SocketAddr;
+        // 返回请求方地址作为记录ID
+        let app = Router::new().route(
+            "/records",
+            post(|ConnectInfo(addr): ConnectInfo<SocketAddr>| async move {
+                format!(r#"{{"success":true,"result":{{"id":"{}"}}}}"#, addr.ip())
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/records", listener.local_addr()?);
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .unwrap();
+        });
+        let config = CustomConfig {
+            create: template(&url),
+            update: template(&url),
+            delete: template(&url),
+        };
+        let mut provider = CustomProvider::new(config, "id".to_string(), "key".to_string());
+        provider.bind.local_address = Some("127.0.0.2".parse()?);
+        let record_id = provider
+            .add_record("www", "example.com", "A", "1.2.3.4", 600, None)
+            .await?;
+        assert_eq!(record_id, "127.0.0.2");
+        Ok(())
+    }
+}
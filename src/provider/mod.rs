@@ -0,0 +1,243 @@
+//! 域名解析服务商抽象
+//! 每个 user_apps 记录对应一个服务商，默认为 DNSPod，
+//! 也可以配置为基于HTTP请求模板的自定义服务商
+use crate::dnspod::action::PodAction;
+use crate::error::ItdResult;
+use crate::ipaddr::bind::Bind;
+use crate::queue::limiter::RateLimiter;
+use crate::utils::decrypt_to_str;
+use crate::{err, BoxFuture};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod custom;
+
+use custom::CustomProvider;
+
+pub const PROVIDER_DNSPOD: &str = "dnspod";
+pub const PROVIDER_CUSTOM: &str = "custom";
+/// 服务商拒绝了密钥，重试无法恢复
+pub const AUTH_FAILURE: &str = "ProviderAuth";
+
+/// 解析记录的增删改操作
+/// 记录ID统一使用字符串表示，DNSPod 的数字ID也按字符串传递
+pub trait DnsProvider: Send + Sync {
+    /// 添加记录，返回服务商侧的记录ID
+    /// line 为解析线路，为空时使用默认线路
+    #[allow(clippy::too_many_arguments)]
+    fn add_record<'a>(
+        &'a self,
+        host: &'a str,
+        domain: &'a str,
+        record_type: &'a str,
+        value: &'a str,
+        ttl: i32,
+        line: Option<&'a str>,
+    ) -> BoxFuture<'a, String>;
+    /// 修改记录，weight 为空时保持服务商侧的权重，line 为空时使用默认线路
+    #[allow(clippy::too_many_arguments)]
+    fn modify_record<'a>(
+        &'a self,
+        host: &'a str,
+        domain: &'a str,
+        record_id: &'a str,
+        record_type: &'a str,
+        value: &'a str,
+        ttl: i32,
+        weight: Option<i32>,
+        line: Option<&'a str>,
+    ) -> BoxFuture<'a, ()>;
+    /// 删除记录
+    fn delete_record<'a>(&'a self, domain: &'a str, record_id: &'a str) -> BoxFuture<'a, ()>;
+}
+
+/// 根据 appid 加载对应的服务商，每次请求前按应用限速
+pub async fn load_provider(
+    db: &sqlx::Pool<sqlx::Sqlite>,
+    limiter: &Arc<RateLimiter>,
+    appid: i32,
+) -> ItdResult<Box<dyn DnsProvider>> {
+    let provider = load_unlimited(db, appid).await?;
+    Ok(Box::new(Limited {
+        provider,
+        limiter: limiter.clone(),
+        appid,
+    }))
+}
+
+async fn load_unlimited(
+    db: &sqlx::Pool<sqlx::Sqlite>,
+    appid: i32,
+) -> ItdResult<Box<dyn DnsProvider>> {
+    let result = sqlx::query_as::<_, (String, String, Option<String>, Option<String>, Option<String>)>(
+        "select secret_id,secret_key,provider,provider_config,bind from user_apps where id = ?",
+    )
+    .bind(appid)
+    .fetch_optional(db)
+    .await?;
+    if result.is_none() {
+        return err!("appid not found");
+    }
+    let (secret_id, secret_key, provider, provider_config, bind) = result.unwrap();
+    let provider = provider.unwrap_or(PROVIDER_DNSPOD.to_string());
+    match provider.as_str() {
+        PROVIDER_DNSPOD => Ok(Box::new(PodAction::new(db, appid).await?)),
+        PROVIDER_CUSTOM => {
+            let config = match provider_config {
+                Some(config) => serde_json::from_str(&config)?,
+                None => return err!("自定义服务商缺少请求模板配置"),
+            };
+            let secret_id = decrypt_to_str(&secret_id)?;
+            let secret_key = decrypt_to_str(&secret_key)?;
+            let mut provider = CustomProvider::new(config, secret_id, secret_key);
+            provider.bind = parse_bind(bind)?;
+            Ok(Box::new(provider))
+        }
+        _ => err!(format!("不支持的服务商类型: {}", provider)),
+    }
+}
+
+/// 解析 user_apps.bind 中保存的出口绑定
+pub fn parse_bind(bind: Option<String>) -> ItdResult<Bind> {
+    match bind {
+        Some(bind) => Ok(serde_json::from_str(&bind)?),
+        None => Ok(Bind::default()),
+    }
+}
+
+/// 服务商请求的HTTP客户端，配置了出口绑定时从指定线路发出，否则使用系统代理等默认设置
+pub fn http_client(bind: &Bind, timeout: Duration) -> ItdResult<reqwest::Client> {
+    let builder = if bind.is_empty() {
+        reqwest::Client::builder()
+    } else {
+        bind.client_builder()?
+    };
+    Ok(builder.timeout(timeout).build()?)
+}
+
+/// Dnspod 的记录ID为数字
+pub fn numeric_record_id(record_id: &str) -> ItdResult<i32> {
+    match record_id.parse::<i32>() {
+        Ok(id) => Ok(id),
+        Err(_) => err!(format!("记录ID不是数字: {}", record_id)),
+    }
+}
+
+/// 经过限速的服务商
+struct Limited {
+    provider: Box<dyn DnsProvider>,
+    limiter: Arc<RateLimiter>,
+    appid: i32,
+}
+
+impl DnsProvider for Limited {
+    fn add_record<'a>(
+        &'a self,
+        host: &'a str,
+        domain: &'a str,
+        record_type: &'a str,
+        value: &'a str,
+        ttl: i32,
+        line: Option<&'a str>,
+    ) -> BoxFuture<'a, String> {
+        Box::pin(async move {
+            self.limiter.acquire(self.appid).await;
+            self.provider.add_record(host, domain, record_type, value, ttl, line).await
+        })
+    }
+    fn modify_record<'a>(
+        &'a self,
+        host: &'a str,
+        domain: &'a str,
+        record_id: &'a str,
+        record_type: &'a str,
+        value: &'a str,
+        ttl: i32,
+        weight: Option<i32>,
+        line: Option<&'a str>,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            self.limiter.acquire(self.appid).await;
+            self.provider
+                .modify_record(host, domain, record_id, record_type, value, ttl, weight, line)
+                .await
+        })
+    }
+    fn delete_record<'a>(&'a self, domain: &'a str, record_id: &'a str) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            self.limiter.acquire(self.appid).await;
+            self.provider.delete_record(domain, record_id).await
+        })
+    }
+}
+
+impl DnsProvider for PodAction {
+    fn add_record<'a>(
+        &'a self,
+        host: &'a str,
+        domain: &'a str,
+        record_type: &'a str,
+        value: &'a str,
+        ttl: i32,
+        line: Option<&'a str>,
+    ) -> BoxFuture<'a, String> {
+        Box::pin(async move {
+            let record_id = self.add_domain(host, domain, record_type, value, ttl, line).await?;
+            Ok(record_id.to_string())
+        })
+    }
+    fn modify_record<'a>(
+        &'a self,
+        host: &'a str,
+        domain: &'a str,
+        record_id: &'a str,
+        record_type: &'a str,
+        value: &'a str,
+        ttl: i32,
+        weight: Option<i32>,
+        line: Option<&'a str>,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let record_id = numeric_record_id(record_id)?;
+            PodAction::modify_record(self, host, domain, record_id, record_type, value, ttl, weight, line)
+                .await?;
+            Ok(())
+        })
+    }
+    fn delete_record<'a>(&'a self, domain: &'a str, record_id: &'a str) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let record_id = numeric_record_id(record_id)?;
+            PodAction::delete_record(self, domain, record_id).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::put, Router};
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn it_load_provider_limits_requests() -> ItdResult<()> {
+        let app = Router::new().route("/update/:id", put(|| async { "ok" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        let appid = crate::tests::insert_custom_app(&db, &format!("http://{}/update/{{record_id}}", addr)).await?;
+        // 两次加载共用同一个令牌桶
+        let limiter = Arc::new(RateLimiter::new(5.0, 1));
+        let started = Instant::now();
+        for _ in 0..2 {
+            let provider = load_provider(&db, &limiter, appid as i32).await?;
+            provider
+                .modify_record("www", "example.com", "1", "A", "198.51.100.1", 600, None, None)
+                .await?;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(180));
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! 按服务商应用限速的令牌桶，避免并发更新触发接口频率限制(RequestLimitExceeded)
+//! 整个进程共用 AppState 中的一个限速器，所有服务商请求都经过它
+use crate::queue::QueueConfig;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+#[derive(Debug)]
+struct Limits {
+    /// 每秒补充的令牌数
+    rate: f64,
+    /// 桶容量，允许的突发请求数
+    burst: f64,
+    buckets: HashMap<i32, Bucket>,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: Mutex<Limits>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        let config = QueueConfig::default();
+        RateLimiter::new(config.rate, config.burst)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        RateLimiter {
+            limits: Mutex::new(Limits {
+                rate,
+                burst: burst.max(1) as f64,
+                buckets: HashMap::new(),
+            }),
+        }
+    }
+    /// 修改限速配置，已有的令牌数不超过新的桶容量
+    pub async fn configure(&self, rate: f64, burst: u32) {
+        let mut limits = self.limits.lock().await;
+        limits.rate = rate;
+        limits.burst = burst.max(1) as f64;
+        let burst = limits.burst;
+        for bucket in limits.buckets.values_mut() {
+            bucket.tokens = bucket.tokens.min(burst);
+        }
+    }
+    /// 取出一个令牌，返回需要等待的时间
+    async fn reserve(&self, appid: i32) -> Duration {
+        let mut limits = self.limits.lock().await;
+        let (rate, burst) = (limits.rate, limits.burst);
+        let now = Instant::now();
+        let bucket = limits.buckets.entry(appid).or_insert(Bucket { tokens: burst, last: now });
+        let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.last = now;
+        // 令牌可以为负，排在后面的请求等待更久
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 || rate <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-bucket.tokens / rate)
+    }
+    /// 等待应用有可用的令牌
+    pub async fn acquire(&self, appid: i32) {
+        let wait = self.reserve(appid).await;
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_rate_limiter_works() {
+        let limiter = RateLimiter::new(10.0, 2);
+        assert_eq!(limiter.reserve(1).await, Duration::ZERO);
+        assert_eq!(limiter.reserve(1).await, Duration::ZERO);
+        let wait = limiter.reserve(1).await;
+        assert!(wait > Duration::from_millis(80) && wait <= Duration::from_millis(100));
+        let wait = limiter.reserve(1).await;
+        assert!(wait > Duration::from_millis(180) && wait <= Duration::from_millis(200));
+        // 不同应用互不影响
+        assert_eq!(limiter.reserve(2).await, Duration::ZERO);
+        // 调整配置后保留已消耗的令牌
+        limiter.configure(1.0, 1).await;
+        assert!(limiter.reserve(1).await > Duration::from_secs(2));
+        assert_eq!(limiter.reserve(3).await, Duration::ZERO);
+    }
+}
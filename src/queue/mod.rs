@@ -0,0 +1,474 @@
+//! 记录同步队列
+//! 检测到IP变化后把期望值写入 sync_job 表，后台任务按指数退避重试直到成功或超过最长保留时间，
+//! 服务商暂时不可用时更新不会丢失
+use crate::error::ItdResult;
+use crate::ipaddr::isp::resolve_line;
+use crate::model::records::{Record, Records};
+use crate::model::setting::SettingModel;
+use crate::notify;
+use crate::model::sync_job::{SyncJob, SyncJobs, JOB_CANCELLED, JOB_DONE, JOB_FAILED, JOB_PENDING};
+use crate::model::targets::Targets;
+use crate::provider::load_provider;
+use crate::verify::spawn_verify;
+use crate::AppState;
+use chrono::{Local, TimeDelta};
+use limiter::RateLimiter;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::{error, info};
+
+pub mod limiter;
+pub mod run;
+
+pub const QUEUE_KEY: &str = "sync_queue";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// 首次重试的等待时间(秒)，之后每次翻倍
+    pub base_delay: i64,
+    /// 重试等待时间上限(秒)
+    pub max_delay: i64,
+    /// 任务最长保留时间(秒)，超过后不再重试
+    pub max_age: i64,
+    /// 同时处理的任务数
+    pub concurrency: usize,
+    /// 每个服务商应用每秒的请求数
+    pub rate: f64,
+    /// 每个服务商应用允许的突发请求数
+    pub burst: u32,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            base_delay: 30,
+            max_delay: 3600,
+            max_age: 86400,
+            concurrency: 4,
+            rate: 5.0,
+            burst: 5,
+        }
+    }
+}
+
+impl QueueConfig {
+    /// 第 attempts 次失败后的等待时间(秒)
+    pub fn backoff(&self, attempts: i64) -> i64 {
+        let exp = attempts.clamp(1, 32) - 1;
+        self.base_delay.saturating_mul(1 << exp).min(self.max_delay)
+    }
+}
+
+pub async fn get_queue_config(db: &SqlitePool) -> ItdResult<QueueConfig> {
+    let setting = SettingModel::new(db);
+    let config = match setting.find(QUEUE_KEY).await? {
+        Some(value) => serde_json::from_str(&value)?,
+        None => QueueConfig::default(),
+    };
+    Ok(config)
+}
+
+pub async fn set_queue_config(db: &SqlitePool, config: &QueueConfig) -> ItdResult<()> {
+    let setting = SettingModel::new(db);
+    let value = serde_json::to_string(config)?;
+    setting.set(QUEUE_KEY, &value, "同步队列配置").await?;
+    Ok(())
+}
+
+/// 把新IP发布到服务商和镜像目标
+/// 服务商返回成功后才写入本地IP，失败时记录错误和连续失败次数
+pub async fn sync_record(db: &SqlitePool, limiter: &Arc<RateLimiter>, item: &Record, ip_value: &str) -> ItdResult<()> {
+    let domain = format!("{}.{}", item.host, item.domain);
+    info!("Update record domain: {} ,ip: {}", domain, ip_value);
+    let provider = match load_provider(db, limiter, item.appid).await {
+        Ok(provider) => resolve_line(db, item.line.as_deref(), item.source.as_deref(), &item.ip_type)
+            .await
+            .map(|line| (provider, line)),
+        Err(e) => Err(e),
+    };
+    let result = match provider {
+        Ok((provider, line)) => {
+            provider
+                .modify_record(
+                    &item.host,
+                    &item.domain,
+                    &item.record_id,
+                    &item.ip_type,
+                    ip_value,
+                    item.ttl,
+                    Some(item.weight),
+                    line.as_deref(),
+                )
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    Records::new(db).set_sync_state(item.id as i64, ip_value, &result).await?;
+    notify::record_synced(db, item, ip_value, &result).await;
+    if result.is_ok() {
+        spawn_verify(db, item.id as i64, &item.host, &item.domain, &item.ip_type, ip_value);
+    }
+    // 镜像目标的失败记录在 record_target 中，不影响任务状态
+    Targets::new(db).sync_targets(item, ip_value, limiter).await?;
+    result
+}
+
+/// 处理一个任务，失败时按退避时间重新排队
+async fn process(db: &SqlitePool, config: &QueueConfig, limiter: &Arc<RateLimiter>, job: &SyncJob) -> ItdResult<()> {
+    let jobs = SyncJobs::new(db);
+    let now = Local::now().naive_local();
+    if (now - job.created_at).num_seconds() > config.max_age {
+        error!("Sync job {} expired after {} attempts", job.id, job.attempts);
+        let detail = job.last_error.clone().unwrap_or("expired".to_string());
+        if jobs.set_status(job.id, &job.value, JOB_FAILED, job.attempts, None, Some(detail.clone())).await? == 0 {
+            return Ok(());
+        }
+        if let Some(record) = Records::new(db).get_record(job.domain_id).await? {
+            let data = json!({ "job_id": job.id, "value": job.value, "attempts": job.attempts, "error": detail });
+            notify::emit_record(db, notify::EVENT_JOB_EXPIRED, &record, data).await?;
+        }
+        return Ok(());
+    }
+    let record = match Records::new(db).get_record(job.domain_id).await? {
+        Some(record) => record,
+        None => {
+            let detail = Some("Record not found".to_string());
+            jobs.set_status(job.id, &job.value, JOB_CANCELLED, job.attempts, None, detail).await?;
+            return Ok(());
+        }
+    };
+    // 排队期间记录或应用被停用
+    if let Some(reason) = record.skip_reason() {
+        info!("Skip sync job {} for {}.{}: {}", job.id, record.host, record.domain, reason);
+        Records::new(db).set_skipped(record.id as i64, &reason).await?;
+        jobs.set_status(job.id, &job.value, JOB_CANCELLED, job.attempts, None, Some(reason)).await?;
+        return Ok(());
+    }
+    let attempts = job.attempts + 1;
+    let updated = match sync_record(db, limiter, &record, &job.value).await {
+        Ok(_) => jobs.set_status(job.id, &job.value, JOB_DONE, attempts, None, None).await?,
+        Err(e) => {
+            let delay = config.backoff(attempts);
+            error!(
+                "Sync job {} for {}.{} failed, retry in {}s: {}",
+                job.id, record.host, record.domain, delay, e
+            );
+            let next_attempt_at = now + TimeDelta::seconds(delay);
+            let error = Some(e.to_string());
+            jobs.set_status(job.id, &job.value, JOB_PENDING, attempts, Some(next_attempt_at), error).await?
+        }
+    };
+    if updated == 0 {
+        info!("Sync job {} was replaced by a new value while syncing, keep it pending", job.id);
+    }
+    Ok(())
+}
+
+/// 并发处理所有到期的任务，返回失败的数量
+/// 同一应用的请求经过令牌桶限速，不同应用互不影响
+pub async fn drain(db: &SqlitePool, limiter: &Arc<RateLimiter>) -> ItdResult<usize> {
+    let config = Arc::new(get_queue_config(db).await?);
+    limiter.configure(config.rate, config.burst).await;
+    let semaphore = Arc::new(Semaphore::new(config.concurrency.max(1)));
+    let mut set = JoinSet::new();
+    for job in SyncJobs::new(db).due().await? {
+        let (db, config, limiter) = (db.clone(), config.clone(), limiter.clone());
+        let permit = semaphore.clone().acquire_owned().await;
+        set.spawn(async move {
+            let _permit = permit;
+            let result = process(&db, &config, &limiter, &job).await;
+            if let Err(e) = &result {
+                error!("Sync job {} failed: {}", job.id, e);
+            }
+            result
+        });
+    }
+    let mut failed = 0;
+    while let Some(result) = set.join_next().await {
+        if !matches!(result, Ok(Ok(_))) {
+            failed += 1;
+        }
+    }
+    Ok(failed)
+}
+
+/// 后台循环和手动触发共用 AppState 中的锁，同一时间只运行一个 drain，避免同一任务被处理两次
+pub async fn drain_exclusive(state: &AppState) -> ItdResult<usize> {
+    let _draining = state.draining.lock().await;
+    drain(&state.db, &state.limiter).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::{set_verify_config, VerifyConfig};
+    use axum::{routing::put, Router};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn it_backoff_works() {
+        let config = QueueConfig::default();
+        assert_eq!(config.backoff(1), 30);
+        assert_eq!(config.backoff(2), 60);
+        assert_eq!(config.backoff(5), 480);
+        assert_eq!(config.backoff(10), 3600);
+        assert_eq!(config.backoff(100), 3600);
+    }
+
+    #[tokio::test]
+    async fn it_queue_retries_until_synced() -> ItdResult<()> {
+        let online = Arc::new(AtomicBool::new(false));
+        let state = online.clone();
+        let app = Router::new().route(
+            "/update/:id",
+            put(move || async move {
+                if state.load(Ordering::SeqCst) {
+                    (http::StatusCode::OK, "ok")
+                } else {
+                    (http::StatusCode::BAD_GATEWAY, "unreachable")
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        set_verify_config(&db, &VerifyConfig { enabled: false, ..Default::default() }).await?;
+        let config = QueueConfig { base_delay: 0, ..Default::default() };
+        set_queue_config(&db, &config).await?;
+        let appid = crate::tests::insert_custom_app(&db, &format!("http://{}/update/{{record_id}}", addr)).await?;
+        sqlx::query(
+            r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id)
+               VALUES (1,?,'www','example.com','A','198.51.100.1',1)"#,
+        )
+        .bind(appid)
+        .execute(&db)
+        .await?;
+        let jobs = SyncJobs::new(&db);
+        let id = jobs.enqueue(1, "198.51.100.2").await?;
+        // 新值替换待处理任务
+        assert_eq!(jobs.enqueue(1, "198.51.100.3").await?, id);
+
+        drain(&db, &Arc::default()).await?;
+        let job = jobs.get(id).await?.unwrap();
+        assert_eq!(job.status, JOB_PENDING);
+        assert_eq!(job.attempts, 1);
+        assert!(job.last_error.is_some());
+        assert_eq!(Records::new(&db).get_record(1).await?.unwrap().ip, "198.51.100.1");
+
+        online.store(true, Ordering::SeqCst);
+        drain(&db, &Arc::default()).await?;
+        let job = jobs.get(id).await?.unwrap();
+        assert_eq!(job.status, JOB_DONE);
+        assert_eq!(job.attempts, 2);
+        assert_eq!(Records::new(&db).get_record(1).await?.unwrap().ip, "198.51.100.3");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_value_replaced_while_syncing_stays_pending() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        // 服务商处理请求期间检测到新的IP
+        let replaced = db.clone();
+        let app = Router::new().route(
+            "/update/:id",
+            put(move || async move {
+                SyncJobs::new(&replaced).enqueue(1, "198.51.100.3").await.unwrap();
+                "ok"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        set_verify_config(&db, &VerifyConfig { enabled: false, ..Default::default() }).await?;
+        let appid = crate::tests::insert_custom_app(&db, &format!("http://{}/update/{{record_id}}", addr)).await?;
+        sqlx::query(
+            r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id)
+               VALUES (1,?,'www','example.com','A','198.51.100.1',1)"#,
+        )
+        .bind(appid)
+        .execute(&db)
+        .await?;
+        let jobs = SyncJobs::new(&db);
+        let id = jobs.enqueue(1, "198.51.100.2").await?;
+        drain(&db, &Arc::default()).await?;
+        let job = jobs.get(id).await?.unwrap();
+        assert_eq!(job.status, JOB_PENDING);
+        assert_eq!(job.value, "198.51.100.3");
+        assert_eq!(job.attempts, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_queue_expires_and_cancels() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        set_queue_config(&db, &QueueConfig { max_age: -1, ..Default::default() }).await?;
+        let jobs = SyncJobs::new(&db);
+        let id = jobs.enqueue(1, "198.51.100.2").await?;
+        drain(&db, &Arc::default()).await?;
+        assert_eq!(jobs.get(id).await?.unwrap().status, JOB_FAILED);
+        assert_eq!(jobs.retry(id).await?, 1);
+        assert_eq!(jobs.cancel(id).await?, 1);
+        assert_eq!(jobs.get(id).await?.unwrap().status, JOB_CANCELLED);
+        // 已取消的任务不能再取消
+        assert_eq!(jobs.cancel(id).await?, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_drain_exclusive_processes_once() -> ItdResult<()> {
+        use std::sync::atomic::AtomicUsize;
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let app = Router::new().route(
+            "/update/:id",
+            put(move || async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                "ok"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        set_verify_config(&db, &VerifyConfig { enabled: false, ..Default::default() }).await?;
+        let appid = crate::tests::insert_custom_app(&db, &format!("http://{}/update/{{record_id}}", addr)).await?;
+        sqlx::query(
+            r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id)
+               VALUES (1,?,'www','example.com','A','198.51.100.1',1)"#,
+        )
+        .bind(appid)
+        .execute(&db)
+        .await?;
+        let id = SyncJobs::new(&db).enqueue(1, "198.51.100.2").await?;
+        // 后台循环和手动触发同时处理队列
+        let state = crate::tests::app_state(db.clone());
+        let (first, second) = tokio::join!(drain_exclusive(&state), drain_exclusive(&state));
+        assert_eq!(first? + second?, 0);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(SyncJobs::new(&db).get(id).await?.unwrap().status, JOB_DONE);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_drain_runs_concurrently() -> ItdResult<()> {
+        let app = Router::new().route(
+            "/update/:id",
+            put(|| async {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                "ok"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        set_verify_config(&db, &VerifyConfig { enabled: false, ..Default::default() }).await?;
+        let jobs = SyncJobs::new(&db);
+        for id in 1..=4 {
+            let appid = crate::tests::insert_custom_app(&db, &format!("http://{}/update/{{record_id}}", addr)).await?;
+            sqlx::query(
+                r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id)
+                   VALUES (?,?,?,'example.com','A','198.51.100.1',?)"#,
+            )
+            .bind(id)
+            .bind(appid)
+            .bind(format!("h{}", id))
+            .bind(id)
+            .execute(&db)
+            .await?;
+            jobs.enqueue(id, "198.51.100.2").await?;
+        }
+        let started = Instant::now();
+        assert_eq!(drain(&db, &Arc::default()).await?, 0);
+        // 4个任务并发，总耗时远小于串行的1200ms
+        assert!(started.elapsed() < Duration::from_millis(1000));
+        assert_eq!(jobs.list(Some(JOB_DONE.to_string()), 1).await?.count, 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_sync_uses_record_attributes() -> ItdResult<()> {
+        use crate::model::ip_state::{IpStateModel, DEFAULT_SOURCE};
+        use crate::IpState;
+        use axum::extract::{Path, Query};
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        let calls = Arc::new(Mutex::new(vec![]));
+        let seen = calls.clone();
+        let app = Router::new().route(
+            "/update/:id/:ttl/:weight",
+            put(
+                move |Path((id, ttl, weight)): Path<(String, String, String)>,
+                      Query(query): Query<HashMap<String, String>>| async move {
+                    seen.lock().unwrap().push((id, ttl, weight, query["line"].clone()));
+                    "ok"
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        set_verify_config(&db, &VerifyConfig { enabled: false, ..Default::default() }).await?;
+        let url = format!("http://{}/update/{{record_id}}/{{ttl}}/{{weight}}?line={{line}}", addr);
+        let appid = crate::tests::insert_custom_app(&db, &url).await?;
+        let disabled_app = crate::tests::insert_custom_app(&db, &url).await?;
+        sqlx::query(r#"UPDATE user_apps SET status=0 WHERE id=?"#)
+            .bind(disabled_app)
+            .execute(&db)
+            .await?;
+        sqlx::query(
+            r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id,ttl,weight,status,line)
+               VALUES (1,?,'a','example.com','A','198.51.100.1',7,120,20,1,'auto'),
+                      (2,?,'b','example.com','A','198.51.100.1',8,600,1,1,NULL),
+                      (3,?,'c','example.com','A','198.51.100.1',9,600,1,0,NULL)"#,
+        )
+        .bind(appid)
+        .bind(disabled_app)
+        .bind(appid)
+        .execute(&db)
+        .await?;
+        // 检测到的出口为联通，auto 线路发布到联通线路
+        let state = IpState {
+            ipv4: Some("198.51.100.2".to_string()),
+            ipv4_asn: Some(4837),
+            ..Default::default()
+        };
+        IpStateModel::new(&db).save(DEFAULT_SOURCE, &state).await?;
+        let jobs = SyncJobs::new(&db);
+        let enabled = jobs.enqueue(1, "198.51.100.2").await?;
+        let app_disabled = jobs.enqueue(2, "198.51.100.2").await?;
+        let record_disabled = jobs.enqueue(3, "198.51.100.2").await?;
+        drain(&db, &Arc::default()).await?;
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![("7".to_string(), "120".to_string(), "20".to_string(), "联通".to_string())]
+        );
+        assert_eq!(jobs.get(enabled).await?.unwrap().status, JOB_DONE);
+        assert_eq!(jobs.get(app_disabled).await?.unwrap().status, JOB_CANCELLED);
+        assert_eq!(jobs.get(record_disabled).await?.unwrap().status, JOB_CANCELLED);
+        let record_model = Records::new(&db);
+        assert!(record_model.get_record(2).await?.unwrap().skip_reason.is_some());
+        assert_eq!(record_model.get_record(3).await?.unwrap().skip_reason, Some("记录已停用".to_string()));
+        assert_eq!(record_model.get_record(3).await?.unwrap().ip, "198.51.100.1");
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+//! 手动触发的检测和同步
+//! 接口立即返回运行id，检测和同步在后台执行，进度和结果保存在内存中供查询
+use crate::error::ItdResult;
+use crate::ipaddr::watch::{resync, task_exclusive};
+use crate::model::sync_job::{SyncJobs, JOB_DONE};
+use crate::queue::drain_exclusive;
+use crate::AppState;
+use chrono::{Local, NaiveDateTime};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+/// 忽略检测频率重新检测IP，变化的记录立即同步
+pub const RUN_REFRESH: &str = "refresh";
+/// 检测后把记录重新推送到服务商，不管IP是否变化
+pub const RUN_SYNC: &str = "sync";
+
+pub const RUN_PENDING: &str = "pending";
+pub const RUN_RUNNING: &str = "running";
+pub const RUN_DONE: &str = "done";
+pub const RUN_FAILED: &str = "failed";
+
+/// 最多保留的运行记录
+const MAX_RUNS: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncRun {
+    pub id: u64,
+    /// refresh | sync
+    pub kind: String,
+    /// 只同步一条记录时的记录id
+    pub record_id: Option<i64>,
+    /// pending | running | done | failed
+    pub status: String,
+    /// 加入同步队列的任务id
+    pub jobs: Vec<i64>,
+    pub message: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SyncRuns {
+    next_id: Arc<AtomicU64>,
+    runs: Arc<RwLock<VecDeque<SyncRun>>>,
+}
+
+impl SyncRuns {
+    async fn create(&self, kind: &str, record_id: Option<i64>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut runs = self.runs.write().await;
+        if runs.len() >= MAX_RUNS {
+            runs.pop_front();
+        }
+        runs.push_back(SyncRun {
+            id,
+            kind: kind.to_string(),
+            record_id,
+            status: RUN_PENDING.to_string(),
+            jobs: vec![],
+            message: None,
+            created_at: Local::now().naive_local(),
+            finished_at: None,
+        });
+        id
+    }
+    pub async fn get(&self, id: u64) -> Option<SyncRun> {
+        self.runs.read().await.iter().find(|run| run.id == id).cloned()
+    }
+    async fn update(&self, id: u64, status: &str, jobs: Vec<i64>, message: Option<String>) {
+        let mut runs = self.runs.write().await;
+        if let Some(run) = runs.iter_mut().find(|run| run.id == id) {
+            run.status = status.to_string();
+            run.jobs = jobs;
+            run.message = message;
+            if status == RUN_DONE || status == RUN_FAILED {
+                run.finished_at = Some(Local::now().naive_local());
+            }
+        }
+    }
+}
+
+/// 执行检测和同步，返回加入队列的任务id和结果说明
+async fn execute(state: &AppState, kind: &str, record_id: Option<i64>) -> ItdResult<(Vec<i64>, bool, String)> {
+    let detected = task_exclusive(state, true).await;
+    let mut message = vec![];
+    if let Err(e) = &detected {
+        message.push(e.to_string());
+    }
+    let jobs = if kind == RUN_SYNC {
+        resync(&state.db, &state.ip_state, &state.source_states, record_id).await?
+    } else {
+        vec![]
+    };
+    drain_exclusive(state).await?;
+    let job_model = SyncJobs::new(&state.db);
+    let mut done = 0;
+    for id in jobs.iter() {
+        if let Some(job) = job_model.get(*id).await? {
+            if job.status == JOB_DONE {
+                done += 1;
+            }
+        }
+    }
+    if kind == RUN_SYNC {
+        message.push(format!("{} 条记录已同步，{} 条未完成", done, jobs.len() - done));
+    }
+    let ok = detected.is_ok() && done == jobs.len();
+    Ok((jobs, ok, message.join("; ")))
+}
+
+/// 创建运行记录并在后台执行，返回运行id
+pub async fn spawn_run(state: Arc<AppState>, kind: &str, record_id: Option<i64>) -> u64 {
+    let id = state.runs.create(kind, record_id).await;
+    let kind = kind.to_string();
+    tokio::spawn(async move {
+        state.runs.update(id, RUN_RUNNING, vec![], None).await;
+        info!("Manual {} run {} started", kind, id);
+        match execute(&state, &kind, record_id).await {
+            Ok((jobs, ok, message)) => {
+                let status = if ok { RUN_DONE } else { RUN_FAILED };
+                state.runs.update(id, status, jobs, Some(message)).await;
+            }
+            Err(e) => {
+                error!("Manual {} run {} failed: {}", kind, id, e);
+                state.runs.update(id, RUN_FAILED, vec![], Some(e.to_string())).await;
+            }
+        }
+    });
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_sync_runs_works() {
+        let runs = SyncRuns::default();
+        let first = runs.create(RUN_SYNC, Some(3)).await;
+        let second = runs.create(RUN_REFRESH, None).await;
+        assert_ne!(first, second);
+        runs.update(first, RUN_DONE, vec![7], Some("ok".to_string())).await;
+        let run = runs.get(first).await.unwrap();
+        assert_eq!(run.status, RUN_DONE);
+        assert_eq!(run.jobs, vec![7]);
+        assert!(run.finished_at.is_some());
+        assert_eq!(runs.get(second).await.unwrap().status, RUN_PENDING);
+        for _ in 0..MAX_RUNS {
+            runs.create(RUN_REFRESH, None).await;
+        }
+        assert!(runs.get(first).await.is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_spawn_run_syncs_unchanged_records() -> ItdResult<()> {
+        use crate::ipaddr::source::{set_source_config, SourceConfig};
+        use crate::model::records::Records;
+        use crate::verify::{set_verify_config, VerifyConfig};
+        use crate::IpState;
+        use axum::{routing::put, Router};
+        use std::time::Duration;
+        let app = Router::new().route("/update/:id", put(|| async { "ok" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let db = crate::tests::memory_db().await;
+        crate::tests::local_commands();
+        let config: SourceConfig =
+            serde_json::from_str(r#"{"kind":"command","name":"echo40","frequency":3600}"#)?;
+        set_source_config(&db, &config).await?;
+        set_verify_config(&db, &VerifyConfig { enabled: false, ..Default::default() }).await?;
+        let appid = crate::tests::insert_custom_app(&db, &format!("http://{}/update/{{record_id}}", addr)).await?;
+        sqlx::query(
+            r#"INSERT INTO user_domain (id,appid,host,domain,ip_type,ip,record_id,allow_reserved)
+               VALUES (1,?,'www','example.com','A','198.51.100.1',1,1)"#,
+        )
+        .bind(appid)
+        .execute(&db)
+        .await?;
+        // 已检测过且在检测频率内，IP不会被当作变化
+        let ip_state = IpState {
+            ipv4: Some("198.51.100.40".to_string()),
+            ipv4_updated_at: Local::now().timestamp(),
+            ..Default::default()
+        };
+        let state = crate::tests::app_state(db.clone());
+        *state.ip_state.write().await = ip_state;
+        let id = spawn_run(state.clone(), RUN_SYNC, Some(1)).await;
+        let mut run = state.runs.get(id).await.unwrap();
+        for _ in 0..50 {
+            if run.finished_at.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            run = state.runs.get(id).await.unwrap();
+        }
+        assert_eq!(run.status, RUN_DONE, "{:?}", run.message);
+        assert_eq!(run.jobs.len(), 1);
+        assert_eq!(Records::new(&db).get_record(1).await?.unwrap().ip, "198.51.100.40");
+        Ok(())
+    }
+}
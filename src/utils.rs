@@ -16,6 +16,8 @@ use tracing_subscriber::fmt::writer::MakeWriterExt;
 
 //use tracing_subscriber::{fmt, layer::SubscriberExt};
 use crate::error::{ItdError, ItdResult};
+use crate::ipaddr::source::source_state;
+use crate::ipaddr::watch::detect_named;
 use crate::model::constants::{AES_KEY, JWT_SECRET};
 use crate::{err, AppState};
 
@@ -162,9 +164,27 @@ pub fn log_setup() {
         .with_writer(logfile.and(stdout))
         .init();
 }
-/// extract ip from app_state
-pub async fn extract_ip(ip_type: &str, app_state: Arc<AppState>) -> ItdResult<String> {
-    let ip_state = app_state.ip_state.read().await;
+/// extract ip from app_state, source 为IP来源名称，为空时使用全局来源
+/// 命名来源还没有检测到对应类型的IP时立即检测一次
+pub async fn extract_ip(ip_type: &str, source: Option<&str>, app_state: Arc<AppState>) -> ItdResult<String> {
+    let ip_state = match source {
+        Some(source) => {
+            let ip_state = app_state.source_states.read().await.get(source).cloned();
+            let detected = match ip_state {
+                Some(ip_state) => match ip_type {
+                    "AAAA" => ip_state.read().await.ipv6.is_some(),
+                    _ => ip_state.read().await.ipv4.is_some(),
+                },
+                None => false,
+            };
+            if !detected {
+                detect_named(&app_state.db, &app_state.source_states, source).await?;
+            }
+            source_state(&app_state.source_states, source).await
+        }
+        None => app_state.ip_state.clone(),
+    };
+    let ip_state = ip_state.read().await;
     match ip_type {
         "A" => ip_state.ipv4.clone()
             .ok_or_else(|| ItdError::new("extract_ip".to_string(), "No ipv4 address found".to_string())),
@@ -207,4 +227,21 @@ mod tests {
         let result = STANDARD.encode(key);
         println!("{}", result);
     }
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_extract_ip_detects_named_source_works() -> ItdResult<()> {
+        use crate::model::ip_source::{IpSourceForm, IpSourceModel};
+        let db = crate::tests::memory_db().await;
+        crate::tests::local_commands();
+        let config = serde_json::from_str(r#"{"kind":"command","name":"echo60"}"#)?;
+        IpSourceModel::new(&db)
+            .add(IpSourceForm { name: "wan2".to_string(), config })
+            .await?;
+        let state = crate::tests::app_state(db);
+        assert_eq!(extract_ip("A", Some("wan2"), state.clone()).await?, "198.51.100.60");
+        assert!(state.source_states.read().await.contains_key("wan2"));
+        assert!(extract_ip("A", Some("wan3"), state.clone()).await.is_err());
+        assert!(!state.source_states.read().await.contains_key("wan3"));
+        Ok(())
+    }
 }
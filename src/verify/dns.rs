@@ -0,0 +1,262 @@
+//! 最小的 DNS over UDP 客户端(RFC 1035)，仅用于发布校验
+use crate::err;
+use crate::error::ItdResult;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u16 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ns(String),
+    Cname(String),
+    Other(u16),
+}
+
+/// 记录类型名称转换为类型值
+pub fn record_type(name: &str) -> ItdResult<u16> {
+    match name {
+        "A" => Ok(TYPE_A),
+        "AAAA" => Ok(TYPE_AAAA),
+        "NS" => Ok(TYPE_NS),
+        "CNAME" => Ok(TYPE_CNAME),
+        _ => err!(format!("不支持的记录类型: {}", name)),
+    }
+}
+
+/// 构造查询报文，recursion 为 false 时用于查询权威服务器
+pub fn build_query(id: u16, name: &str, qtype: u16, recursion: bool) -> ItdResult<Vec<u8>> {
+    let mut query = Vec::with_capacity(64);
+    query.extend_from_slice(&id.to_be_bytes());
+    let flags: u16 = if recursion { 0x0100 } else { 0 };
+    query.extend_from_slice(&flags.to_be_bytes());
+    // qdcount=1 ancount=0 nscount=0 arcount=0
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return err!(format!("域名格式错误: {}", name));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> ItdResult<u16> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => err!("DNS 响应长度错误"),
+    }
+}
+
+/// 读取域名，支持压缩指针，返回 (域名, 域名之后的偏移)
+pub fn read_name(data: &[u8], offset: usize) -> ItdResult<(String, usize)> {
+    let mut labels = vec![];
+    let mut pos = offset;
+    let mut end = None;
+    // 防止指针循环
+    for _ in 0..128 {
+        let len = match data.get(pos) {
+            Some(len) => *len as usize,
+            None => return err!("DNS 域名长度错误"),
+        };
+        if len == 0 {
+            let end = end.unwrap_or(pos + 1);
+            return Ok((labels.join("."), end));
+        }
+        if len & 0xC0 == 0xC0 {
+            let pointer = (read_u16(data, pos)? & 0x3FFF) as usize;
+            if end.is_none() {
+                end = Some(pos + 2);
+            }
+            pos = pointer;
+            continue;
+        }
+        match data.get(pos + 1..pos + 1 + len) {
+            Some(label) => labels.push(String::from_utf8_lossy(label).to_string()),
+            None => return err!("DNS 域名长度错误"),
+        }
+        pos += 1 + len;
+    }
+    err!("DNS 域名压缩指针循环")
+}
+
+/// 解析响应报文中的回答部分
+pub fn parse_response(data: &[u8], id: u16) -> ItdResult<Vec<RecordData>> {
+    if data.len() < 12 || read_u16(data, 0)? != id {
+        return err!("DNS 响应的 id 不匹配");
+    }
+    let flags = read_u16(data, 2)?;
+    if flags & 0x8000 == 0 {
+        return err!("不是 DNS 响应");
+    }
+    let rcode = flags & 0x000F;
+    if rcode == RCODE_NXDOMAIN {
+        return Ok(vec![]);
+    }
+    if rcode != 0 {
+        return err!(format!("DNS 返回错误码: {}", rcode));
+    }
+    let qdcount = read_u16(data, 4)?;
+    let ancount = read_u16(data, 6)?;
+    let mut offset = 12;
+    for _ in 0..qdcount {
+        offset = read_name(data, offset)?.1 + 4;
+    }
+    let mut answers = vec![];
+    for _ in 0..ancount {
+        offset = read_name(data, offset)?.1;
+        let rtype = read_u16(data, offset)?;
+        let rdlen = read_u16(data, offset + 8)? as usize;
+        let start = offset + 10;
+        let rdata = match data.get(start..start + rdlen) {
+            Some(rdata) => rdata,
+            None => return err!("DNS 记录长度错误"),
+        };
+        let answer = match (rtype, rdlen) {
+            (TYPE_A, 4) => RecordData::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(rdata);
+                RecordData::Aaaa(Ipv6Addr::from(octets))
+            }
+            (TYPE_NS, _) => RecordData::Ns(read_name(data, start)?.0),
+            (TYPE_CNAME, _) => RecordData::Cname(read_name(data, start)?.0),
+            _ => RecordData::Other(rtype),
+        };
+        answers.push(answer);
+        offset = start + rdlen;
+    }
+    Ok(answers)
+}
+
+/// 向指定服务器查询
+pub async fn query(
+    server: SocketAddr,
+    name: &str,
+    qtype: u16,
+    recursion: bool,
+    wait: Duration,
+) -> ItdResult<Vec<RecordData>> {
+    let mut id = [0u8; 2];
+    getrandom::getrandom(&mut id)?;
+    let id = u16::from_be_bytes(id);
+    let request = build_query(id, name, qtype, recursion)?;
+    let local: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    let mut buf = [0u8; 4096];
+    // UDP 可能丢包，超时前重发一次
+    for _ in 0..2 {
+        socket.send(&request).await?;
+        match timeout(wait / 2, socket.recv(&mut buf)).await {
+            Ok(Ok(len)) => return parse_response(&buf[..len], id),
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => continue,
+        }
+    }
+    err!(format!("DNS 服务器 {} 响应超时", server))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// 本地 DNS 服务，按 (域名, 类型) 返回固定回答
+    pub async fn dns_server(records: Vec<(String, RecordData)>) -> ItdResult<SocketAddr> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let addr = socket.local_addr()?;
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+                let query = &buf[..len];
+                let (name, end) = read_name(query, 12).unwrap();
+                let qtype = read_u16(query, end).unwrap();
+                let answers: Vec<&RecordData> = records
+                    .iter()
+                    .filter(|(host, data)| {
+                        *host == name
+                            && match data {
+                                RecordData::A(_) => qtype == TYPE_A,
+                                RecordData::Aaaa(_) => qtype == TYPE_AAAA,
+                                RecordData::Ns(_) => qtype == TYPE_NS,
+                                _ => false,
+                            }
+                    })
+                    .map(|(_, data)| data)
+                    .collect();
+                let mut response = query[..2].to_vec();
+                let rcode = if answers.is_empty() { RCODE_NXDOMAIN } else { 0 };
+                response.extend_from_slice(&(0x8400 | rcode).to_be_bytes());
+                response.extend_from_slice(&[0, 1]);
+                response.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+                response.extend_from_slice(&[0, 0, 0, 0]);
+                response.extend_from_slice(&query[12..end + 4]);
+                for data in answers {
+                    let (rtype, rdata) = match data {
+                        RecordData::A(ip) => (TYPE_A, ip.octets().to_vec()),
+                        RecordData::Aaaa(ip) => (TYPE_AAAA, ip.octets().to_vec()),
+                        RecordData::Ns(ns) => (TYPE_NS, build_query(0, ns, 0, false).unwrap()[12..].to_vec()),
+                        _ => continue,
+                    };
+                    // NS 的 rdata 取自构造的查询报文，去掉末尾的 qtype/qclass
+                    let rdata = if rtype == TYPE_NS { rdata[..rdata.len() - 4].to_vec() } else { rdata };
+                    response.extend_from_slice(&[0xC0, 0x0C]);
+                    response.extend_from_slice(&rtype.to_be_bytes());
+                    response.extend_from_slice(&CLASS_IN.to_be_bytes());
+                    response.extend_from_slice(&600u32.to_be_bytes());
+                    response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+                    response.extend_from_slice(&rdata);
+                }
+                let _ = socket.send_to(&response, from).await;
+            }
+        });
+        Ok(addr)
+    }
+
+    #[test]
+    fn it_read_name_works() -> ItdResult<()> {
+        // 12 字节头 + example.com + 指向它的压缩指针
+        let mut data = vec![0u8; 12];
+        data.extend_from_slice(b"\x07example\x03com\x00");
+        data.extend_from_slice(b"\x03ns1\xC0\x0C");
+        assert_eq!(read_name(&data, 12)?, ("example.com".to_string(), 25));
+        assert_eq!(read_name(&data, 25)?, ("ns1.example.com".to_string(), 31));
+        // 指向自身的指针
+        let data = vec![0xC0, 0x00];
+        assert!(read_name(&data, 0).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_query_works() -> ItdResult<()> {
+        let server = dns_server(vec![
+            ("itd.example.com".to_string(), RecordData::A("203.0.113.5".parse()?)),
+            ("example.com".to_string(), RecordData::Ns("ns1.example.net".to_string())),
+        ])
+        .await?;
+        let wait = Duration::from_secs(2);
+        let answers = query(server, "itd.example.com", TYPE_A, false, wait).await?;
+        assert_eq!(answers, vec![RecordData::A("203.0.113.5".parse()?)]);
+        let answers = query(server, "example.com", TYPE_NS, true, wait).await?;
+        assert_eq!(answers, vec![RecordData::Ns("ns1.example.net".to_string())]);
+        assert!(query(server, "none.example.com", TYPE_A, false, wait).await?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+//! 发布校验
+//! 服务商接口返回成功后，向域名的权威服务器(以及可选的公共解析器)查询记录，
+//! 与期望值比较，记录传播状态和生效耗时
+//! 非默认线路的记录只有对应运营商的解析器能查到，不做校验
+use crate::error::ItdResult;
+use crate::ipaddr::isp::{resolve_line, LINE_DEFAULT};
+use crate::model::records::Records;
+use crate::model::setting::SettingModel;
+use crate::notify::{self, EVENT_DRIFT_DETECTED};
+use crate::err;
+use dns::{query, record_type, RecordData, TYPE_NS};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::lookup_host;
+use tracing::{error, info};
+
+pub mod dns;
+
+pub const VERIFY_KEY: &str = "publish_verify";
+
+pub const VERIFY_PENDING: &str = "pending";
+pub const VERIFY_CONSISTENT: &str = "consistent";
+pub const VERIFY_INCONSISTENT: &str = "inconsistent";
+pub const VERIFY_FAILED: &str = "failed";
+pub const VERIFY_SKIPPED: &str = "skipped";
+/// 校验期间记录发布了新的值，结果不再写入
+pub const VERIFY_SUPERSEDED: &str = "superseded";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VerifyConfig {
+    pub enabled: bool,
+    /// 用于查询 NS 记录的递归解析器
+    pub resolvers: Vec<String>,
+    /// 指定权威服务器，为空时按 NS 记录查找
+    pub nameservers: Vec<String>,
+    /// 额外检查的公共解析器，为空时不检查
+    pub public_resolvers: Vec<String>,
+    /// 两次检查的间隔(秒)
+    pub interval: u64,
+    /// 最多检查次数
+    pub attempts: u32,
+    /// 单次查询超时(秒)
+    pub timeout: u64,
+}
+
+impl Default for VerifyConfig {
+    fn default() -> Self {
+        VerifyConfig {
+            enabled: true,
+            resolvers: vec!["223.5.5.5:53".to_string(), "119.29.29.29:53".to_string()],
+            nameservers: vec![],
+            public_resolvers: vec![],
+            interval: 10,
+            attempts: 30,
+            timeout: 3,
+        }
+    }
+}
+
+pub async fn get_verify_config(db: &SqlitePool) -> ItdResult<VerifyConfig> {
+    let setting = SettingModel::new(db);
+    let config = match setting.find(VERIFY_KEY).await? {
+        Some(value) => serde_json::from_str(&value)?,
+        None => VerifyConfig::default(),
+    };
+    Ok(config)
+}
+
+pub async fn set_verify_config(db: &SqlitePool, config: &VerifyConfig) -> ItdResult<()> {
+    let setting = SettingModel::new(db);
+    let value = serde_json::to_string(config)?;
+    setting.set(VERIFY_KEY, &value, "发布校验配置").await?;
+    Ok(())
+}
+
+/// 单个服务器的查询结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerAnswer {
+    pub server: String,
+    pub answers: Vec<String>,
+    pub matched: bool,
+    pub error: Option<String>,
+}
+
+async fn resolve_addrs(servers: &[String]) -> Vec<SocketAddr> {
+    let mut addrs = vec![];
+    for server in servers {
+        match lookup_host(server.as_str()).await {
+            Ok(found) => addrs.extend(found),
+            Err(e) => error!("Resolve {} failed: {}", server, e),
+        }
+    }
+    addrs
+}
+
+impl VerifyConfig {
+    fn wait(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+    /// 查找域名的权威服务器地址
+    pub async fn authoritative(&self, zone: &str) -> ItdResult<Vec<SocketAddr>> {
+        if !self.nameservers.is_empty() {
+            return Ok(resolve_addrs(&self.nameservers).await);
+        }
+        for resolver in resolve_addrs(&self.resolvers).await {
+            let answers = match query(resolver, zone, TYPE_NS, true, self.wait()).await {
+                Ok(answers) => answers,
+                Err(e) => {
+                    info!("Query NS of {} from {} failed: {}", zone, resolver, e);
+                    continue;
+                }
+            };
+            let names: Vec<String> = answers
+                .into_iter()
+                .filter_map(|answer| match answer {
+                    RecordData::Ns(name) => Some(format!("{}:53", name)),
+                    _ => None,
+                })
+                .collect();
+            if !names.is_empty() {
+                return Ok(resolve_addrs(&names).await);
+            }
+        }
+        err!(format!("未找到 {} 的权威服务器", zone))
+    }
+    /// 向各服务器查询一次，返回每个服务器的结果
+    pub async fn check(
+        &self,
+        servers: &[(SocketAddr, bool)],
+        fqdn: &str,
+        rtype: &str,
+        value: &str,
+    ) -> ItdResult<Vec<ServerAnswer>> {
+        let qtype = record_type(rtype)?;
+        let expected: Option<IpAddr> = value.parse().ok();
+        let mut results = vec![];
+        for (server, recursion) in servers {
+            let result = query(*server, fqdn, qtype, *recursion, self.wait()).await;
+            let answer = match result {
+                Ok(answers) => {
+                    let answers: Vec<String> = answers
+                        .into_iter()
+                        .filter_map(|answer| match answer {
+                            RecordData::A(ip) => Some(ip.to_string()),
+                            RecordData::Aaaa(ip) => Some(ip.to_string()),
+                            RecordData::Ns(name) | RecordData::Cname(name) => Some(name),
+                            RecordData::Other(_) => None,
+                        })
+                        .collect();
+                    // IP 按地址比较，避免IPv6写法不同
+                    let matched = answers.iter().any(|answer| match expected {
+                        Some(expected) => answer.parse::<IpAddr>().ok() == Some(expected),
+                        None => answer.trim_end_matches('.') == value.trim_end_matches('.'),
+                    });
+                    ServerAnswer { server: server.to_string(), answers, matched, error: None }
+                }
+                Err(e) => ServerAnswer {
+                    server: server.to_string(),
+                    answers: vec![],
+                    matched: false,
+                    error: Some(e.to_string()),
+                },
+            };
+            results.push(answer);
+        }
+        Ok(results)
+    }
+}
+
+/// 校验结果
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyResult {
+    pub status: String,
+    /// 从开始校验到所有服务器一致的耗时
+    pub propagation_ms: Option<i64>,
+    pub answers: Vec<ServerAnswer>,
+}
+
+/// 两个记录值是否相同，IP 按地址比较
+fn same_value(a: &str, b: &str) -> bool {
+    match (a.parse::<IpAddr>(), b.parse::<IpAddr>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// 记录已被删除或发布了其它值时，这次校验已被新的校验取代
+async fn superseded(record_model: &Records<'_>, id: i64, value: &str) -> ItdResult<bool> {
+    match record_model.get_record(id).await? {
+        Some(record) => Ok(!same_value(&record.ip, value)),
+        None => Ok(true),
+    }
+}
+
+/// 反复检查直到所有服务器一致或达到最大次数，并写入记录
+pub async fn verify_record(
+    db: &SqlitePool,
+    config: &VerifyConfig,
+    id: i64,
+    host: &str,
+    zone: &str,
+    rtype: &str,
+    value: &str,
+) -> ItdResult<VerifyResult> {
+    let record_model = Records::new(db);
+    let started = Instant::now();
+    let fqdn = if host == "@" { zone.to_string() } else { format!("{}.{}", host, zone) };
+    let record = match record_model.get_record(id).await? {
+        Some(record) => record,
+        None => return err!(format!("记录不存在: {}", id)),
+    };
+    if !same_value(&record.ip, value) {
+        return Ok(VerifyResult {
+            status: VERIFY_SUPERSEDED.to_string(),
+            propagation_ms: None,
+            answers: vec![],
+        });
+    }
+    let line = resolve_line(db, record.line.as_deref(), record.source.as_deref(), rtype).await?;
+    if let Some(line) = line.filter(|line| line != LINE_DEFAULT) {
+        let detail = format!("线路 {} 的记录只有对应运营商的解析器能查到，跳过校验", line);
+        record_model.set_verify(id, VERIFY_SKIPPED, Some(detail), None).await?;
+        return Ok(VerifyResult {
+            status: VERIFY_SKIPPED.to_string(),
+            propagation_ms: None,
+            answers: vec![],
+        });
+    }
+    record_model.set_verify(id, VERIFY_PENDING, None, None).await?;
+    let mut servers: Vec<(SocketAddr, bool)> = match config.authoritative(zone).await {
+        Ok(servers) => servers.into_iter().map(|server| (server, false)).collect(),
+        Err(e) => {
+            record_model.set_verify(id, VERIFY_FAILED, Some(e.to_string()), None).await?;
+            return Err(e);
+        }
+    };
+    servers.extend(
+        resolve_addrs(&config.public_resolvers)
+            .await
+            .into_iter()
+            .map(|server| (server, true)),
+    );
+    if servers.is_empty() {
+        let detail = "没有可查询的DNS服务器".to_string();
+        record_model.set_verify(id, VERIFY_FAILED, Some(detail.clone()), None).await?;
+        return err!(detail);
+    }
+    let mut answers = vec![];
+    for attempt in 0..config.attempts.max(1) {
+        if attempt > 0 {
+            tokio::time::sleep(Duration::from_secs(config.interval)).await;
+        }
+        answers = config.check(&servers, &fqdn, rtype, value).await?;
+        // 新的值有自己的校验任务，旧任务不再写入结果
+        if superseded(&record_model, id, value).await? {
+            info!("Verify of {} superseded, record is no longer {}", fqdn, value);
+            return Ok(VerifyResult {
+                status: VERIFY_SUPERSEDED.to_string(),
+                propagation_ms: None,
+                answers,
+            });
+        }
+        if answers.iter().all(|answer| answer.matched) {
+            let propagation_ms = started.elapsed().as_millis() as i64;
+            let detail = serde_json::to_string(&answers)?;
+            record_model
+                .set_verify(id, VERIFY_CONSISTENT, Some(detail), Some(propagation_ms))
+                .await?;
+            info!("Record {} is consistent after {}ms", fqdn, propagation_ms);
+            return Ok(VerifyResult {
+                status: VERIFY_CONSISTENT.to_string(),
+                propagation_ms: Some(propagation_ms),
+                answers,
+            });
+        }
+    }
+    let detail = serde_json::to_string(&answers)?;
+    record_model.set_verify(id, VERIFY_INCONSISTENT, Some(detail), None).await?;
+    error!("Record {} is not consistent: {:?}", fqdn, answers);
+    let data = json!({ "value": value, "answers": answers });
+    if let Err(e) = notify::emit_record(db, EVENT_DRIFT_DETECTED, &record, data).await {
+        error!("Notify drift of {} failed: {}", fqdn, e);
+    }
+    Ok(VerifyResult {
+        status: VERIFY_INCONSISTENT.to_string(),
+        propagation_ms: None,
+        answers,
+    })
+}
+
+/// 在后台校验刚发布的记录，未启用时跳过
+pub fn spawn_verify(db: &SqlitePool, id: i64, host: &str, zone: &str, rtype: &str, value: &str) {
+    let db = db.clone();
+    let (host, zone, rtype, value) = (host.to_string(), zone.to_string(), rtype.to_string(), value.to_string());
+    tokio::spawn(async move {
+        let config = match get_verify_config(&db).await {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Load verify config failed: {}", e);
+                return;
+            }
+        };
+        if !config.enabled {
+            return;
+        }
+        if let Err(e) = verify_record(&db, &config, id, &host, &zone, &rtype, &value).await {
+            error!("Verify record {}.{} failed: {}", host, zone, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::dns::tests::dns_server;
+
+    #[tokio::test]
+    async fn it_verify_record_works() -> ItdResult<()> {
+        let db = crate::tests::memory_db().await;
+        sqlx::query(r#"INSERT INTO user_domain (appid,host,domain,ip,ip_type,record_id) VALUES (1,'itd','example.com','2001:db8::5','AAAA',1)"#)
+            .execute(&db)
+            .await?;
+        let server = dns_server(vec![(
+            "itd.example.com".to_string(),
+            RecordData::Aaaa("2001:db8::5".parse()?),
+        )])
+        .await?;
+        let config = VerifyConfig {
+            nameservers: vec![server.to_string()],
+            interval: 0,
+            attempts: 2,
+            timeout: 1,
+            ..Default::default()
+        };
+        let result = verify_record(&db, &config, 1, "itd", "example.com", "AAAA", "2001:0db8::0005").await?;
+        assert_eq!(result.status, VERIFY_CONSISTENT);
+        let record = Records::new(&db).get_record(1).await?.unwrap();
+        assert_eq!(record.verify_status, Some(VERIFY_CONSISTENT.to_string()));
+        assert!(record.propagation_ms.is_some());
+
+        sqlx::query(r#"UPDATE user_domain SET ip='2001:db8::6' WHERE id=1"#).execute(&db).await?;
+        let result = verify_record(&db, &config, 1, "itd", "example.com", "AAAA", "2001:db8::6").await?;
+        assert_eq!(result.status, VERIFY_INCONSISTENT);
+        assert_eq!(result.answers[0].answers, vec!["2001:db8::5".to_string()]);
+        let record = Records::new(&db).get_record(1).await?.unwrap();
+        assert_eq!(record.verify_status, Some(VERIFY_INCONSISTENT.to_string()));
+
+        // 记录已经发布了新值，旧的校验不写入结果
+        Records::new(&db).set_verify(1, VERIFY_CONSISTENT, None, None).await?;
+        let result = verify_record(&db, &config, 1, "itd", "example.com", "AAAA", "2001:db8::7").await?;
+        assert_eq!(result.status, VERIFY_SUPERSEDED);
+        let record = Records::new(&db).get_record(1).await?.unwrap();
+        assert_eq!(record.verify_status, Some(VERIFY_CONSISTENT.to_string()));
+
+        // 运营商线路的记录不校验
+        sqlx::query(r#"UPDATE user_domain SET line='电信' WHERE id=1"#).execute(&db).await?;
+        let result = verify_record(&db, &config, 1, "itd", "example.com", "AAAA", "2001:db8::6").await?;
+        assert_eq!(result.status, VERIFY_SKIPPED);
+        let record = Records::new(&db).get_record(1).await?.unwrap();
+        assert_eq!(record.verify_status, Some(VERIFY_SKIPPED.to_string()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_authoritative_works() -> ItdResult<()> {
+        let resolver = dns_server(vec![(
+            "example.com".to_string(),
+            RecordData::Ns("localhost".to_string()),
+        )])
+        .await?;
+        let config = VerifyConfig {
+            resolvers: vec![resolver.to_string()],
+            timeout: 1,
+            ..Default::default()
+        };
+        let servers = config.authoritative("example.com").await?;
+        assert!(servers.iter().all(|server| server.port() == 53 && server.ip().is_loopback()));
+        assert!(!servers.is_empty());
+        Ok(())
+    }
+}
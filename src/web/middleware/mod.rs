@@ -1,4 +1,4 @@
-pub mod auth;
-pub mod header;
-pub mod log_bearer;
-pub mod validate;
+pub mod auth;
+pub mod header;
+pub mod log_bearer;
+pub mod validate;
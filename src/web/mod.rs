@@ -1,3 +1,3 @@
-pub mod main;
-pub mod middleware;
-pub mod routes;
+pub mod main;
+pub mod middleware;
+pub mod routes;
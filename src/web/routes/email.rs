@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use crate::{
+    err,
+    error::ItdResult,
+    model::{
+        constants::RespMsg,
+        notify_email::{EmailSetting, EmailSettingForm, EmailSettings},
+    },
+    notify::{
+        email::{self, get_smtp_config, set_smtp_config, SmtpConfig},
+        EVENT_PING,
+    },
+    web::middleware::{auth::UserIdentify, validate::ValidatedData},
+    AppState,
+};
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde_json::json;
+
+pub fn create_route() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/v1/notify/smtp", get(get_smtp).put(set_smtp))
+        .route("/v1/notify/email", get(get_email).put(set_email))
+        .route("/v1/notify/email/test", post(test_email))
+}
+/// 获取SMTP配置，不返回密码
+async fn get_smtp(State(state): State<Arc<AppState>>) -> ItdResult<Json<SmtpConfig>> {
+    let config = get_smtp_config(&state.db).await?;
+    Ok(Json(SmtpConfig { password: None, ..config }))
+}
+/// 设置SMTP配置，未填写密码时保持不变
+async fn set_smtp(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SmtpConfig>,
+) -> ItdResult<Json<SmtpConfig>> {
+    set_smtp_config(&state.db, &payload).await?;
+    Ok(Json(SmtpConfig { password: None, ..payload }))
+}
+/// 当前用户的邮件通知设置
+async fn get_email(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<UserIdentify>,
+) -> ItdResult<Json<Option<EmailSetting>>> {
+    let setting = EmailSettings::new(&state.db).get(user.id).await?;
+    Ok(Json(setting))
+}
+async fn set_email(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<UserIdentify>,
+    ValidatedData(payload): ValidatedData<EmailSettingForm>,
+) -> ItdResult<Json<Option<EmailSetting>>> {
+    let setting_model = EmailSettings::new(&state.db);
+    setting_model.set(user.id, payload).await?;
+    Ok(Json(setting_model.get(user.id).await?))
+}
+/// 立即给当前用户的收件人发送测试邮件，返回发送失败的原因
+async fn test_email(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<UserIdentify>,
+) -> ItdResult<Json<RespMsg>> {
+    let setting = match EmailSettings::new(&state.db).get(user.id).await? {
+        Some(setting) if !setting.recipients.is_empty() => setting,
+        _ => return err!("请先设置收件人"),
+    };
+    let config = get_smtp_config(&state.db).await?;
+    email::send(&config, &setting.recipients, EVENT_PING, &json!({ "uid": user.id })).await?;
+    Ok(Json(RespMsg { code: Some(1000), message: "Ok".to_string() }))
+}
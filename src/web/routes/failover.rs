@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use crate::{
+    error::ItdResult,
+    model::{
+        constants::RespMsg,
+        failover::{FailoverDetail, FailoverForm, FailoverModel},
+    },
+    web::middleware::validate::ValidatedData,
+    AppState,
+    err,
+};
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get},
+    Json, Router,
+};
+
+pub fn create_route() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/v1/record/:id/failover", get(view_failover).post(add_failover))
+        .route("/v1/failover/:id", delete(delete_failover))
+}
+/// 获取记录的故障切换组和切换记录
+async fn view_failover(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<Option<FailoverDetail>>> {
+    let failover_model = FailoverModel::new(&state.db);
+    let detail = failover_model.detail(id).await?;
+    Ok(Json(detail))
+}
+/// 为记录添加故障切换组
+async fn add_failover(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    ValidatedData(payload): ValidatedData<FailoverForm>,
+) -> ItdResult<Json<Option<FailoverDetail>>> {
+    let failover_model = FailoverModel::new(&state.db);
+    failover_model.add(id, payload).await?;
+    let detail = failover_model.detail(id).await?;
+    Ok(Json(detail))
+}
+/// 删除故障切换组，记录保持当前的值
+async fn delete_failover(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<RespMsg>> {
+    let failover_model = FailoverModel::new(&state.db);
+    if failover_model.get(id).await?.is_none() {
+        return err!("Failover group not found");
+    }
+    let _ = failover_model.delete(id).await?;
+    Ok(Json(RespMsg { code: Some(1000), message: "Ok".to_string() }))
+}
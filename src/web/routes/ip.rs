@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use crate::{
+    error::ItdResult,
+    ipaddr::{
+        confirm::{get_confirm_config, set_confirm_config, ConfirmConfig},
+        netlink::{get_netlink_config, set_netlink_config, NetlinkConfig},
+        source::{get_source_config, set_source_config, SourceConfig},
+    },
+    model::{
+        constants::{Pagination, RespMsg},
+        echo_health::{EchoHealth, EchoHealthModel},
+        ip_source::{IpSource, IpSourceForm, IpSourceModel},
+        ip_state::{HistoryQuery, IpHistory, IpStateModel},
+    },
+    queue::run::{spawn_run, SyncRun, RUN_REFRESH},
+    web::middleware::validate::ValidatedData,
+    AppState, IpState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post, put},
+    Json, Router,
+};
+
+pub fn create_route() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/v1/ip/source", get(get_source).put(set_source))
+        .route("/v1/ip/echo-health", get(echo_health))
+        .route("/v1/ip/state", get(ip_state))
+        .route("/v1/ip/sources", get(list_sources).post(add_source))
+        .route("/v1/ip/sources/:id", put(update_source).delete(delete_source))
+        .route("/v1/ip/netlink", get(get_netlink).put(set_netlink))
+        .route("/v1/ip/history", get(history))
+        .route("/v1/ip/refresh", post(refresh))
+        .route("/v1/ip/confirm", get(get_confirm).put(set_confirm))
+}
+/// 获取IP来源配置
+async fn get_source(State(state): State<Arc<AppState>>) -> ItdResult<Json<SourceConfig>> {
+    let config = get_source_config(&state.db).await?;
+    Ok(Json(config))
+}
+/// 设置IP来源配置
+async fn set_source(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SourceConfig>,
+) -> ItdResult<Json<SourceConfig>> {
+    set_source_config(&state.db, &payload).await?;
+    Ok(Json(payload))
+}
+/// 获取IP回显服务的健康统计
+async fn echo_health(State(state): State<Arc<AppState>>) -> ItdResult<Json<Vec<EchoHealth>>> {
+    let health_model = EchoHealthModel::new(&state.db);
+    let list = health_model.list().await?;
+    Ok(Json(list))
+}
+/// 获取当前检测到的IP及IPv6选择原因
+async fn ip_state(State(state): State<Arc<AppState>>) -> ItdResult<Json<IpState>> {
+    let ip_state = state.ip_state.read().await.clone();
+    Ok(Json(ip_state))
+}
+/// 获取命名IP来源列表
+async fn list_sources(State(state): State<Arc<AppState>>) -> ItdResult<Json<Vec<IpSource>>> {
+    let source_model = IpSourceModel::new(&state.db);
+    let list = source_model.list().await?;
+    Ok(Json(list))
+}
+/// 添加命名IP来源
+async fn add_source(
+    State(state): State<Arc<AppState>>,
+    ValidatedData(payload): ValidatedData<IpSourceForm>,
+) -> ItdResult<Json<Option<IpSource>>> {
+    let source_model = IpSourceModel::new(&state.db);
+    let id = source_model.add(payload).await?;
+    let source = source_model.get(id).await?;
+    Ok(Json(source))
+}
+/// 修改命名IP来源的配置
+async fn update_source(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    Json(payload): Json<SourceConfig>,
+) -> ItdResult<Json<Option<IpSource>>> {
+    let source_model = IpSourceModel::new(&state.db);
+    source_model.update(id, &payload).await?;
+    let source = source_model.get(id).await?;
+    Ok(Json(source))
+}
+/// 删除命名IP来源，被解析记录引用时不允许删除
+async fn delete_source(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<RespMsg>> {
+    let source_model = IpSourceModel::new(&state.db);
+    source_model.delete(id).await?;
+    Ok(Json(RespMsg { code: Some(1000), message: "Ok".to_string() }))
+}
+/// 获取 netlink 地址变化监听配置
+async fn get_netlink(State(state): State<Arc<AppState>>) -> ItdResult<Json<NetlinkConfig>> {
+    let config = get_netlink_config(&state.db).await?;
+    Ok(Json(config))
+}
+/// 设置 netlink 地址变化监听配置，重启后生效
+async fn set_netlink(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NetlinkConfig>,
+) -> ItdResult<Json<NetlinkConfig>> {
+    set_netlink_config(&state.db, &payload).await?;
+    Ok(Json(payload))
+}
+/// 分页获取IP变化历史，可按来源过滤
+async fn history(
+    State(state): State<Arc<AppState>>,
+    Query(payload): Query<HistoryQuery>,
+) -> ItdResult<Json<Pagination<IpHistory>>> {
+    let state_model = IpStateModel::new(&state.db);
+    let HistoryQuery { page, source } = payload;
+    state_model.history(source, page.unwrap_or(1)).await
+}
+/// 获取地址变更确认和频率限制配置
+async fn get_confirm(State(state): State<Arc<AppState>>) -> ItdResult<Json<ConfirmConfig>> {
+    let config = get_confirm_config(&state.db).await?;
+    Ok(Json(config))
+}
+/// 设置地址变更确认和频率限制配置
+async fn set_confirm(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ConfirmConfig>,
+) -> ItdResult<Json<ConfirmConfig>> {
+    set_confirm_config(&state.db, &payload).await?;
+    Ok(Json(payload))
+}
+/// 忽略检测频率立即检测IP，变化的记录立即同步，返回运行记录
+async fn refresh(State(state): State<Arc<AppState>>) -> ItdResult<Json<Option<SyncRun>>> {
+    let id = spawn_run(state.clone(), RUN_REFRESH, None).await;
+    Ok(Json(state.runs.get(id).await))
+}
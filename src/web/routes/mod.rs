@@ -1,3 +1,8 @@
-pub mod user;
-pub mod app;
-pub mod record;
\ No newline at end of file
+pub mod user;
+pub mod app;
+pub mod record;
+pub mod ip;
+pub mod failover;
+pub mod sync;
+pub mod webhook;
+pub mod email;
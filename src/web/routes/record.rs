@@ -2,9 +2,14 @@ use std::sync::Arc;
 
 use crate::{
     error::ItdResult, model::{
+        app::StatusForm,
         constants::{Pagination, RespMsg},
         records::{QueryForm, Record, RecordForm, Records},
-    }, utils::extract_ip, web::middleware::validate::ValidatedData, AppState
+        targets::{Target, TargetForm, Targets},
+    }, utils::extract_ip, web::middleware::validate::ValidatedData, AppState,
+    verify::{self, get_verify_config, set_verify_config, VerifyConfig, VerifyResult},
+    queue::run::{spawn_run, SyncRun, RUN_SYNC},
+    err,
 };
 use axum::{extract::{Path, Query, State}, routing::{ delete, get, post, put }, Json, Router};
 
@@ -15,6 +20,13 @@ pub fn create_route() -> Router<Arc<AppState>> {
         .route("/v1/record/:id", get(view_record))
         .route("/v1/record/:id", put(update_record)) 
         .route("/v1/record/:id", delete(delete_record))
+        .route("/v1/record/:id/targets", get(list_targets))
+        .route("/v1/record/:id/targets", post(add_target))
+        .route("/v1/record/target/:id", delete(delete_target))
+        .route("/v1/record/:id/verify", post(verify_record))
+        .route("/v1/record/verify-config", get(get_verify).put(set_verify))
+        .route("/v1/record/status/:id", post(set_status))
+        .route("/v1/record/:id/sync", post(sync_record))
 }
 async fn list_all(
     State(state): State<Arc<AppState>>,
@@ -35,11 +47,11 @@ async fn create_record(
          
         let app_state = state.clone();
         //let ip_state = app_state.ip_state.lock().unwrap();
-        let ip_value =  extract_ip(&payload.ip_type,app_state).await?;
+        let ip_value =  extract_ip(&payload.ip_type,payload.source.as_deref(),app_state).await?;
       data.ip = Some(ip_value);
     }
     
-    let last_insert_id = record_model.create_record(data).await?;
+    let last_insert_id = record_model.create_record(data, &state.limiter).await?;
 
     let record = record_model.get_record(last_insert_id).await?;
     //Ok(Redirect::temporary("/v1/record/".to_string() + &last_insert_id.to_string()))
@@ -65,10 +77,10 @@ async fn update_record(
          
       let app_state = state.clone();
       //let ip_state = app_state.ip_state.lock().unwrap();
-      let ip_value =  extract_ip(&payload.ip_type,app_state).await?;
+      let ip_value =  extract_ip(&payload.ip_type,payload.source.as_deref(),app_state).await?;
       data.ip = Some(ip_value);
     }
-    let _ = record_model.update_record(id, data).await?;
+    let _ = record_model.update_record(id, data, &state.limiter).await?;
     let record = record_model.get_record(id).await?;
     //let record = record.unwrap();
     Ok(Json(record))
@@ -78,10 +90,100 @@ async fn delete_record(
     Path(id): Path<i64>,
 ) -> ItdResult<Json<RespMsg>> {
     let record_model = Records::new(&state.db);
-    let _ = record_model.delete_record(id).await?;
+    let _ = record_model.delete_record(id, &state.limiter).await?;
+    Ok(Json(RespMsg { code: Some(1000), message: "Ok".to_string() }))
+}
+/// 启用或停用记录，停用的记录不自动同步
+async fn set_status(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    ValidatedData(payload): ValidatedData<StatusForm>,
+) -> ItdResult<Json<RespMsg>> {
+    let record_model = Records::new(&state.db);
+    if record_model.set_status(id, payload.status).await? == 0 {
+        return err!("Record not found");
+    }
+    Ok(Json(RespMsg { code: Some(1000), message: "Ok".to_string() }))
+}
+/// 立即把记录重新推送到服务商，返回运行记录
+async fn sync_record(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<Option<SyncRun>>> {
+    let record_model = Records::new(&state.db);
+    if record_model.get_record(id).await?.is_none() {
+        return err!("Record not found");
+    }
+    let run_id = spawn_run(state.clone(), RUN_SYNC, Some(id)).await;
+    Ok(Json(state.runs.get(run_id).await))
+}
+/// 获取记录的镜像目标
+async fn list_targets(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<Vec<Target>>> {
+    let target_model = Targets::new(&state.db);
+    let targets = target_model.list(id).await?;
+    Ok(Json(targets))
+}
+/// 为记录添加镜像目标
+async fn add_target(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    ValidatedData(payload): ValidatedData<TargetForm>,
+) -> ItdResult<Json<Option<Target>>> {
+    let target_model = Targets::new(&state.db);
+    let target_id = target_model.add_target(id, payload.appid, &state.limiter).await?;
+    let target = target_model.get(target_id).await?;
+    Ok(Json(target))
+}
+/// 删除镜像目标
+async fn delete_target(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<RespMsg>> {
+    let target_model = Targets::new(&state.db);
+    let _ = target_model.delete_target(id, &state.limiter).await?;
     Ok(Json(RespMsg { code: Some(1000), message: "Ok".to_string() }))
 }
 
+/// 立即校验记录是否已在DNS服务器上生效，只检查一次
+async fn verify_record(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<VerifyResult>> {
+    let record_model = Records::new(&state.db);
+    let record = match record_model.get_record(id).await? {
+        Some(record) => record,
+        None => return err!("Record not found"),
+    };
+    let mut config = get_verify_config(&state.db).await?;
+    config.attempts = 1;
+    let result = verify::verify_record(
+        &state.db,
+        &config,
+        id,
+        &record.host,
+        &record.domain,
+        &record.ip_type,
+        &record.ip,
+    )
+    .await?;
+    Ok(Json(result))
+}
+/// 获取发布校验配置
+async fn get_verify(State(state): State<Arc<AppState>>) -> ItdResult<Json<VerifyConfig>> {
+    let config = get_verify_config(&state.db).await?;
+    Ok(Json(config))
+}
+/// 设置发布校验配置
+async fn set_verify(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<VerifyConfig>,
+) -> ItdResult<Json<VerifyConfig>> {
+    set_verify_config(&state.db, &payload).await?;
+    Ok(Json(payload))
+}
 #[cfg(test)]
 mod tests {
 
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use crate::{
+    err,
+    error::ItdResult,
+    model::{
+        constants::{Pagination, RespMsg},
+        sync_job::{JobQuery, SyncJob, SyncJobs},
+    },
+    queue::{
+        get_queue_config,
+        run::{spawn_run, SyncRun, RUN_SYNC},
+        set_queue_config, QueueConfig,
+    },
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+
+pub fn create_route() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/v1/sync/jobs", get(list_jobs))
+        .route("/v1/sync/jobs/:id", get(view_job).delete(cancel_job))
+        .route("/v1/sync/jobs/:id/retry", post(retry_job))
+        .route("/v1/sync/config", get(get_config).put(set_config))
+        .route("/v1/sync", post(sync_all))
+        .route("/v1/sync/runs/:id", get(view_run))
+}
+/// 立即把所有记录重新推送到服务商，返回运行记录
+async fn sync_all(State(state): State<Arc<AppState>>) -> ItdResult<Json<Option<SyncRun>>> {
+    let id = spawn_run(state.clone(), RUN_SYNC, None).await;
+    Ok(Json(state.runs.get(id).await))
+}
+/// 查询手动运行的进度和结果
+async fn view_run(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+) -> ItdResult<Json<SyncRun>> {
+    match state.runs.get(id).await {
+        Some(run) => Ok(Json(run)),
+        None => err!("Sync run not found"),
+    }
+}
+/// 同步队列中的任务，可按状态过滤
+async fn list_jobs(
+    State(state): State<Arc<AppState>>,
+    Query(payload): Query<JobQuery>,
+) -> ItdResult<Json<Pagination<SyncJob>>> {
+    let job_model = SyncJobs::new(&state.db);
+    job_model.list(payload.status, payload.page.unwrap_or(1)).await
+}
+async fn view_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<SyncJob>> {
+    let job_model = SyncJobs::new(&state.db);
+    match job_model.get(id).await? {
+        Some(job) => Ok(Json(job)),
+        None => err!("Sync job not found"),
+    }
+}
+/// 立即重试失败或已取消的任务
+async fn retry_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<RespMsg>> {
+    let job_model = SyncJobs::new(&state.db);
+    if job_model.retry(id).await? == 0 {
+        return err!("只能重试失败或已取消的任务");
+    }
+    Ok(Json(RespMsg { code: Some(1000), message: "Ok".to_string() }))
+}
+/// 取消待处理的任务
+async fn cancel_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<RespMsg>> {
+    let job_model = SyncJobs::new(&state.db);
+    if job_model.cancel(id).await? == 0 {
+        return err!("只能取消待处理的任务");
+    }
+    Ok(Json(RespMsg { code: Some(1000), message: "Ok".to_string() }))
+}
+/// 获取同步队列配置
+async fn get_config(State(state): State<Arc<AppState>>) -> ItdResult<Json<QueueConfig>> {
+    let config = get_queue_config(&state.db).await?;
+    Ok(Json(config))
+}
+/// 设置同步队列配置
+async fn set_config(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<QueueConfig>,
+) -> ItdResult<Json<QueueConfig>> {
+    set_queue_config(&state.db, &payload).await?;
+    state.limiter.configure(payload.rate, payload.burst).await;
+    Ok(Json(payload))
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use crate::{
+    error::ItdResult,
+    model::{
+        constants::{Pagination, RespMsg},
+        webhook::{DeliveryQuery, Webhook, WebhookCreated, WebhookDelivery, WebhookForm, Webhooks},
+    },
+    notify,
+    web::middleware::{auth::UserIdentify, validate::ValidatedData},
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+
+pub fn create_route() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/v1/webhooks", get(list_webhooks).post(add_webhook))
+        .route("/v1/webhooks/events", get(list_events))
+        .route("/v1/webhooks/:id", put(update_webhook).delete(delete_webhook))
+        .route("/v1/webhooks/:id/deliveries", get(list_deliveries))
+        .route("/v1/webhooks/:id/ping", post(ping_webhook))
+}
+/// 当前用户的通知目标
+async fn list_webhooks(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<UserIdentify>,
+) -> ItdResult<Json<Vec<Webhook>>> {
+    let webhooks = Webhooks::new(&state.db).list(user.id).await?;
+    Ok(Json(webhooks))
+}
+/// 可订阅的事件
+async fn list_events() -> Json<Vec<&'static str>> {
+    Json(notify::EVENTS.to_vec())
+}
+/// 添加通知目标，返回的 secret 用于校验 X-Itd-Signature
+async fn add_webhook(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<UserIdentify>,
+    ValidatedData(payload): ValidatedData<WebhookForm>,
+) -> ItdResult<Json<WebhookCreated>> {
+    let webhook_model = Webhooks::new(&state.db);
+    let (id, secret) = webhook_model.add(user.id, payload).await?;
+    let webhook = webhook_model.get_owned(user.id, id).await?;
+    Ok(Json(WebhookCreated { webhook, secret }))
+}
+async fn update_webhook(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<UserIdentify>,
+    Path(id): Path<i64>,
+    ValidatedData(payload): ValidatedData<WebhookForm>,
+) -> ItdResult<Json<Webhook>> {
+    let webhook_model = Webhooks::new(&state.db);
+    webhook_model.get_owned(user.id, id).await?;
+    webhook_model.update(id, payload).await?;
+    Ok(Json(webhook_model.get_owned(user.id, id).await?))
+}
+/// 删除通知目标及其投递记录
+async fn delete_webhook(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<UserIdentify>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<RespMsg>> {
+    let webhook_model = Webhooks::new(&state.db);
+    webhook_model.get_owned(user.id, id).await?;
+    webhook_model.delete(id).await?;
+    Ok(Json(RespMsg { code: Some(1000), message: "Ok".to_string() }))
+}
+/// 投递记录，包含响应状态码和失败原因
+async fn list_deliveries(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<UserIdentify>,
+    Path(id): Path<i64>,
+    Query(payload): Query<DeliveryQuery>,
+) -> ItdResult<Json<Pagination<WebhookDelivery>>> {
+    let webhook_model = Webhooks::new(&state.db);
+    webhook_model.get_owned(user.id, id).await?;
+    webhook_model.deliveries(id, payload.page.unwrap_or(1)).await
+}
+/// 发送测试事件
+async fn ping_webhook(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<UserIdentify>,
+    Path(id): Path<i64>,
+) -> ItdResult<Json<RespMsg>> {
+    let webhook = Webhooks::new(&state.db).get_owned(user.id, id).await?;
+    let delivery_id = notify::ping(&state.db, &webhook).await?;
+    Ok(Json(RespMsg { code: Some(1000), message: delivery_id.to_string() }))
+}